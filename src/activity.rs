@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use google_drive3::hyper::{header, Body, Request};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    drive_api::send_json, format_size, output, restore_files, retry::RetryPolicy, Drive, Store,
+};
+
+const ACTIVITY_SCOPE: &str = "https://www.googleapis.com/auth/drive.activity.readonly";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityPage {
+    #[serde(default)]
+    activities: Vec<Activity>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Activity {
+    primary_action_detail: Value,
+    #[serde(default)]
+    actors: Vec<Value>,
+    #[serde(default)]
+    targets: Vec<Target>,
+    timestamp: Option<DateTime<Utc>>,
+    time_range: Option<TimeRange>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeRange {
+    end_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Target {
+    drive_item: Option<DriveItem>,
+}
+
+#[derive(Deserialize)]
+struct DriveItem {
+    /// `items/` followed by the id
+    name: String,
+    #[serde(default)]
+    title: String,
+}
+
+impl Activity {
+    fn time(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+            .or(self.time_range.as_ref().map(|r| r.end_time))
+    }
+    fn action(&self) -> &'static str {
+        let detail = &self.primary_action_detail;
+        if let Some(create) = detail.get("create") {
+            if create.get("upload").is_some() {
+                "Upload"
+            } else if create.get("copy").is_some() {
+                "Copy"
+            } else {
+                "Create"
+            }
+        } else if detail.get("move").is_some() {
+            "Move"
+        } else {
+            "Other"
+        }
+    }
+    /// People are only known by the id in the People API, except for me
+    fn actor(&self) -> String {
+        let Some(actor) = self.actors.first() else {
+            return "(nobody)".into();
+        };
+        let user = &actor["user"];
+        if user["knownUser"]["isCurrentUser"].as_bool() == Some(true) {
+            "me".into()
+        } else if let Some(name) = user["knownUser"]["personName"].as_str() {
+            name.into()
+        } else if user.get("deletedUser").is_some() {
+            "(deleted user)".into()
+        } else if actor.get("administrator").is_some() {
+            "(administrator)".into()
+        } else if actor.get("system").is_some() {
+            "(system)".into()
+        } else if actor.get("anonymous").is_some() {
+            "(anonymous)".into()
+        } else {
+            "(unknown)".into()
+        }
+    }
+}
+
+/// Queries a page of the activities with a request built by hand, since the Drive Activity API
+/// is not covered by the generated client.
+async fn query(drive: &Drive, request: &Value) -> google_drive3::Result<ActivityPage> {
+    let request = Request::post("https://driveactivity.googleapis.com/v2/activity:query")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(request.to_string()))
+        .expect("the request is valid");
+    send_json(drive, ACTIVITY_SCOPE, request).await
+}
+
+/// Lists who created, uploaded, copied or moved items into the folder or its descendants in the
+/// last `days` days, and how much each of them added.  Sizes are taken from the snapshot, so
+/// items crawled later than the activity are shown without one.
+pub async fn show_activity(
+    drive: &Drive,
+    store: &Store,
+    retry: &RetryPolicy,
+    folder_id: &str,
+    days: i64,
+    limit: usize,
+) -> anyhow::Result<()> {
+    let sizes: HashMap<_, _> = match restore_files(store) {
+        Ok(files) => files
+            .into_iter()
+            .map(|f| (f.id, f.quota_bytes_used.unwrap_or(0)))
+            .collect(),
+        Err(e) => {
+            warn!("Sizes are not shown since the snapshot could not be loaded: {e}");
+            HashMap::new()
+        }
+    };
+    let since = (Utc::now() - Duration::days(days)).to_rfc3339();
+    let mut request = json!({
+        "ancestorName": format!("items/{folder_id}"),
+        "filter": format!("time >= \"{since}\" AND detail.action_detail_case:(CREATE MOVE)"),
+        "pageSize": 100,
+    });
+    let mut activities = vec![];
+    while activities.len() < limit {
+        let page = retry.run(|| query(drive, &request)).await?;
+        activities.extend(page.activities);
+        let Some(token) = page.next_page_token else {
+            break;
+        };
+        request["pageToken"] = token.into();
+    }
+    activities.truncate(limit);
+    info!("Fetched {} activities", activities.len());
+
+    let mut by_actor = HashMap::<_, (u64, u64)>::new();
+    let mut rows = vec![];
+    for activity in &activities {
+        let actor = activity.actor();
+        for item in activity
+            .targets
+            .iter()
+            .filter_map(|t| t.drive_item.as_ref())
+        {
+            let id = item.name.strip_prefix("items/").unwrap_or(&item.name);
+            let size = sizes.get(id).copied();
+            outln!(
+                "{}  {:<6}  {:>10}  {:<24}  {}",
+                activity
+                    .time()
+                    .map_or("?".into(), |t| t.format("%Y-%m-%d %H:%M").to_string()),
+                activity.action(),
+                size.map_or("?".into(), format_size),
+                actor,
+                item.title,
+            );
+            rows.push(json!({
+                "time": activity.time(),
+                "action": activity.action(),
+                "actor": actor,
+                "id": id,
+                "title": item.title,
+                "size": size,
+            }));
+            let entry = by_actor.entry(actor.clone()).or_default();
+            entry.0 += 1;
+            entry.1 += size.unwrap_or(0);
+        }
+    }
+    let mut by_actor: Vec<_> = by_actor.into_iter().collect();
+    by_actor.sort_by_key(|&(_, (_, bytes))| std::cmp::Reverse(bytes));
+    outln!();
+    outln!("=== By actor ===");
+    for (actor, (items, bytes)) in &by_actor {
+        outln!("{:>10}  {items:>6} items  {actor}", format_size(*bytes));
+    }
+    output::emit(&json!({
+        "activities": rows,
+        "by_actor": by_actor
+            .iter()
+            .map(|(actor, (items, bytes))| json!({
+                "actor": actor,
+                "items": items,
+                "bytes": bytes,
+            }))
+            .collect::<Vec<_>>(),
+    }))
+}
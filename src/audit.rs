@@ -0,0 +1,243 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use google_drive3::api;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{retry::RetryPolicy, CancellationToken, Drive, Store};
+
+fn log_path(store: &Store) -> PathBuf {
+    store.dir.join("audit-log.jsonl")
+}
+
+/// Line of `audit-log.jsonl`, which is only ever appended to
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    time: DateTime<Utc>,
+    /// Like `trash`, `move` or `delete_revision`
+    action: String,
+    file_id: String,
+    /// State before the mutation, `null` if the thing did not exist
+    old: Value,
+    /// State after the mutation, `null` if the thing no longer exists
+    new: Value,
+    /// Time of the entry this one reverted, if made by `undo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    undoes: Option<DateTime<Utc>>,
+}
+
+/// Records every mutation made to the drive so that it can be reviewed or undone by a script.
+/// In dry-run mode, mutating commands only show what they would do and nothing is recorded.
+pub struct AuditLog {
+    /// `None` in dry-run mode
+    writer: Option<BufWriter<fs_err::File>>,
+}
+impl AuditLog {
+    pub fn open(store: &Store, dry_run: bool) -> anyhow::Result<Self> {
+        let writer = if dry_run {
+            None
+        } else {
+            Some(BufWriter::new(
+                fs_err::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log_path(store))?,
+            ))
+        };
+        Ok(Self { writer })
+    }
+    pub fn dry_run(&self) -> bool {
+        self.writer.is_none()
+    }
+    /// Appends the mutation, flushing right away so that it survives a crash.
+    pub fn record(
+        &mut self,
+        action: &str,
+        file_id: &str,
+        old: impl Serialize,
+        new: impl Serialize,
+    ) -> anyhow::Result<()> {
+        self.write(&Entry {
+            time: Utc::now(),
+            action: action.to_owned(),
+            file_id: file_id.to_owned(),
+            old: serde_json::to_value(old)?,
+            new: serde_json::to_value(new)?,
+            undoes: None,
+        })
+    }
+    fn write(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        let Some(ref mut writer) = self.writer else {
+            return Ok(());
+        };
+        serde_json::to_writer(&mut *writer, entry)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn load_entries(store: &Store) -> anyhow::Result<Vec<Entry>> {
+    let file = match fs_err::File::open(log_path(store)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => Err(e)?,
+    };
+    BufReader::new(file)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// `parents` of the state recorded for a move
+#[derive(Deserialize)]
+struct Parents {
+    parents: Vec<String>,
+}
+
+/// Reverts the last `count` mutations in the audit log, newest first, skipping those already
+/// undone.  Deletions cannot be reverted and are only reported.  The reverting mutations are
+/// recorded as well, pointing at the entries they revert.
+pub async fn undo(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    count: usize,
+) -> anyhow::Result<()> {
+    let entries = load_entries(store)?;
+    let undone: HashSet<_> = entries.iter().filter_map(|e| e.undoes).collect();
+    let targets = entries
+        .iter()
+        .rev()
+        .filter(|e| e.undoes.is_none() && !undone.contains(&e.time))
+        .take(count);
+
+    let mut reverted = 0;
+    for entry in targets {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let id = &entry.file_id;
+        // The inverse action, along with the states before and after it
+        let (action, description, old, new) = match entry.action.as_str() {
+            "trash" => (
+                "untrash",
+                "Restore from the trash",
+                entry.new.clone(),
+                entry.old.clone(),
+            ),
+            "move" => ("move", "Move back", entry.new.clone(), entry.old.clone()),
+            "rename" => (
+                "rename",
+                "Rename back",
+                entry.new.clone(),
+                entry.old.clone(),
+            ),
+            // Trashed rather than deleted so that the undo can be undone by hand
+            "create_shortcut" => (
+                "trash",
+                "Trash the shortcut",
+                serde_json::json!({ "trashed": false }),
+                serde_json::json!({ "trashed": true }),
+            ),
+            "upload" | "create_folder" => (
+                "trash",
+                "Trash the uploaded file",
+                serde_json::json!({ "trashed": false }),
+                serde_json::json!({ "trashed": true }),
+            ),
+            action => {
+                warn!("Cannot undo {action} of {id}");
+                continue;
+            }
+        };
+        if audit.dry_run() {
+            outln!("Would {}: {id}", description.to_lowercase());
+            reverted += 1;
+            continue;
+        }
+        let res = match action {
+            "move" => {
+                let Parents { parents: from } = serde_json::from_value(old.clone())?;
+                let Parents { parents: to } = serde_json::from_value(new.clone())?;
+                retry
+                    .run(|| {
+                        drive
+                            .files()
+                            .update(api::File::default(), id)
+                            .add_parents(&to.join(","))
+                            .remove_parents(&from.join(","))
+                            .supports_all_drives(true)
+                            .param("fields", "id")
+                            .doit_without_upload()
+                    })
+                    .await
+            }
+            "rename" => {
+                let file = api::File {
+                    name: new["name"].as_str().map(Into::into),
+                    ..Default::default()
+                };
+                retry
+                    .run(|| {
+                        drive
+                            .files()
+                            .update(file.clone(), id)
+                            .supports_all_drives(true)
+                            .param("fields", "id")
+                            .doit_without_upload()
+                    })
+                    .await
+            }
+            _ => {
+                let file = api::File {
+                    trashed: Some(action == "trash"),
+                    ..Default::default()
+                };
+                retry
+                    .run(|| {
+                        drive
+                            .files()
+                            .update(file.clone(), id)
+                            .supports_all_drives(true)
+                            .param("fields", "id")
+                            .doit_without_upload()
+                    })
+                    .await
+            }
+        };
+        if let Err(e) = res {
+            error!("Failed to undo {} of {id}: {e}", entry.action);
+            continue;
+        }
+        audit.write(&Entry {
+            time: Utc::now(),
+            action: action.to_owned(),
+            file_id: id.clone(),
+            old,
+            new,
+            undoes: Some(entry.time),
+        })?;
+        outln!("{description}: {id}");
+        reverted += 1;
+    }
+    let verb = if audit.dry_run() {
+        "Would undo"
+    } else {
+        "Undid"
+    };
+    outln!("{verb} {reverted} mutations");
+    if !audit.dry_run() && reverted > 0 {
+        outln!("Run sync to reflect the changes in the snapshot.");
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use chrono::{Local, NaiveTime};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::parse_size;
+
+/// Limits on the transfer rate by the local time of day, like rclone's `--bwlimit`
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    /// Sorted by the time each limit takes effect, in bytes per second, where `None` means no
+    /// limit.  The last one lasts until the first one of the next day.
+    limits: Vec<(NaiveTime, Option<u64>)>,
+}
+
+impl Schedule {
+    /// Parses either a single rate like `512k`, or space-separated `HH:MM,rate` pairs like
+    /// `08:00,512k 23:00,off`, where the rates are in bytes per second and `off` lifts the limit.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let rate = |s: &str| -> anyhow::Result<Option<u64>> {
+            match s {
+                "off" => Ok(None),
+                s => match parse_size(s)? {
+                    0 => bail!("The rate must be positive, or `off`"),
+                    rate => Ok(Some(rate)),
+                },
+            }
+        };
+        let s = s.trim();
+        if !s.contains(',') {
+            let midnight = NaiveTime::from_hms_opt(0, 0, 0).expect("the time is valid");
+            return Ok(Self {
+                limits: vec![(midnight, rate(s)?)],
+            });
+        }
+        let mut limits = vec![];
+        for entry in s.split_whitespace() {
+            let (time, limit) = entry
+                .split_once(',')
+                .with_context(|| format!("Expected `HH:MM,rate`, got {entry:?}"))?;
+            let time = NaiveTime::parse_from_str(time, "%H:%M")
+                .with_context(|| format!("Invalid time {time:?}"))?;
+            limits.push((time, rate(limit)?));
+        }
+        limits.sort_by_key(|&(time, _)| time);
+        if limits.windows(2).any(|w| w[0].0 == w[1].0) {
+            bail!("Two limits start at the same time in {s:?}");
+        }
+        Ok(Self { limits })
+    }
+
+    /// Bytes per second allowed at the time, if limited
+    pub fn rate_at(&self, time: NaiveTime) -> Option<u64> {
+        self.limits
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= time)
+            .or(self.limits.last())
+            .and_then(|&(_, rate)| rate)
+    }
+}
+
+/// Token bucket on the bytes transferred, shared by all transfers of the run, that lets through
+/// up to a second's worth of bytes at once
+pub(crate) struct Bandwidth {
+    schedule: Schedule,
+    bucket: Mutex<Bucket>,
+}
+struct Bucket {
+    /// Negative while the bytes already let through are being paid for
+    tokens: f64,
+    refilled_at: Option<Instant>,
+}
+
+impl Bandwidth {
+    pub(crate) fn new(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled_at: None,
+            }),
+        }
+    }
+
+    /// Takes the bytes out of the bucket and returns how long to wait before going on.
+    fn reserve(&self, bytes: u64) -> Duration {
+        let mut bucket = self.bucket.lock().expect("the lock is never poisoned");
+        let now = Instant::now();
+        let Some(rate) = self.schedule.rate_at(Local::now().time()) else {
+            bucket.refilled_at = None;
+            return Duration::ZERO;
+        };
+        let rate = rate as f64;
+        bucket.tokens = match bucket.refilled_at {
+            None => rate,
+            Some(t) => (bucket.tokens + (now - t).as_secs_f64() * rate).min(rate),
+        };
+        bucket.refilled_at = Some(now);
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+
+    /// Waits until the bytes just transferred fit in the limit.
+    pub(crate) async fn consume(&self, bytes: u64) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Like [`Self::consume`], but blocks the thread, for the readers that the generated API
+    /// reads the uploads from synchronously.  On a worker of the multi-threaded runtime, the
+    /// other tasks are moved off the worker first so that they keep running meanwhile.
+    pub(crate) fn consume_blocking(&self, bytes: u64) {
+        let wait = self.reserve(bytes);
+        if wait.is_zero() {
+            return;
+        }
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| std::thread::sleep(wait))
+            }
+            _ => std::thread::sleep(wait),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, io::stdout};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Terminal,
+};
+
+use crate::{format_size, root_name, root_names, with_index, FileIndex, Store};
+
+/// A file or folder with the cumulative size of its subtree
+struct Node {
+    name: String,
+    size: u64,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    Size,
+    Name,
+}
+
+struct Tree {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+impl Tree {
+    fn build(index: &dyn FileIndex, root_names: &HashMap<String, String>) -> anyhow::Result<Self> {
+        fn dfs(
+            index: &dyn FileIndex,
+            nodes: &mut Vec<Node>,
+            id: &str,
+            name: String,
+            size: u64,
+            parent: Option<usize>,
+        ) -> anyhow::Result<usize> {
+            let this = nodes.len();
+            nodes.push(Node {
+                name,
+                size,
+                parent,
+                children: vec![],
+                expanded: false,
+            });
+            for child in index.children(id)? {
+                let bytes = child.quota_bytes_used.unwrap_or(0);
+                let child = dfs(index, nodes, &child.id, child.name, bytes, Some(this))?;
+                nodes[this].size += nodes[child].size;
+                nodes[this].children.push(child);
+            }
+            Ok(this)
+        }
+        let mut nodes = vec![];
+        let mut roots = vec![];
+        for id in index.roots()? {
+            let (name, size) = match index.get(&id)? {
+                Some(file) => (file.name, file.quota_bytes_used.unwrap_or(0)),
+                None => (root_name(root_names, &id), 0),
+            };
+            roots.push(dfs(index, &mut nodes, &id, name, size, None)?);
+        }
+        let mut tree = Self { nodes, roots };
+        tree.sort(SortOrder::Size);
+        Ok(tree)
+    }
+
+    fn sort(&mut self, order: SortOrder) {
+        let sort_children = |nodes: &[Node], children: &mut Vec<usize>| match order {
+            SortOrder::Size => children.sort_by(|&x, &y| nodes[y].size.cmp(&nodes[x].size)),
+            SortOrder::Name => children.sort_by(|&x, &y| nodes[x].name.cmp(&nodes[y].name)),
+        };
+        for i in 0..self.nodes.len() {
+            let mut children = std::mem::take(&mut self.nodes[i].children);
+            sort_children(&self.nodes, &mut children);
+            self.nodes[i].children = children;
+        }
+        let mut roots = std::mem::take(&mut self.roots);
+        sort_children(&self.nodes, &mut roots);
+        self.roots = roots;
+    }
+
+    /// Nodes currently visible, i.e. whose ancestors are all expanded, along with their depth
+    fn visible(&self) -> Vec<(usize, usize)> {
+        fn push(tree: &Tree, ret: &mut Vec<(usize, usize)>, node: usize, depth: usize) {
+            ret.push((node, depth));
+            if tree.nodes[node].expanded {
+                for &child in &tree.nodes[node].children {
+                    push(tree, ret, child, depth + 1);
+                }
+            }
+        }
+        let mut ret = vec![];
+        for &root in &self.roots {
+            push(self, &mut ret, root, 0);
+        }
+        ret
+    }
+
+    fn total_size(&self) -> u64 {
+        self.roots.iter().map(|&root| self.nodes[root].size).sum()
+    }
+}
+
+pub fn browse(store: &Store) -> anyhow::Result<()> {
+    let root_names = root_names(store)?;
+    let mut tree = with_index(store, |index| Tree::build(index, &root_names))?;
+
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    let res = run(&mut tree);
+    disable_raw_mode()?;
+    execute!(stdout(), LeaveAlternateScreen)?;
+    res
+}
+
+fn run(tree: &mut Tree) -> anyhow::Result<()> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let mut order = SortOrder::Size;
+    let mut state = ListState::default();
+    let mut selected = tree.roots.first().copied();
+    loop {
+        let visible = tree.visible();
+        let position = visible.iter().position(|&(node, _)| Some(node) == selected);
+        state.select(position);
+
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)])
+                .split(f.size());
+            let items: Vec<_> = visible
+                .iter()
+                .map(|&(i, depth)| {
+                    let node = &tree.nodes[i];
+                    let parent_size = match node.parent {
+                        Some(parent) => tree.nodes[parent].size,
+                        None => tree.total_size(),
+                    };
+                    let percent = match parent_size {
+                        0 => 0.0,
+                        _ => node.size as f64 / parent_size as f64 * 100.0,
+                    };
+                    let marker = match (node.children.is_empty(), node.expanded) {
+                        (true, _) => ' ',
+                        (false, true) => '-',
+                        (false, false) => '+',
+                    };
+                    ListItem::new(format!(
+                        "{:>12} {percent:5.1}%  {}{marker} {}",
+                        format_size(node.size),
+                        "  ".repeat(depth),
+                        node.name
+                    ))
+                })
+                .collect();
+            let title = format!(" Total {} ", format_size(tree.total_size()));
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(list, chunks[0], &mut state);
+            let help = match order {
+                SortOrder::Size => "↑↓ move  → expand  ← collapse  s sort by name  q quit",
+                SortOrder::Name => "↑↓ move  → expand  ← collapse  s sort by size  q quit",
+            };
+            f.render_widget(Paragraph::new(help), chunks[1]);
+        })?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let (Some(position), Some(node)) = (position, selected) else {
+            if let KeyCode::Char('q') | KeyCode::Esc = key.code {
+                return Ok(());
+            }
+            continue;
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Up | KeyCode::Char('k') => {
+                selected = Some(visible[position.saturating_sub(1)].0);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                selected = Some(visible[(position + 1).min(visible.len() - 1)].0);
+            }
+            KeyCode::PageUp => {
+                selected = Some(visible[position.saturating_sub(20)].0);
+            }
+            KeyCode::PageDown => {
+                selected = Some(visible[(position + 20).min(visible.len() - 1)].0);
+            }
+            KeyCode::Home => selected = Some(visible[0].0),
+            KeyCode::End => selected = Some(visible[visible.len() - 1].0),
+            KeyCode::Right | KeyCode::Char('l') => {
+                let node = &mut tree.nodes[node];
+                if node.expanded {
+                    selected = node.children.first().copied().or(selected);
+                } else {
+                    node.expanded = true;
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                let node = &mut tree.nodes[node];
+                if node.expanded {
+                    node.expanded = false;
+                } else if node.parent.is_some() {
+                    selected = node.parent;
+                }
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let node = &mut tree.nodes[node];
+                node.expanded = !node.expanded;
+            }
+            KeyCode::Char('s') => {
+                order = match order {
+                    SortOrder::Size => SortOrder::Name,
+                    SortOrder::Name => SortOrder::Size,
+                };
+                tree.sort(order);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{
+    bandwidth::{Bandwidth, Schedule},
+    format_size,
+};
+
+/// Token bucket limiting the rate of API calls, shared by every call of the run, along with the
+/// statistics of the calls made
+pub struct ApiBudget {
+    /// `None` for no limit
+    requests_per_sec: Option<f64>,
+    bucket: Mutex<Bucket>,
+    calls: AtomicU64,
+    retries: AtomicU64,
+    throttled: AtomicU64,
+    /// Calls that failed for good, after all retries
+    errors: AtomicU64,
+    bytes: AtomicU64,
+    /// Limit on the content of the files downloaded and uploaded
+    bandwidth: Option<Bandwidth>,
+}
+struct Bucket {
+    tokens: f64,
+    /// `None` until the first call, when the bucket is full
+    refilled_at: Option<Instant>,
+}
+
+/// Budget without a limit, for callers that do not care about the statistics either
+pub static UNLIMITED: ApiBudget = ApiBudget::new(None);
+
+impl ApiBudget {
+    pub const fn new(requests_per_sec: Option<f64>) -> Self {
+        Self {
+            requests_per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled_at: None,
+            }),
+            calls: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            bandwidth: None,
+        }
+    }
+    /// Limits the transfer rate of the content of files by the schedule.
+    pub fn with_bandwidth(mut self, schedule: Schedule) -> Self {
+        self.bandwidth = Some(Bandwidth::new(schedule));
+        self
+    }
+
+    /// Waits until a call is allowed and counts it.  Bursts of up to a second's worth of calls
+    /// are let through at once.
+    pub async fn acquire(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let Some(rate) = self.requests_per_sec else {
+            return;
+        };
+        let capacity = rate.max(1.0);
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().expect("the lock is never poisoned");
+                let now = Instant::now();
+                bucket.tokens = match bucket.refilled_at {
+                    None => capacity,
+                    Some(t) => (bucket.tokens + (now - t).as_secs_f64() * rate).min(capacity),
+                };
+                bucket.refilled_at = Some(now);
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+    pub(crate) fn record_retry(&self, throttled: bool) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        if throttled {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Counts the content of files downloaded outside of the generated API
+    pub(crate) fn record_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+    /// Waits until the content just transferred fits in the bandwidth limit, if any.
+    pub(crate) async fn throttle(&self, bytes: u64) {
+        if let Some(ref bandwidth) = self.bandwidth {
+            bandwidth.consume(bytes).await;
+        }
+    }
+    /// Like [`Self::throttle`], but blocks the thread
+    pub(crate) fn throttle_blocking(&self, bytes: u64) {
+        if let Some(ref bandwidth) = self.bandwidth {
+            bandwidth.consume_blocking(bytes);
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            calls: self.calls.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Logs the statistics, if any call has been made.
+    pub fn report(&self) {
+        let stats = self.stats();
+        if stats.calls == 0 {
+            return;
+        }
+        info!(
+            "API usage: {} calls, {} retries, {} of which were throttled, {} failed, {} downloaded",
+            stats.calls,
+            stats.retries,
+            stats.throttled,
+            stats.errors,
+            format_size(stats.bytes)
+        );
+    }
+}
+
+/// Counts of the calls made so far
+pub struct Stats {
+    pub calls: u64,
+    pub retries: u64,
+    pub throttled: u64,
+    pub errors: u64,
+    pub bytes: u64,
+}
@@ -0,0 +1,27 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Shared flag telling long-running operations to save their progress and stop.  Unlike a
+/// channel, every operation checking it sees the request, not only the first one.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+    /// Completes once cancelled, to be raced against operations that cannot check the flag
+    /// themselves.  The flag is polled, so this may lag behind by a fraction of a second.
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+}
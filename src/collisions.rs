@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::{format_size, full_path, output, restore_files, MemoryIndex, Store};
+
+/// Lists the items sharing a name in the same folder, which Drive allows but most sync tools
+/// cannot represent.  With `ignore_case`, names differing only in case collide too, as they do
+/// on case-insensitive file systems.
+pub fn show_collisions(store: &Store, ignore_case: bool) -> anyhow::Result<()> {
+    let mut files = restore_files(store)?;
+    files.retain(|f| !f.trashed);
+    let index = MemoryIndex::new(&files)?;
+
+    let mut groups = HashMap::<_, Vec<_>>::new();
+    for file in &files {
+        let name = if ignore_case {
+            file.name.to_lowercase()
+        } else {
+            file.name.clone()
+        };
+        for parent in &file.parents {
+            groups
+                .entry((parent as &str, name.clone()))
+                .or_default()
+                .push(file);
+        }
+    }
+    let mut collisions = vec![];
+    for (_, files) in groups {
+        if files.len() < 2 {
+            continue;
+        }
+        // The path through the first parent, which is the same for all of them only if none has
+        // several parents
+        let path = full_path(&index, files[0])?;
+        collisions.push((path, files));
+    }
+    collisions.sort_by(|x, y| x.0.cmp(&y.0));
+
+    for (path, files) in &mut collisions {
+        outln!("=== {path} ({} items) ===", files.len());
+        files.sort_by_key(|f| f.modified_time);
+        for file in files.iter() {
+            let modified = file
+                .modified_time
+                .map_or("?".into(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+            let same = files
+                .iter()
+                .filter(|f| f.md5_checksum.is_some() && f.md5_checksum == file.md5_checksum)
+                .count()
+                > 1;
+            outln!(
+                "{:>12}  {modified}  {:32}  {}  {}{}",
+                file.size.map_or("-".into(), format_size),
+                file.md5_checksum.as_deref().unwrap_or("-"),
+                file.id,
+                file.name,
+                if same { "  (same content)" } else { "" }
+            );
+        }
+    }
+    outln!("{} names collide", collisions.len());
+    output::emit(&json!(collisions
+        .iter()
+        .map(|(path, files)| json!({ "path": path, "files": files }))
+        .collect::<Vec<_>>()))
+}
@@ -0,0 +1,74 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::json;
+
+use crate::{
+    format_size, output,
+    parents::{self, FolderKind},
+    restore_files, Store,
+};
+
+/// Shows how much each computer backed up by Drive for Desktop uses, largest first.  The
+/// computers are told apart from the other roots by `resolve-parents`.
+pub fn show_computers(store: &Store) -> anyhow::Result<()> {
+    let folders = parents::restore(store)?;
+    if folders.is_empty() || folders.values().any(|f| f.kind.is_none()) {
+        outln!("Run resolve-parents to find the roots of the computers.");
+    }
+    let computers: HashMap<_, _> = folders
+        .iter()
+        .filter(|(_, f)| f.kind == Some(FolderKind::Computer))
+        .map(|(id, f)| (id as &str, f))
+        .collect();
+    let files = restore_files(store)?;
+    let id_to_file: HashMap<_, _> = files.iter().map(|f| (&f.id as &str, f)).collect();
+
+    // Computer => (files, bytes)
+    let mut usage = HashMap::<&str, (u64, u64)>::new();
+    for file in &files {
+        // The root that the first parents lead up to
+        let mut visited = HashSet::new();
+        let mut current = file.parents.first();
+        while let Some(parent) = current.filter(|&id| visited.insert(id)) {
+            match id_to_file.get(parent as &str) {
+                Some(folder) => current = folder.parents.first(),
+                None => break,
+            }
+        }
+        let Some((&id, _)) = current.and_then(|id| computers.get_key_value(id as &str)) else {
+            continue;
+        };
+        let entry = usage.entry(id).or_default();
+        entry.0 += 1;
+        entry.1 += file.quota_bytes_used.unwrap_or(0);
+    }
+
+    let mut rows: Vec<_> = computers
+        .iter()
+        .map(|(&id, folder)| {
+            let (files, bytes) = usage.get(id).copied().unwrap_or_default();
+            (id, folder.name.as_deref().unwrap_or("?"), files, bytes)
+        })
+        .collect();
+    rows.sort_by(|x, y| y.3.cmp(&x.3).then_with(|| x.0.cmp(y.0)));
+    for &(id, name, files, bytes) in &rows {
+        outln!(
+            "{:>12}  {files:>9} files  {name} ({id})",
+            format_size(bytes)
+        );
+    }
+    outln!(
+        "{} computers using {} in total",
+        rows.len(),
+        format_size(rows.iter().map(|row| row.3).sum())
+    );
+    output::emit(&json!(rows
+        .iter()
+        .map(|&(id, name, files, bytes)| json!({
+            "id": id,
+            "name": name,
+            "files": files,
+            "bytes": bytes,
+        }))
+        .collect::<Vec<_>>()))
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use drive_crawl::{outln, output};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::token_store;
+
+/// Command line overrides of the paths in the config file
+#[derive(clap::Args)]
+pub struct PathArgs {
+    /// Config file to use instead of `drive-crawl/config.toml` under the user config directory
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+    /// Keep the credentials and the snapshot of an account apart from those of the others
+    #[clap(long, global = true)]
+    profile: Option<String>,
+    /// Directory holding the snapshot and the other crawl state
+    #[clap(long, global = true)]
+    data_dir: Option<PathBuf>,
+    #[clap(long, global = true)]
+    client_secret: Option<PathBuf>,
+    #[clap(long, global = true)]
+    token_cache: Option<PathBuf>,
+    /// Key of the service account used with `--auth service-account`
+    #[clap(long, global = true)]
+    service_account_key: Option<PathBuf>,
+    /// File the log of the run is written to, replacing that of the previous run
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+}
+
+impl PathArgs {
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+}
+
+/// Paths given in the config file, either at the top level or for a profile
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Paths {
+    data_dir: Option<PathBuf>,
+    client_secret: Option<PathBuf>,
+    token_cache: Option<PathBuf>,
+    service_account_key: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+}
+impl Paths {
+    fn entries_mut(&mut self) -> [&mut Option<PathBuf>; 5] {
+        [
+            &mut self.data_dir,
+            &mut self.client_secret,
+            &mut self.token_cache,
+            &mut self.service_account_key,
+            &mut self.log_file,
+        ]
+    }
+}
+
+/// Contents of the config file.  Relative paths are resolved against the directory of the file.
+/// A profile takes the paths from its own `[profiles.<name>]` table instead of the top level.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    data_dir: Option<PathBuf>,
+    client_secret: Option<PathBuf>,
+    token_cache: Option<PathBuf>,
+    service_account_key: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+    /// Refuse `--read-write` for every profile, for configurations handed out to auditors
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    profiles: BTreeMap<String, Paths>,
+}
+
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub client_secret: PathBuf,
+    pub token_cache: PathBuf,
+    pub service_account_key: PathBuf,
+    pub log_file: PathBuf,
+    pub read_only: bool,
+}
+
+/// Where the files of profiles not configured otherwise are kept
+const PROFILES_DIR: &str = "ignore/profiles";
+
+pub fn load(args: &PathArgs) -> anyhow::Result<Config> {
+    Ok(load_profile(
+        args,
+        &read_config_file(args)?,
+        args.profile.as_deref(),
+    ))
+}
+
+/// Resolves the paths from the command line, then the config file, then the defaults under
+/// `ignore/` in the working directory, or under `ignore/profiles/<name>/` for a profile.
+fn load_profile(args: &PathArgs, file: &ConfigFile, profile: Option<&str>) -> Config {
+    let read_only = file.read_only;
+    let (file, default_dir) = match profile {
+        None => (
+            Paths {
+                data_dir: file.data_dir.clone(),
+                client_secret: file.client_secret.clone(),
+                token_cache: file.token_cache.clone(),
+                service_account_key: file.service_account_key.clone(),
+                log_file: file.log_file.clone(),
+            },
+            PathBuf::from("ignore"),
+        ),
+        Some(name) => (
+            file.profiles.get(name).cloned().unwrap_or_default(),
+            Path::new(PROFILES_DIR).join(name),
+        ),
+    };
+    let resolve = |arg: &Option<PathBuf>, file: Option<PathBuf>, default: &str| {
+        arg.clone()
+            .or(file)
+            .unwrap_or_else(|| default_dir.join(default))
+    };
+    Config {
+        data_dir: args
+            .data_dir
+            .clone()
+            .or(file.data_dir)
+            .unwrap_or(default_dir.clone()),
+        client_secret: resolve(&args.client_secret, file.client_secret, "clientsecret.json"),
+        token_cache: resolve(&args.token_cache, file.token_cache, "tokencache.json"),
+        service_account_key: resolve(
+            &args.service_account_key,
+            file.service_account_key,
+            "serviceaccount.json",
+        ),
+        log_file: resolve(&args.log_file, file.log_file, "log.log"),
+        read_only,
+    }
+}
+
+/// Data directory of another profile, ignoring `--data-dir` which is meant for the current one
+pub fn data_dir_of(args: &PathArgs, profile: &str) -> anyhow::Result<PathBuf> {
+    let file = read_config_file(args)?;
+    let paths = file.profiles.get(profile).cloned().unwrap_or_default();
+    Ok(paths
+        .data_dir
+        .unwrap_or_else(|| Path::new(PROFILES_DIR).join(profile)))
+}
+
+fn read_config_file(args: &PathArgs) -> anyhow::Result<ConfigFile> {
+    Ok(match args.config {
+        Some(ref path) => read(path)?,
+        None => match dirs::config_dir().map(|dir| dir.join("drive-crawl").join("config.toml")) {
+            Some(path) if path.exists() => read(&path)?,
+            _ => ConfigFile::default(),
+        },
+    })
+}
+
+fn read(path: &Path) -> anyhow::Result<ConfigFile> {
+    let text = fs_err::read_to_string(path)?;
+    let mut file: ConfigFile =
+        toml::from_str(&text).with_context(|| format!("Failed to parse {path:?}"))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let top_level = [
+        &mut file.data_dir,
+        &mut file.client_secret,
+        &mut file.token_cache,
+        &mut file.service_account_key,
+        &mut file.log_file,
+    ];
+    for entry in top_level
+        .into_iter()
+        .chain(file.profiles.values_mut().flat_map(Paths::entries_mut))
+        .flatten()
+    {
+        *entry = base.join(&*entry);
+    }
+    Ok(file)
+}
+
+/// The names of the profiles in the config file and those created under `ignore/profiles/`
+fn profile_names(file: &ConfigFile) -> anyhow::Result<Vec<String>> {
+    let mut names: Vec<_> = file.profiles.keys().cloned().collect();
+    match fs_err::read_dir(PROFILES_DIR) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => Err(e)?,
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// The data directories of the default profile, named `default`, and of every other profile
+pub fn all_data_dirs(args: &PathArgs) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let file = read_config_file(args)?;
+    let mut dirs = vec![(
+        "default".to_owned(),
+        load_profile(args, &file, None).data_dir,
+    )];
+    for name in profile_names(&file)? {
+        let data_dir = data_dir_of(args, &name)?;
+        dirs.push((name, data_dir));
+    }
+    Ok(dirs)
+}
+
+/// Lists the profiles in the config file and those created under `ignore/profiles/`.
+pub fn show_profiles(args: &PathArgs) -> anyhow::Result<()> {
+    let file = read_config_file(args)?;
+    let names = profile_names(&file)?;
+    let mut rows = vec![];
+    for name in &names {
+        let config = load_profile(args, &file, Some(name));
+        let signed_in = token_store::has_tokens(&config.token_cache);
+        let status = if signed_in {
+            "signed in"
+        } else {
+            "not signed in"
+        };
+        outln!("{name:20}  {status:14}  {:?}", config.data_dir);
+        rows.push(json!({
+            "name": name,
+            "signed_in": signed_in,
+            "data_dir": config.data_dir,
+        }));
+    }
+    if names.is_empty() {
+        outln!("No profiles yet.  Pass --profile <name> to any command to create one.");
+    }
+    output::emit(&rows)
+}
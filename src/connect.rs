@@ -0,0 +1,221 @@
+use std::{
+    future::Future,
+    io::BufReader,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{self, Poll},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use base64::Engine;
+use google_drive3::hyper::{self, client::HttpConnector, service::Service, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use log::info;
+use rustls::{ClientConfig, RootCertStore};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+pub type Connector = HttpsConnector<ProxyConnector>;
+pub type Client = hyper::Client<Connector>;
+
+/// How the HTTP clients connect
+pub struct Options {
+    /// PEM file of extra root certificates to trust
+    pub ca_bundle: Option<PathBuf>,
+    /// Speak only HTTP/1.1, for middleboxes that break HTTP/2
+    pub http1: bool,
+    pub connect_timeout: Option<Duration>,
+    /// How long idle connections are kept in the pool
+    pub idle_timeout: Duration,
+    /// Most idle connections kept per host, unlimited if `None`
+    pub pool_size: Option<usize>,
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Connects directly, or tunnels through the proxy with `CONNECT` so that TLS is still end to end
+#[derive(Clone)]
+pub struct ProxyConnector {
+    http: HttpConnector,
+    proxy: Option<Proxy>,
+}
+
+#[derive(Clone)]
+struct Proxy {
+    uri: Uri,
+    /// Value of the `Proxy-Authorization` header, from the user info of the proxy URL
+    authorization: Option<String>,
+    /// Hosts reached directly, from `NO_PROXY`
+    no_proxy: Vec<String>,
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .or_else(|_| std::env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+impl Proxy {
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(url) = env_var("HTTPS_PROXY") else {
+            return Ok(None);
+        };
+        let with_scheme = if url.contains("://") {
+            url.clone()
+        } else {
+            format!("http://{url}")
+        };
+        let uri: Uri = with_scheme
+            .parse()
+            .with_context(|| format!("Invalid HTTPS_PROXY {url:?}"))?;
+        if uri.scheme_str() != Some("http") {
+            bail!("Only HTTP proxies are supported, not {url:?}");
+        }
+        let authorization = uri
+            .authority()
+            .and_then(|a| Some(a.as_str().rsplit_once('@')?.0))
+            .map(|user| {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(user);
+                format!("Basic {encoded}")
+            });
+        let no_proxy = env_var("NO_PROXY")
+            .unwrap_or_default()
+            .split(',')
+            .map(|host| host.trim().trim_start_matches('.').to_owned())
+            .filter(|host| !host.is_empty())
+            .collect();
+        info!(
+            "Connecting through the proxy {}",
+            uri.host().unwrap_or_default()
+        );
+        Ok(Some(Self {
+            uri,
+            authorization,
+            no_proxy,
+        }))
+    }
+
+    fn bypasses(&self, host: &str) -> bool {
+        self.no_proxy
+            .iter()
+            .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{entry}")))
+    }
+}
+
+/// Asks the proxy to open a tunnel to the destination of `dst`.
+async fn tunnel(
+    stream: &mut TcpStream,
+    dst: &Uri,
+    authorization: Option<&str>,
+) -> anyhow::Result<()> {
+    let host = dst.host().context("The URL has no host")?;
+    let port = dst
+        .port_u16()
+        .unwrap_or(if dst.scheme_str() == Some("http") {
+            80
+        } else {
+            443
+        });
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some(authorization) = authorization {
+        request += &format!("Proxy-Authorization: {authorization}\r\n");
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so as not to consume what the destination sends after the headers
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            bail!("The response of the proxy is too long");
+        }
+        let byte = stream.read_u8().await?;
+        response.push(byte);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("The proxy refused to connect to {host}:{port}: {status}");
+    }
+    Ok(())
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TcpStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let mut http = self.http.clone();
+        let proxy = self
+            .proxy
+            .clone()
+            .filter(|proxy| !proxy.bypasses(dst.host().unwrap_or_default()));
+        Box::pin(async move {
+            let Some(proxy) = proxy else {
+                return Ok(http.call(dst).await?);
+            };
+            let mut stream = http.call(proxy.uri.clone()).await?;
+            tunnel(&mut stream, &dst, proxy.authorization.as_deref()).await?;
+            Ok(stream)
+        })
+    }
+}
+
+/// The native roots, plus the certificates in the PEM file if any.
+fn tls_config(ca_bundle: Option<&Path>) -> anyhow::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    let native: Vec<_> = rustls_native_certs::load_native_certs()
+        .context("Failed to load the native root certificates")?
+        .into_iter()
+        .map(|cert| cert.0)
+        .collect();
+    roots.add_parsable_certificates(&native);
+    if let Some(path) = ca_bundle {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(fs_err::File::open(path)?))
+            .with_context(|| format!("Failed to parse {path:?}"))?;
+        let (added, _) = roots.add_parsable_certificates(&certs);
+        if added == 0 {
+            bail!("No certificate found in {path:?}");
+        }
+        info!("Trusting {added} more certificates from {path:?}");
+    }
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+/// The HTTP client, which goes through the proxy in `HTTPS_PROXY` unless the host is in
+/// `NO_PROXY`, and trusts the extra root certificates in the CA bundle.
+pub fn client(options: &Options) -> anyhow::Result<Client> {
+    let mut http = HttpConnector::new();
+    // The scheme is checked by the TLS layer instead
+    http.enforce_http(false);
+    http.set_connect_timeout(options.connect_timeout);
+    let connector = ProxyConnector {
+        http,
+        proxy: Proxy::from_env()?,
+    };
+    let builder = HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config(options.ca_bundle.as_deref())?)
+        .https_or_http()
+        .enable_http1();
+    let connector = if options.http1 {
+        builder.wrap_connector(connector)
+    } else {
+        builder.enable_http2().wrap_connector(connector)
+    };
+    Ok(hyper::Client::builder()
+        .pool_idle_timeout(options.idle_timeout)
+        .pool_max_idle_per_host(options.pool_size.unwrap_or(usize::MAX))
+        .build(connector))
+}
@@ -0,0 +1,118 @@
+use chrono::{Duration, Utc};
+use log::info;
+use serde_json::json;
+
+use crate::{
+    collect_files, format_size, history, merge::Account, output, restore_data_from, restore_files,
+    restore_quota, trend::top_folder_usage, File,
+};
+
+/// Quota used by the files in My Drive, which shared drives do not count towards
+fn my_drive_usage(files: &[File]) -> u64 {
+    files
+        .iter()
+        .filter(|f| f.drive_id.is_none())
+        .filter_map(|f| f.quota_bytes_used)
+        .sum()
+}
+
+/// Growth of the usage of My Drive since the oldest snapshot archived within `days`, if any
+fn growth(account: &Account, usage: u64, days: u32) -> anyhow::Result<Option<i64>> {
+    let since = (Utc::now() - Duration::days(days as i64)).naive_utc();
+    let Some((_, path)) = history::list(&account.store)?
+        .into_iter()
+        .find(|(time, _)| *time >= since)
+    else {
+        return Ok(None);
+    };
+    let files = collect_files(restore_data_from(&path, false)?)?;
+    Ok(Some(usage as i64 - my_drive_usage(&files) as i64))
+}
+
+/// Shows the usage of every account side by side: the crawled usage of My Drive, the usage and
+/// the limit as of the last `quota`, the growth over `days` and the `top` largest folders.
+pub fn show_dashboard(accounts: &[Account], top: usize, days: u32) -> anyhow::Result<()> {
+    let mut rows = vec![];
+    let (mut total_crawled, mut total_usage, mut total_limit) = (0, 0, 0);
+    outln!(
+        "{:20}  {:>12}  {:>12}  {:>12}  {:>6}  {:>13}  top folders",
+        "account",
+        "crawled",
+        "usage",
+        "limit",
+        "used",
+        format!("{days}d growth")
+    );
+    for account in accounts {
+        if !account.store.has_snapshot() {
+            info!("Skipping {}, which has not been crawled", account.name);
+            continue;
+        }
+        let files = restore_files(&account.store)?;
+        let crawled = my_drive_usage(&files);
+        let my_drive: Vec<_> = files.into_iter().filter(|f| f.drive_id.is_none()).collect();
+        let (_, folders) = top_folder_usage(&my_drive)?;
+        let mut folders: Vec<_> = folders.into_iter().collect();
+        folders.sort_by(|(x, a), (y, b)| b.cmp(a).then_with(|| x.cmp(y)));
+        folders.truncate(top);
+        let quota = restore_quota(&account.store)?;
+        let usage = quota.as_ref().and_then(|q| q.usage);
+        let limit = quota.as_ref().and_then(|q| q.limit);
+        let growth = growth(account, crawled, days)?;
+
+        total_crawled += crawled;
+        total_usage += usage.unwrap_or(0);
+        total_limit += limit.unwrap_or(0);
+        let size = |x: Option<u64>| x.map_or("-".into(), format_size);
+        let used = match (usage, limit) {
+            (Some(usage), Some(limit)) if limit > 0 => {
+                format!("{:.1}%", usage as f64 * 100.0 / limit as f64)
+            }
+            _ => "-".into(),
+        };
+        let growth_text = match growth {
+            Some(g) if g < 0 => format!("-{}", format_size(g.unsigned_abs())),
+            Some(g) => format!("+{}", format_size(g as u64)),
+            None => "-".into(),
+        };
+        let folders_text = folders
+            .iter()
+            .map(|(folder, bytes)| format!("{folder} ({})", format_size(*bytes)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        outln!(
+            "{:20}  {:>12}  {:>12}  {:>12}  {used:>6}  {growth_text:>13}  {folders_text}",
+            account.name,
+            format_size(crawled),
+            size(usage),
+            size(limit),
+        );
+        rows.push(json!({
+            "account": account.name,
+            "crawled": crawled,
+            "usage": usage,
+            "limit": limit,
+            "quota_fetched_at": quota.as_ref().map(|q| q.fetched_at),
+            "growth": growth,
+            "top_folders": folders
+                .iter()
+                .map(|(folder, bytes)| json!({ "folder": folder, "bytes": bytes }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+    if rows.is_empty() {
+        outln!("No profile has been crawled yet.");
+        return Ok(());
+    }
+    outln!(
+        "{:20}  {:>12}  {:>12}  {:>12}",
+        "(total)",
+        format_size(total_crawled),
+        format_size(total_usage),
+        format_size(total_limit),
+    );
+    if rows.iter().any(|row| row["usage"].is_null()) {
+        outln!("Run `quota` with the profiles lacking the usage and the limit to record them.");
+    }
+    output::emit(&rows)
+}
@@ -0,0 +1,175 @@
+use std::io::{BufWriter, Write};
+
+use chrono::{DateTime, Utc};
+use google_drive3::api::{self, FileShortcutDetails};
+use log::{error, info};
+use serde::Serialize;
+
+use crate::{
+    audit::AuditLog, drive_api::create_file, duplicate_clusters, format_size, full_path,
+    restore_files, retry::RetryPolicy, CancellationToken, Drive, File, MemoryIndex, Store,
+};
+
+/// Entry of `dedupe-log.jsonl`, enough to restore the duplicate and remove the shortcut
+#[derive(Serialize)]
+struct LogEntry<'a> {
+    time: DateTime<Utc>,
+    /// Trashed duplicate
+    file_id: &'a str,
+    name: &'a str,
+    parent_id: &'a str,
+    /// The copy that was kept
+    canonical_id: &'a str,
+    shortcut_id: &'a str,
+}
+
+/// Keeps one copy in each cluster of duplicates and replaces the others with shortcuts to it.
+/// Only previews the changes in dry-run mode.
+pub async fn dedupe(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let log_path = store.dir.join("dedupe-log.jsonl");
+    let mut log = if !audit.dry_run() {
+        Some(BufWriter::new(
+            fs_err::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log_path)?,
+        ))
+    } else {
+        None
+    };
+
+    let (mut replaced, mut reclaimed) = (0, 0);
+    'clusters: for (_, _, size, files) in duplicate_clusters(&files) {
+        // Keep a copy I cannot trash if any, since it stays anyway, and otherwise the copy with
+        // the shortest path, which is likely the most deliberate one
+        let mut files: Vec<_> = files
+            .into_iter()
+            .map(|file| anyhow::Ok((full_path(&index, file)?, file)))
+            .collect::<anyhow::Result<_>>()?;
+        files.sort_by(|(x, a), (y, b)| {
+            (a.can_trash().cmp(&b.can_trash()))
+                .then_with(|| x.len().cmp(&y.len()))
+                .then_with(|| x.cmp(y))
+        });
+        let (canonical_path, canonical) = &files[0];
+        outln!("=== Keeping {canonical_path} ({}) ===", format_size(size));
+        for (path, file) in &files[1..] {
+            let Some(parent_id) = file.parents.first() else {
+                outln!("Skipping {path}, which has no parent");
+                continue;
+            };
+            if !file.can_trash() {
+                outln!("Skipping {path}, which I cannot trash");
+                continue;
+            }
+            let Some(ref mut log) = log else {
+                outln!("Would replace {path}");
+                replaced += 1;
+                reclaimed += size;
+                continue;
+            };
+            match replace_with_shortcut(drive, retry, audit, file, parent_id, &canonical.id).await {
+                Ok(shortcut_id) => {
+                    let entry = LogEntry {
+                        time: Utc::now(),
+                        file_id: &file.id,
+                        name: &file.name,
+                        parent_id,
+                        canonical_id: &canonical.id,
+                        shortcut_id: &shortcut_id,
+                    };
+                    serde_json::to_writer(&mut *log, &entry)?;
+                    writeln!(log)?;
+                    log.flush()?;
+                    outln!("Replaced {path}");
+                }
+                Err(e) => {
+                    error!("Failed to replace {path}: {e:#}");
+                    continue;
+                }
+            }
+            replaced += 1;
+            reclaimed += size;
+            if cancel.is_cancelled() {
+                info!("Received ctrl-c.  Terminating.");
+                break 'clusters;
+            }
+        }
+    }
+    match log {
+        Some(_) => {
+            outln!(
+                "Replaced {replaced} files, reclaiming {}",
+                format_size(reclaimed)
+            );
+            info!("Recorded the replacements in {log_path:?}");
+        }
+        None => {
+            outln!(
+                "Would replace {replaced} files, reclaiming {}.  Run with --apply to do so.",
+                format_size(reclaimed)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Creates a shortcut to `canonical_id` next to the file, then moves the file to the trash.
+/// The file is trashed rather than deleted so that the replacement can be undone.
+async fn replace_with_shortcut(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    file: &File,
+    parent_id: &str,
+    canonical_id: &str,
+) -> anyhow::Result<String> {
+    let shortcut = api::File {
+        name: Some(file.name.clone()),
+        mime_type: Some("application/vnd.google-apps.shortcut".into()),
+        parents: Some(vec![parent_id.to_owned()]),
+        shortcut_details: Some(FileShortcutDetails {
+            target_id: Some(canonical_id.to_owned()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let shortcut = retry.run(|| create_file(drive, &shortcut)).await?;
+    let shortcut_id = shortcut.id.unwrap_or_default();
+    let new = serde_json::json!({
+        "name": file.name,
+        "parents": [parent_id],
+        "target_id": canonical_id,
+    });
+    audit.record("create_shortcut", &shortcut_id, (), new)?;
+
+    let trashed = api::File {
+        trashed: Some(true),
+        ..Default::default()
+    };
+    retry
+        .run(|| {
+            drive
+                .files()
+                .update(trashed.clone(), &file.id)
+                .supports_all_drives(true)
+                .param("fields", "id")
+                .doit_without_upload()
+        })
+        .await?;
+    audit.record(
+        "trash",
+        &file.id,
+        serde_json::json!({ "trashed": false }),
+        serde_json::json!({ "trashed": true }),
+    )?;
+    Ok(shortcut_id)
+}
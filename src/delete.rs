@@ -0,0 +1,151 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::Context;
+use google_drive3::api;
+use log::{error, info, warn};
+use serde_json::json;
+
+use crate::{
+    audit::AuditLog, drive_api::DriveApi, format_size, full_path, restore_files,
+    retry::RetryPolicy, CancellationToken, MemoryIndex, Store,
+};
+
+/// Reads the file ids from a CSV with an `id` column, such as one written by `export`, or from
+/// a text file with one id per line, where blank lines and those starting with `#` are ignored.
+fn read_ids(path: &Path) -> anyhow::Result<Vec<String>> {
+    let mut ids = vec![];
+    if path.extension().is_some_and(|e| e == "csv") {
+        let mut reader = csv::Reader::from_path(path)?;
+        let column = reader
+            .headers()?
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case("id"))
+            .with_context(|| format!("{path:?} has no `id` column"))?;
+        for record in reader.records() {
+            if let Some(id) = record?.get(column).filter(|id| !id.is_empty()) {
+                ids.push(id.to_owned());
+            }
+        }
+    } else {
+        for line in fs_err::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                ids.push(line.to_owned());
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+    Ok(ids)
+}
+
+/// Trashes, or permanently deletes, the files listed in the file after showing what they are
+/// according to the snapshot and asking for confirmation.
+pub async fn delete_from(
+    drive: &impl DriveApi,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    list: &Path,
+    permanent: bool,
+) -> anyhow::Result<()> {
+    let mut ids = read_ids(list)?;
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let id_to_file: HashMap<_, _> = files.iter().map(|f| (&f.id as &str, f)).collect();
+
+    // Files I lack the permission for would only fail
+    ids.retain(|id| match id_to_file.get(id as &str) {
+        Some(file) if permanent && !file.can_delete() => {
+            warn!("Skipping {}, which I cannot delete", file.name);
+            false
+        }
+        Some(file) if !permanent && !file.can_trash() => {
+            warn!("Skipping {}, which I cannot trash", file.name);
+            false
+        }
+        _ => true,
+    });
+    let mut total = 0;
+    for id in &ids {
+        match id_to_file.get(id as &str) {
+            Some(file) => {
+                let bytes = file.quota_bytes_used.unwrap_or(0);
+                total += bytes;
+                outln!("{:>12}  {}", format_size(bytes), full_path(&index, file)?);
+            }
+            None => warn!("{id} is not in the snapshot; its usage is unknown"),
+        }
+    }
+    let (verb, prompt) = if permanent {
+        ("permanently delete", "Permanently delete")
+    } else {
+        ("trash", "Trash")
+    };
+    let message = format!("{} files using {}", ids.len(), format_size(total));
+    if audit.dry_run() {
+        outln!("Would {verb} {message}");
+        return Ok(());
+    }
+    if ids.is_empty() {
+        outln!("No files are listed in {list:?}");
+        return Ok(());
+    }
+    eprint!("{prompt} {message}? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        outln!("Cancelled");
+        return Ok(());
+    }
+
+    let (mut done, mut failed) = (0, 0);
+    for id in &ids {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let res = if permanent {
+            retry.run(|| drive.delete_file(id)).await
+        } else {
+            let trashed = api::File {
+                trashed: Some(true),
+                ..Default::default()
+            };
+            retry
+                .run(|| drive.update_file(id, trashed.clone(), "id"))
+                .await
+                .map(drop)
+        };
+        if let Err(e) = res {
+            error!("Failed to {verb} {id}: {e}");
+            failed += 1;
+            continue;
+        }
+        if permanent {
+            let old = id_to_file
+                .get(id as &str)
+                .map(|file| json!({ "name": file.name, "parents": file.parents }));
+            audit.record("delete", id, old, ())?;
+        } else {
+            audit.record(
+                "trash",
+                id,
+                json!({ "trashed": false }),
+                json!({ "trashed": true }),
+            )?;
+        }
+        done += 1;
+    }
+    outln!("Done with {done} files, failed {failed}");
+    if done > 0 {
+        outln!("Run sync to reflect the changes in the snapshot.");
+    }
+    Ok(())
+}
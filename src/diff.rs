@@ -0,0 +1,160 @@
+use std::{collections::HashMap, path::Path};
+
+use serde_json::json;
+
+use crate::{collect_files, format_size, full_path, output, restore_data_from, File, MemoryIndex};
+
+/// One of the two snapshots being compared
+struct Side<'a> {
+    index: MemoryIndex<'a>,
+    by_id: HashMap<&'a str, &'a File>,
+}
+impl<'a> Side<'a> {
+    fn new(files: &'a [File]) -> anyhow::Result<Self> {
+        Ok(Self {
+            index: MemoryIndex::new(files)?,
+            by_id: files.iter().map(|file| (&file.id as &str, file)).collect(),
+        })
+    }
+
+    fn path(&self, file: &File) -> anyhow::Result<String> {
+        full_path(&self.index, file)
+    }
+
+    /// The folder directly under a root that contains the file, or the file itself if it is
+    /// directly under a root
+    fn top_level(&self, mut file: &'a File) -> &'a File {
+        for _ in 0..self.by_id.len() {
+            match file
+                .parents
+                .first()
+                .and_then(|id| self.by_id.get(id as &str).copied())
+            {
+                Some(parent) if !parent.parents.is_empty() => file = parent,
+                _ => break,
+            }
+        }
+        file
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<Vec<File>> {
+    collect_files(restore_data_from(path, false)?)
+}
+
+fn quota(file: &File) -> u64 {
+    file.quota_bytes_used.unwrap_or(0)
+}
+
+/// Adds the quota of the file, with the sign, to its top-level folder.
+fn account<'a>(delta: &mut HashMap<&'a str, i64>, side: &Side<'a>, file: &'a File, sign: i64) {
+    let top = side.top_level(file);
+    *delta.entry(&top.id).or_default() += sign * quota(file) as i64;
+}
+
+pub fn diff(old_path: &Path, new_path: &Path) -> anyhow::Result<()> {
+    let (old_files, new_files) = (load(old_path)?, load(new_path)?);
+    let (old, new) = (Side::new(&old_files)?, Side::new(&new_files)?);
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut renamed = vec![];
+    let mut moved = vec![];
+    let mut resized = vec![];
+    // Net quota change keyed by the id of the top-level folder
+    let mut delta = HashMap::<&str, i64>::new();
+    for file in &new_files {
+        let Some(&before) = old.by_id.get(&file.id as &str) else {
+            added.push(file);
+            account(&mut delta, &new, file, 1);
+            continue;
+        };
+        if before.name != file.name {
+            renamed.push((before, file));
+        }
+        if before.parents != file.parents {
+            moved.push((before, file));
+        }
+        if quota(before) != quota(file) {
+            resized.push((before, file));
+        }
+        if before.parents != file.parents || quota(before) != quota(file) {
+            account(&mut delta, &old, before, -1);
+            account(&mut delta, &new, file, 1);
+        }
+    }
+    for file in &old_files {
+        if !new.by_id.contains_key(&file.id as &str) {
+            removed.push(file);
+            account(&mut delta, &old, file, -1);
+        }
+    }
+
+    added.sort_by_key(|file| std::cmp::Reverse(quota(file)));
+    removed.sort_by_key(|file| std::cmp::Reverse(quota(file)));
+    resized.sort_by_key(|(before, file)| std::cmp::Reverse(quota(file).abs_diff(quota(before))));
+
+    let mut report = json!({});
+    outln!("=== Added ({}) ===", added.len());
+    let mut rows = vec![];
+    for file in &added {
+        let path = new.path(file)?;
+        outln!("{:>12}  {path}", format_size(quota(file)));
+        rows.push(json!({ "path": path, "quota": quota(file) }));
+    }
+    report["added"] = rows.into();
+    outln!("=== Removed ({}) ===", removed.len());
+    let mut rows = vec![];
+    for file in &removed {
+        let path = old.path(file)?;
+        outln!("{:>12}  {path}", format_size(quota(file)));
+        rows.push(json!({ "path": path, "quota": quota(file) }));
+    }
+    report["removed"] = rows.into();
+    outln!("=== Renamed ({}) ===", renamed.len());
+    let mut rows = vec![];
+    for (before, file) in &renamed {
+        let path = old.path(before)?;
+        outln!("{path} -> {:?}", file.name);
+        rows.push(json!({ "path": path, "name": file.name }));
+    }
+    report["renamed"] = rows.into();
+    outln!("=== Moved ({}) ===", moved.len());
+    let mut rows = vec![];
+    for (before, file) in &moved {
+        let (from, to) = (old.path(before)?, new.path(file)?);
+        outln!("{from} -> {to}");
+        rows.push(json!({ "from": from, "to": to }));
+    }
+    report["moved"] = rows.into();
+    outln!("=== Resized ({}) ===", resized.len());
+    let mut rows = vec![];
+    for (before, file) in &resized {
+        let path = new.path(file)?;
+        outln!(
+            "{:>12} -> {:>12}  {path}",
+            format_size(quota(before)),
+            format_size(quota(file)),
+        );
+        rows.push(json!({ "path": path, "before": quota(before), "after": quota(file) }));
+    }
+    report["resized"] = rows.into();
+
+    outln!("=== Net change per top-level folder ===");
+    let mut delta: Vec<_> = delta.into_iter().filter(|&(_, bytes)| bytes != 0).collect();
+    delta.sort_by_key(|&(id, bytes)| (std::cmp::Reverse(bytes), id));
+    let mut rows = vec![];
+    for (id, bytes) in delta {
+        // Prefer the current name, unless the folder itself is gone
+        let path = match (new.by_id.get(id), old.by_id.get(id)) {
+            (Some(file), _) => new.path(file)?,
+            (None, Some(file)) => old.path(file)?,
+            (None, None) => unreachable!("every key comes from one of the snapshots"),
+        };
+        let sign = if bytes < 0 { "-" } else { "+" };
+        outln!("{sign}{:>11}  {path}", format_size(bytes.unsigned_abs()));
+        rows.push(json!({ "path": path, "delta": bytes }));
+    }
+    report["net_change"] = rows.into();
+    output::emit(&report)
+}
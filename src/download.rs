@@ -0,0 +1,618 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::{stream, StreamExt};
+use google_drive3::{
+    api::Scope,
+    hyper::{body::HttpBody, header, Body, Request, StatusCode},
+    Error,
+};
+use log::{error, info, warn};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    budget::ApiBudget, drive_api::DriveApi, fetch_subtree, format_size, full_path,
+    path_with_suffix, restore_files, retry::RetryPolicy, with_index, CancellationToken, Drive,
+    File, FileIndex, ListOptions, MemoryIndex, Store,
+};
+
+/// Name of the manifest kept in the local folder
+pub(crate) const MANIFEST_NAME: &str = ".drive-crawl-manifest.jsonl";
+
+/// Line of the manifest, recorded once the file has been downloaded and verified
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    id: String,
+    /// Relative to the local folder
+    path: PathBuf,
+    size: u64,
+    /// The checksum the file was verified against, if any
+    checksum: Option<String>,
+    /// Tells whether an exported copy of a Google-native file is outdated
+    #[serde(default)]
+    modified_time: Option<DateTime<Utc>>,
+    time: DateTime<Utc>,
+}
+impl ManifestEntry {
+    fn new(
+        file: &File,
+        path: &Path,
+        local_path: &Path,
+        checksum: Option<String>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: file.id.clone(),
+            path: path.strip_prefix(local_path).unwrap_or(path).to_owned(),
+            size: fs_err::metadata(path)?.len(),
+            checksum,
+            modified_time: file.modified_time,
+            time: Utc::now(),
+        })
+    }
+    /// Whether the entry still describes the file, which may have changed since.
+    fn is_current(&self, file: &File) -> bool {
+        if is_native(file) {
+            return file.modified_time.is_some() && self.modified_time == file.modified_time;
+        }
+        // Entries written before SHA1 was fetched hold the MD5 instead
+        let checksums = [
+            &file.sha256_checksum,
+            &file.sha1_checksum,
+            &file.md5_checksum,
+        ];
+        file.size.is_some_and(|size| size == self.size)
+            && match self.checksum {
+                Some(ref checksum) => checksums.iter().any(|c| c.as_ref() == Some(checksum)),
+                None => checksums.iter().all(|c| c.is_none()),
+            }
+    }
+}
+
+fn load_manifest(path: &Path) -> anyhow::Result<HashMap<String, ManifestEntry>> {
+    let file = match fs_err::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => Err(e)?,
+    };
+    let mut manifest = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let entry: ManifestEntry = serde_json::from_str(&line?)?;
+        manifest.insert(entry.id.clone(), entry);
+    }
+    Ok(manifest)
+}
+
+fn write_manifest_entry(writer: &mut impl Write, entry: &ManifestEntry) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, entry)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(crate) fn is_native(file: &File) -> bool {
+    file.mime_type.starts_with("application/vnd.google-apps.")
+}
+
+/// Format Google-native files are converted to, picked per kind of document
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Microsoft Office: docx, xlsx and pptx
+    Docx,
+    /// OpenDocument: odt, ods and odp
+    Odt,
+    Pdf,
+    /// Only the first sheet of a spreadsheet, and plain text for the other documents
+    Csv,
+}
+impl ExportFormat {
+    /// MIME type and extension of the export of a file with the given MIME type, if any
+    fn export_type(self, mime_type: &str) -> Option<(&'static str, &'static str)> {
+        use ExportFormat::*;
+        const PDF: (&str, &str) = ("application/pdf", "pdf");
+        const TEXT: (&str, &str) = ("text/plain", "txt");
+        Some(match (mime_type, self) {
+            ("application/vnd.google-apps.document", Docx) => (
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                "docx",
+            ),
+            ("application/vnd.google-apps.document", Odt) => {
+                ("application/vnd.oasis.opendocument.text", "odt")
+            }
+            ("application/vnd.google-apps.document", Pdf) => PDF,
+            ("application/vnd.google-apps.document", Csv) => TEXT,
+            ("application/vnd.google-apps.spreadsheet", Docx) => (
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "xlsx",
+            ),
+            ("application/vnd.google-apps.spreadsheet", Odt) => {
+                ("application/vnd.oasis.opendocument.spreadsheet", "ods")
+            }
+            ("application/vnd.google-apps.spreadsheet", Pdf) => PDF,
+            ("application/vnd.google-apps.spreadsheet", Csv) => ("text/csv", "csv"),
+            ("application/vnd.google-apps.presentation", Docx) => (
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+                "pptx",
+            ),
+            ("application/vnd.google-apps.presentation", Odt) => {
+                ("application/vnd.oasis.opendocument.presentation", "odp")
+            }
+            ("application/vnd.google-apps.presentation", Pdf) => PDF,
+            ("application/vnd.google-apps.presentation", Csv) => TEXT,
+            // Drawings have no office format to speak of
+            ("application/vnd.google-apps.drawing", _) => PDF,
+            // Forms, sites, shortcuts and the like cannot be exported
+            _ => return None,
+        })
+    }
+}
+
+pub struct Options {
+    /// Fetch the folder tree from the API instead of the snapshot
+    pub live: bool,
+    /// Number of files downloaded at the same time
+    pub concurrency: usize,
+    /// Convert Google-native files to this format instead of skipping them
+    pub export_native: Option<ExportFormat>,
+    /// Delete the local files and folders that are not on Drive (anymore)
+    pub delete_extraneous: bool,
+}
+
+pub async fn download_folder(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    folder_id: &str,
+    local_path: &Path,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let files = if options.live {
+        let files = fetch_subtree(drive, cancel, retry, folder_id, &ListOptions::default()).await?;
+        walk(&MemoryIndex::new(&files)?, folder_id, local_path)?
+    } else {
+        with_index(store, |index| walk(index, folder_id, local_path))?
+    };
+    info!("{} files and folders to download", files.len());
+
+    fs_err::create_dir_all(local_path)?;
+    let manifest_path = local_path.join(MANIFEST_NAME);
+    let manifest = load_manifest(&manifest_path)?;
+    let mut manifest_writer = BufWriter::new(
+        fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&manifest_path)?,
+    );
+
+    let (mut downloaded, mut moved, mut skipped, mut failed) = (0, 0, 0, 0);
+    // Local paths of everything on Drive, which are not extraneous
+    let mut expected = HashSet::from([local_path.to_owned(), manifest_path.clone()]);
+    let mut targets = vec![];
+    for (path, file) in files {
+        if file.mime_type == "application/vnd.google-apps.folder" {
+            fs_err::create_dir_all(&path)?;
+            expected.insert(path);
+            continue;
+        }
+        let (path, export) = if is_native(&file) {
+            let export = options
+                .export_native
+                .and_then(|format| format.export_type(&file.mime_type));
+            let Some((mime_type, extension)) = export else {
+                warn!("Skipping Google-native file {path:?} ({})", file.mime_type);
+                skipped += 1;
+                continue;
+            };
+            (
+                path_with_suffix(&path, &format!(".{extension}")),
+                Some(mime_type),
+            )
+        } else {
+            (path, None)
+        };
+        expected.insert(path.clone());
+        targets.push((path, file, export));
+    }
+
+    let mut queue = vec![];
+    for (path, file, export) in targets {
+        if path.exists() {
+            if manifest.get(&file.id).is_some_and(|e| e.is_current(&file)) {
+                info!("Already downloaded: {path:?}");
+                skipped += 1;
+                continue;
+            }
+            // Downloaded before the manifest was introduced.  Exports have no checksum to verify.
+            if export.is_none() {
+                if let Ok(checksum @ Some(_)) = verify(&path, &file) {
+                    info!("Already downloaded: {path:?}");
+                    let entry = ManifestEntry::new(&file, &path, local_path, checksum)?;
+                    write_manifest_entry(&mut manifest_writer, &entry)?;
+                    skipped += 1;
+                    continue;
+                }
+            }
+        } else if let Some(entry) = manifest.get(&file.id).filter(|e| e.is_current(&file)) {
+            // Moved or renamed on Drive since the last run, unless another file has taken over
+            // the old path
+            let old = local_path.join(&entry.path);
+            if old.exists() && !expected.contains(&old) {
+                fs_err::rename(&old, &path)?;
+                info!("Moved {old:?} to {path:?}");
+                let entry = ManifestEntry::new(&file, &path, local_path, entry.checksum.clone())?;
+                write_manifest_entry(&mut manifest_writer, &entry)?;
+                moved += 1;
+                continue;
+            }
+        }
+        queue.push((path, file, export));
+    }
+
+    let mut results = stream::iter(&queue)
+        .map(|(path, file, export)| async move {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            info!("Downloading {path:?}");
+            let result = match export {
+                Some(mime_type) => export_file(drive, cancel, retry, file, mime_type, path).await,
+                None => download_file(drive, cancel, retry, file, path).await,
+            };
+            Some((path, file, result))
+        })
+        .buffer_unordered(options.concurrency.max(1));
+    // Downloaded files that no checksum is known for, whose content is trusted as is
+    let mut unverified = vec![];
+    while let Some(result) = results.next().await {
+        let Some((path, file, result)) = result else {
+            continue;
+        };
+        match result {
+            Ok(checksum) => {
+                if checksum.is_none() && !is_native(file) {
+                    unverified.push(path);
+                }
+                let entry = ManifestEntry::new(file, path, local_path, checksum)?;
+                write_manifest_entry(&mut manifest_writer, &entry)?;
+                downloaded += 1;
+            }
+            Err(e) => {
+                error!("Failed to download {path:?}: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+    if cancel.is_cancelled() {
+        info!("Received ctrl-c.  Interrupted downloads will resume from where they stopped.");
+    }
+    info!("Downloaded {downloaded}, moved {moved}, skipped {skipped}, failed {failed} files");
+    if !unverified.is_empty() {
+        unverified.sort();
+        outln!("=== Downloaded without a checksum to verify against ===");
+        for path in unverified {
+            outln!("{}", path.display());
+        }
+    }
+
+    if options.delete_extraneous && !cancel.is_cancelled() {
+        let deleted = delete_extraneous(local_path, &expected)?;
+        info!("Deleted {deleted} files and folders no longer on Drive");
+    }
+    Ok(())
+}
+
+/// Resolves a file given by id, or by a path like `/folder/file` in the snapshot, to its
+/// current metadata, following a shortcut to its target.
+async fn resolve_file(
+    drive: &Drive,
+    store: &Store,
+    retry: &RetryPolicy,
+    target: &str,
+) -> anyhow::Result<File> {
+    let id = if target.starts_with('/') {
+        let files = restore_files(store)?;
+        let index = MemoryIndex::new(&files)?;
+        let mut found = vec![];
+        for file in &files {
+            if !file.trashed && full_path(&index, file)? == target {
+                found.push(&file.id as &str);
+            }
+        }
+        match found[..] {
+            [] => bail!("{target} is not in the snapshot.  Sync it, or pass the id instead."),
+            [id] => id.to_owned(),
+            _ => bail!(
+                "{} files are at {target}: {}.  Pass one of the ids instead.",
+                found.len(),
+                found.join(", ")
+            ),
+        }
+    } else {
+        target.to_owned()
+    };
+    // The snapshot may be outdated, while the checksum has to match the content downloaded
+    let fields = ListOptions::default().fields();
+    let file = File::try_from(retry.run(|| drive.get_file(&id, &fields)).await?)?;
+    match file.shortcut_details {
+        Some(ref details) => {
+            info!("Following the shortcut {} to its target", file.name);
+            let target = retry
+                .run(|| drive.get_file(&details.target_id, &fields))
+                .await?;
+            File::try_from(target)
+        }
+        None => Ok(file),
+    }
+}
+
+/// Downloads a single file given by id or path into `dest`, which defaults to the current
+/// directory, converting a Google-native one to the format and verifying the checksum.
+pub async fn download_one(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    target: &str,
+    dest: Option<&Path>,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    let file = resolve_file(drive, store, retry, target).await?;
+    if file.mime_type == "application/vnd.google-apps.folder" {
+        bail!("{} is a folder.  Use `download` for folders.", file.name);
+    }
+    let export = if is_native(&file) {
+        let Some(export) = format.export_type(&file.mime_type) else {
+            bail!("{} ({}) cannot be exported", file.name, file.mime_type);
+        };
+        Some(export)
+    } else {
+        None
+    };
+    // A directory, or no destination at all, takes the name on Drive
+    let path = match dest {
+        Some(dest) if !dest.is_dir() => dest.to_owned(),
+        dest => {
+            let path = dest
+                .unwrap_or(Path::new(""))
+                .join(sanitize_name(&file.name));
+            match export {
+                Some((_, extension)) => path_with_suffix(&path, &format!(".{extension}")),
+                None => path,
+            }
+        }
+    };
+    let checksum = match export {
+        Some((mime_type, _)) => export_file(drive, cancel, retry, &file, mime_type, &path).await?,
+        None => download_file(drive, cancel, retry, &file, &path).await?,
+    };
+    let size = fs_err::metadata(&path)?.len();
+    match checksum {
+        Some(checksum) => outln!(
+            "Saved {} ({}), verified against {checksum}",
+            path.display(),
+            format_size(size)
+        ),
+        None => outln!("Saved {} ({})", path.display(), format_size(size)),
+    }
+    Ok(())
+}
+
+/// Deletes the files and folders under `dir` that are not in `expected`, keeping the partial
+/// downloads of the expected files so that they can be resumed.  Returns the number deleted.
+fn delete_extraneous(dir: &Path, expected: &HashSet<PathBuf>) -> anyhow::Result<usize> {
+    let mut deleted = 0;
+    for entry in fs_err::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+        if expected.contains(&path) {
+            if is_dir {
+                deleted += delete_extraneous(&path, expected)?;
+            }
+            continue;
+        }
+        let partial = path
+            .to_str()
+            .and_then(|p| p.strip_suffix(".part"))
+            .is_some_and(|p| expected.contains(Path::new(p)));
+        if partial {
+            continue;
+        }
+        if is_dir {
+            fs_err::remove_dir_all(&path)?;
+        } else {
+            fs_err::remove_file(&path)?;
+        }
+        info!("Deleted {path:?}");
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+/// Lists the folder and its descendants along with their local paths, parents first.
+pub(crate) fn walk(
+    index: &dyn FileIndex,
+    folder_id: &str,
+    local_path: &Path,
+) -> anyhow::Result<Vec<(PathBuf, File)>> {
+    let Some(root) = index.get(folder_id)? else {
+        bail!("File with id {folder_id:?} was not found");
+    };
+    let mut ret = vec![];
+    let mut stack = vec![(local_path.to_owned(), root)];
+    while let Some((path, file)) = stack.pop() {
+        for child in index.children(&file.id)? {
+            stack.push((path.join(sanitize_name(&child.name)), child));
+        }
+        ret.push((path, file));
+    }
+    Ok(ret)
+}
+
+/// Drive allows `/` in names, which would otherwise introduce an extra directory level.
+fn sanitize_name(name: &str) -> String {
+    name.replace('/', "_")
+}
+
+/// Downloads into `<path>.part`, which is kept across failures and runs so that the transfer
+/// resumes from where it stopped, and moves it to the path once verified.  Returns the checksum
+/// it was verified against.
+async fn download_file(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    file: &File,
+    path: &Path,
+) -> anyhow::Result<Option<String>> {
+    let part = path_with_suffix(path, ".part");
+    retry
+        .without_timeout()
+        .run(|| fetch_into(drive, cancel, retry.budget, &file.id, &part))
+        .await?;
+    let checksum = match verify(&part, file) {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            // Resuming a corrupt file would never succeed
+            fs_err::remove_file(&part)?;
+            return Err(e);
+        }
+    };
+    fs_err::rename(&part, path)?;
+    Ok(checksum)
+}
+
+/// Exports a Google-native file into `<path>.part` and moves it to the path once complete.
+/// Exports cannot be resumed, so the partial file is always overwritten.
+async fn export_file(
+    api: &impl DriveApi,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    file: &File,
+    mime_type: &str,
+    path: &Path,
+) -> anyhow::Result<Option<String>> {
+    let part = path_with_suffix(path, ".part");
+    retry
+        .without_timeout()
+        .run(|| async {
+            let mut res = api.export_file(&file.id, mime_type).await?;
+            let mut writer = BufWriter::new(fs_err::File::create(&part).map_err(Error::Io)?);
+            while let Some(chunk) = res.body_mut().data().await {
+                if cancel.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                let chunk = chunk.map_err(Error::HttpError)?;
+                retry.budget.record_bytes(chunk.len() as u64);
+                writer.write_all(&chunk).map_err(Error::Io)?;
+                retry.budget.throttle(chunk.len() as u64).await;
+            }
+            writer.flush().map_err(Error::Io)
+        })
+        .await?;
+    fs_err::rename(&part, path)?;
+    // Exports carry no checksum
+    Ok(None)
+}
+
+/// Appends the rest of the content to the partial file with an HTTP range request.  Built by
+/// hand since the generated API offers no way to set the `Range` header.
+async fn fetch_into(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    budget: &ApiBudget,
+    file_id: &str,
+    part: &Path,
+) -> google_drive3::Result<()> {
+    let offset = fs_err::metadata(part).map_or(0, |m| m.len());
+    let token = drive
+        .auth
+        .get_token(&[Scope::Readonly.as_ref()])
+        .await
+        .map_err(Error::MissingToken)?
+        .ok_or_else(|| Error::MissingToken("No access token was obtained".into()))?;
+    let mut request = Request::get(format!(
+        "https://www.googleapis.com/drive/v3/files/{file_id}?alt=media&supportsAllDrives=true"
+    ))
+    .header(header::AUTHORIZATION, format!("Bearer {token}"));
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={offset}-"));
+    }
+    let request = request.body(Body::empty()).expect("the request is valid");
+    let mut res = drive
+        .client
+        .request(request)
+        .await
+        .map_err(Error::HttpError)?;
+    let append = match res.status() {
+        StatusCode::PARTIAL_CONTENT => true,
+        // The server may ignore the range and send everything
+        StatusCode::OK => false,
+        // Everything has already been fetched
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        _ => return Err(Error::Failure(res)),
+    };
+    let file = fs_err::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .map_err(Error::Io)?;
+    let mut writer = BufWriter::new(file);
+    while let Some(chunk) = res.body_mut().data().await {
+        if cancel.is_cancelled() {
+            writer.flush().map_err(Error::Io)?;
+            return Err(Error::Cancelled);
+        }
+        let chunk = chunk.map_err(Error::HttpError)?;
+        budget.record_bytes(chunk.len() as u64);
+        writer.write_all(&chunk).map_err(Error::Io)?;
+        budget.throttle(chunk.len() as u64).await;
+    }
+    writer.flush().map_err(Error::Io)?;
+    Ok(())
+}
+
+/// Checks the file against the size and the strongest checksum in the snapshot, SHA256, SHA1 or
+/// MD5 in this order, and returns the checksum it was verified against.
+pub(crate) fn verify(path: &Path, file: &File) -> anyhow::Result<Option<String>> {
+    let len = fs_err::metadata(path)?.len();
+    if let Some(size) = file.size {
+        if len != size {
+            bail!("Size mismatch: expected {size}, got {len}");
+        }
+    }
+    let (expected, actual) = if let Some(ref expected) = file.sha256_checksum {
+        (expected, hash_file::<Sha256>(path)?)
+    } else if let Some(ref expected) = file.sha1_checksum {
+        (expected, hash_file::<Sha1>(path)?)
+    } else if let Some(ref expected) = file.md5_checksum {
+        (expected, hash_file::<Md5>(path)?)
+    } else {
+        warn!("{path:?} does not have a checksum to verify against");
+        return Ok(None);
+    };
+    if *expected != actual {
+        bail!("Checksum mismatch: expected {expected}, got {actual}");
+    }
+    Ok(Some(actual))
+}
+
+pub(crate) fn hash_file<D: Digest + Write>(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = D::new();
+    std::io::copy(&mut fs_err::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
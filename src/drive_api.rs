@@ -0,0 +1,394 @@
+#[cfg(any(test, feature = "mock"))]
+use std::{collections::BTreeMap, path::Path, sync::Mutex};
+
+use async_trait::async_trait;
+#[cfg(any(test, feature = "mock"))]
+use google_drive3::hyper::StatusCode;
+use google_drive3::{
+    api::{self, Scope},
+    client::remove_json_null_values,
+    hyper::{body, header, Body, Request, Response},
+    Error,
+};
+use serde::de::DeserializeOwned;
+#[cfg(any(test, feature = "mock"))]
+use serde::Deserialize;
+#[cfg(any(test, feature = "mock"))]
+use serde_json::json;
+
+use crate::Drive;
+
+/// Parameters of `files.list`, where `None` leaves the default of the API
+#[derive(Clone, Default)]
+pub struct ListRequest {
+    pub corpora: Option<String>,
+    pub drive_id: Option<String>,
+    pub q: Option<String>,
+    pub spaces: Option<String>,
+    /// Include the items in shared drives
+    pub all_drives: bool,
+    pub page_size: i32,
+    pub page_token: Option<String>,
+    /// Field mask of the response
+    pub fields: String,
+    /// URLs of the scopes requested instead of the default one, if any, since [`Scope`] cannot be
+    /// cloned
+    pub scopes: Vec<String>,
+    /// Comma-separated ids of the labels returned in `labelInfo`
+    pub include_labels: Option<String>,
+}
+
+/// The calls to Drive made while crawling and modifying files, so that they can be served by
+/// `MockDrive` in tests
+#[async_trait]
+pub trait DriveApi: Sync {
+    async fn list_files(&self, request: &ListRequest) -> google_drive3::Result<api::FileList>;
+    async fn get_file(&self, id: &str, fields: &str) -> google_drive3::Result<api::File>;
+    /// Exports the Google Docs editors file in the format, returning the response to read the
+    /// content from
+    async fn export_file(&self, id: &str, mime_type: &str)
+        -> google_drive3::Result<Response<Body>>;
+    /// Applies the metadata set in `file`, returning the fields of the updated one
+    async fn update_file(
+        &self,
+        id: &str,
+        file: api::File,
+        fields: &str,
+    ) -> google_drive3::Result<api::File>;
+    /// Deletes the file permanently, skipping the trash
+    async fn delete_file(&self, id: &str) -> google_drive3::Result<()>;
+}
+
+#[async_trait]
+impl DriveApi for Drive {
+    async fn list_files(&self, request: &ListRequest) -> google_drive3::Result<api::FileList> {
+        let mut call = self
+            .files()
+            .list()
+            .page_size(request.page_size)
+            .param("fields", &request.fields)
+            .add_scopes(&request.scopes);
+        if let Some(ref corpora) = request.corpora {
+            call = call.corpora(corpora);
+        }
+        if let Some(ref drive_id) = request.drive_id {
+            call = call.drive_id(drive_id);
+        }
+        if let Some(ref q) = request.q {
+            call = call.q(q);
+        }
+        if let Some(ref spaces) = request.spaces {
+            call = call.spaces(spaces);
+        }
+        if request.all_drives {
+            call = call
+                .supports_all_drives(true)
+                .include_items_from_all_drives(true);
+        }
+        if let Some(ref token) = request.page_token {
+            call = call.page_token(token);
+        }
+        if let Some(ref labels) = request.include_labels {
+            call = call.include_labels(labels);
+        }
+        Ok(call.doit().await?.1)
+    }
+    async fn get_file(&self, id: &str, fields: &str) -> google_drive3::Result<api::File> {
+        let (_, file) = self
+            .files()
+            .get(id)
+            .supports_all_drives(true)
+            .param("fields", fields)
+            .doit()
+            .await?;
+        Ok(file)
+    }
+    async fn export_file(
+        &self,
+        id: &str,
+        mime_type: &str,
+    ) -> google_drive3::Result<Response<Body>> {
+        self.files().export(id, mime_type).doit().await
+    }
+    async fn update_file(
+        &self,
+        id: &str,
+        file: api::File,
+        fields: &str,
+    ) -> google_drive3::Result<api::File> {
+        let (_, file) = self
+            .files()
+            .update(file, id)
+            .supports_all_drives(true)
+            .param("fields", fields)
+            .doit_without_upload()
+            .await?;
+        Ok(file)
+    }
+    async fn delete_file(&self, id: &str) -> google_drive3::Result<()> {
+        self.files()
+            .delete(id)
+            .supports_all_drives(true)
+            .doit()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Sends a request built by hand, for the calls the generated client cannot make, with a token
+/// for the scope.  The response is parsed as JSON.
+pub(crate) async fn send_json<T: DeserializeOwned>(
+    drive: &Drive,
+    scope: &str,
+    request: Request<Body>,
+) -> google_drive3::Result<T> {
+    let token = drive
+        .auth
+        .get_token(&[scope])
+        .await
+        .map_err(Error::MissingToken)?
+        .ok_or_else(|| Error::MissingToken("No access token was obtained".into()))?;
+    let mut request = request;
+    request.headers_mut().insert(
+        header::AUTHORIZATION,
+        format!("Bearer {token}")
+            .parse()
+            .expect("the token is a valid header value"),
+    );
+    let res = drive
+        .client
+        .request(request)
+        .await
+        .map_err(Error::HttpError)?;
+    let status = res.status();
+    let bytes = body::to_bytes(res.into_body())
+        .await
+        .map_err(Error::HttpError)?;
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    if !status.is_success() {
+        return Err(match serde_json::from_str(&text) {
+            Ok(value) => Error::BadRequest(value),
+            Err(_) => Error::Failure(
+                Response::builder()
+                    .status(status)
+                    .body(Body::from(text))
+                    .expect("the response is valid"),
+            ),
+        });
+    }
+    serde_json::from_str(&text).map_err(|e| Error::JsonDecodeError(text, e))
+}
+
+/// Creates a file without content, like a folder or a shortcut, returning its id.  Built by
+/// hand since the generated client creates files only along with some content.
+pub(crate) async fn create_file(
+    drive: &Drive,
+    file: &api::File,
+) -> google_drive3::Result<api::File> {
+    let mut value = serde_json::to_value(file).expect("files are serializable");
+    remove_json_null_values(&mut value);
+    let request =
+        Request::post("https://www.googleapis.com/drive/v3/files?supportsAllDrives=true&fields=id")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(value.to_string()))
+            .expect("the request is valid");
+    send_json(drive, Scope::Full.as_ref(), request).await
+}
+
+/// Error injected into the calls of [`MockDrive`]
+#[cfg(any(test, feature = "mock"))]
+#[derive(Clone, Deserialize)]
+pub struct Failure {
+    /// `list`, `get`, `export`, `update` or `delete`
+    pub call: String,
+    /// Fails only the calls for this page token of `list`, or this id of the others
+    #[serde(default)]
+    pub arg: Option<String>,
+    pub status: u16,
+    /// Reason given in the body, like `userRateLimitExceeded`
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Number of matching calls that fail before they start succeeding
+    #[serde(default = "one")]
+    pub times: u32,
+}
+#[cfg(any(test, feature = "mock"))]
+fn one() -> u32 {
+    1
+}
+
+#[cfg(any(test, feature = "mock"))]
+/// Files served from memory, listed in pages of the requested size whose tokens are the offsets.
+/// Queries, spaces and field masks are ignored.
+#[derive(Deserialize)]
+pub struct Fixture {
+    pub files: Vec<api::File>,
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+/// Stands in for Drive in tests, serving the files of a [`Fixture`] and recording the calls
+pub struct MockDrive {
+    state: Mutex<MockState>,
+}
+#[cfg(any(test, feature = "mock"))]
+struct MockState {
+    /// By id, so that the pages are in a stable order
+    files: BTreeMap<String, api::File>,
+    failures: Vec<Failure>,
+    calls: Vec<String>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockDrive {
+    pub fn new(fixture: Fixture) -> Self {
+        let files = fixture
+            .files
+            .into_iter()
+            .map(|file| (file.id.clone().unwrap_or_default(), file))
+            .collect();
+        Self {
+            state: Mutex::new(MockState {
+                files,
+                failures: fixture.failures,
+                calls: vec![],
+            }),
+        }
+    }
+    /// Loads the fixture from a JSON file.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let fixture = serde_json::from_reader(std::io::BufReader::new(fs_err::File::open(path)?))?;
+        Ok(Self::new(fixture))
+    }
+    /// Calls made so far, like `list 2` for listing the page with the token `2`
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+    /// The files as modified by the calls so far
+    pub fn files(&self) -> Vec<api::File> {
+        self.state.lock().unwrap().files.values().cloned().collect()
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockState {
+    /// Records the call and fails it if a failure is left for it.
+    fn call(&mut self, call: &str, arg: &str) -> google_drive3::Result<()> {
+        self.calls.push(format!("{call} {arg}"));
+        let failure = self
+            .failures
+            .iter_mut()
+            .find(|f| f.call == call && f.times > 0 && f.arg.as_deref().is_none_or(|a| a == arg));
+        let Some(failure) = failure else {
+            return Ok(());
+        };
+        failure.times -= 1;
+        Err(match failure.reason {
+            Some(ref reason) => Error::BadRequest(json!({
+                "error": {
+                    "code": failure.status,
+                    "errors": [{ "reason": reason }],
+                },
+            })),
+            None => Error::Failure(
+                Response::builder()
+                    .status(failure.status)
+                    .body(Body::empty())
+                    .expect("the response is valid"),
+            ),
+        })
+    }
+    fn not_found(id: &str) -> Error {
+        Error::BadRequest(json!({
+            "error": {
+                "code": StatusCode::NOT_FOUND.as_u16(),
+                "message": format!("File not found: {id}."),
+            },
+        }))
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+#[async_trait]
+impl DriveApi for MockDrive {
+    async fn list_files(&self, request: &ListRequest) -> google_drive3::Result<api::FileList> {
+        let mut state = self.state.lock().unwrap();
+        let token = request.page_token.as_deref().unwrap_or_default();
+        state.call("list", token)?;
+        let offset: usize = token.parse().unwrap_or(0);
+        let page_size = request.page_size.max(1) as usize;
+        let files: Vec<_> = state
+            .files
+            .values()
+            .skip(offset)
+            .take(page_size)
+            .cloned()
+            .collect();
+        let end = offset + page_size;
+        Ok(api::FileList {
+            files: Some(files),
+            next_page_token: (end < state.files.len()).then(|| end.to_string()),
+            ..Default::default()
+        })
+    }
+    async fn get_file(&self, id: &str, _fields: &str) -> google_drive3::Result<api::File> {
+        let mut state = self.state.lock().unwrap();
+        state.call("get", id)?;
+        state
+            .files
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MockState::not_found(id))
+    }
+    async fn export_file(
+        &self,
+        id: &str,
+        mime_type: &str,
+    ) -> google_drive3::Result<Response<Body>> {
+        let mut state = self.state.lock().unwrap();
+        state.call("export", id)?;
+        let file = state
+            .files
+            .get(id)
+            .ok_or_else(|| MockState::not_found(id))?;
+        let content = format!(
+            "{} as {mime_type}",
+            file.name.as_deref().unwrap_or_default()
+        );
+        Ok(Response::new(Body::from(content)))
+    }
+    async fn update_file(
+        &self,
+        id: &str,
+        file: api::File,
+        _fields: &str,
+    ) -> google_drive3::Result<api::File> {
+        let mut state = self.state.lock().unwrap();
+        state.call("update", id)?;
+        let target = state
+            .files
+            .get_mut(id)
+            .ok_or_else(|| MockState::not_found(id))?;
+        // Only the fields set in `file` are changed, as with a PATCH
+        let mut merged = serde_json::to_value(&*target).expect("files are serializable");
+        if let serde_json::Value::Object(changes) =
+            serde_json::to_value(file).expect("files are serializable")
+        {
+            for (key, value) in changes.into_iter().filter(|(_, value)| !value.is_null()) {
+                merged[key] = value;
+            }
+        }
+        *target = serde_json::from_value(merged).expect("the merged file is valid");
+        Ok(target.clone())
+    }
+    async fn delete_file(&self, id: &str) -> google_drive3::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.call("delete", id)?;
+        state
+            .files
+            .remove(id)
+            .map(drop)
+            .ok_or_else(|| MockState::not_found(id))
+    }
+}
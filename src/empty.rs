@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use google_drive3::api;
+use log::{error, info};
+use serde_json::json;
+
+use crate::{
+    audit::AuditLog, full_path, output, restore_files, retry::RetryPolicy, CancellationToken,
+    Drive, File, MemoryIndex, Store,
+};
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Whether the folder contains nothing but folders that are empty in turn, memoized by id
+fn is_empty<'a>(
+    id_to_children: &HashMap<&str, Vec<&'a File>>,
+    memo: &mut HashMap<&'a str, bool>,
+    folder: &'a File,
+) -> bool {
+    if let Some(&empty) = memo.get(&folder.id as &str) {
+        return empty;
+    }
+    // Guards against cycles, which are not considered empty
+    memo.insert(&folder.id, false);
+    let empty = match id_to_children.get(&folder.id as &str) {
+        None => true,
+        Some(children) => children.iter().all(|&child| {
+            child.mime_type == FOLDER_MIME_TYPE && is_empty(id_to_children, memo, child)
+        }),
+    };
+    memo.insert(&folder.id, empty);
+    empty
+}
+
+/// Empty items in the snapshot, excluding the trashed ones
+struct Empty<'a> {
+    /// Topmost only, since trashing a folder trashes its subfolders too
+    folders: Vec<&'a File>,
+    /// Files of zero bytes, excluding Google Docs and shortcuts which have no size
+    files: Vec<&'a File>,
+}
+
+fn find_empty(files: &[File]) -> Empty<'_> {
+    let mut id_to_children = HashMap::<&str, Vec<&File>>::new();
+    for file in files {
+        for parent in &file.parents {
+            id_to_children.entry(parent).or_default().push(file);
+        }
+    }
+    let mut memo = HashMap::new();
+    for file in files {
+        if file.mime_type == FOLDER_MIME_TYPE {
+            is_empty(&id_to_children, &mut memo, file);
+        }
+    }
+    let folders = files
+        .iter()
+        .filter(|f| memo.get(&f.id as &str) == Some(&true))
+        .filter(|f| !f.parents.iter().any(|p| memo.get(p as &str) == Some(&true)))
+        .collect();
+    let empty_files = files
+        .iter()
+        .filter(|f| f.mime_type != FOLDER_MIME_TYPE && f.size == Some(0))
+        .collect();
+    Empty {
+        folders,
+        files: empty_files,
+    }
+}
+
+fn annotation(file: &File) -> &'static str {
+    if file.can_trash() {
+        ""
+    } else {
+        "  (cannot trash)"
+    }
+}
+
+/// Lists the empty items, and returns them in the order they are printed.
+fn print_empty<'a>(files: &'a [File], index: &MemoryIndex) -> anyhow::Result<Vec<&'a File>> {
+    let empty = find_empty(files);
+    let mut folders = empty
+        .folders
+        .iter()
+        .map(|&f| Ok((full_path(index, f)?, f)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    folders.sort_by(|x, y| x.0.cmp(&y.0));
+    outln!("=== Empty folders ===");
+    for (path, folder) in &folders {
+        outln!("{path}/{}", annotation(folder));
+    }
+    let mut empty_files = empty
+        .files
+        .iter()
+        .map(|&f| Ok((full_path(index, f)?, f)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    empty_files.sort_by(|x, y| x.0.cmp(&y.0));
+    outln!("=== Zero-byte files ===");
+    for (path, file) in &empty_files {
+        outln!("{path}{}", annotation(file));
+    }
+    outln!(
+        "{} empty folders and {} zero-byte files",
+        folders.len(),
+        empty_files.len()
+    );
+    let paths = |items: &[(String, &File)]| -> Vec<String> {
+        items.iter().map(|(path, _)| path.clone()).collect()
+    };
+    output::emit(&json!({
+        "folders": paths(&folders),
+        "files": paths(&empty_files),
+    }))?;
+    Ok(folders
+        .into_iter()
+        .chain(empty_files)
+        .map(|(_, f)| f)
+        .collect())
+}
+
+fn untrashed(store: &Store) -> anyhow::Result<Vec<File>> {
+    let mut files = restore_files(store)?;
+    files.retain(|f| !f.trashed);
+    Ok(files)
+}
+
+/// Lists the folders containing nothing but empty folders, and the files of zero bytes.
+pub fn show_empty(store: &Store) -> anyhow::Result<()> {
+    let files = untrashed(store)?;
+    print_empty(&files, &MemoryIndex::new(&files)?)?;
+    Ok(())
+}
+
+/// Lists the empty items as [`show_empty`] does and trashes them.
+pub async fn clean_empty(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+) -> anyhow::Result<()> {
+    let files = untrashed(store)?;
+    let mut targets = print_empty(&files, &MemoryIndex::new(&files)?)?;
+    let count = targets.len();
+    targets.retain(|f| f.can_trash());
+    if targets.len() < count {
+        outln!(
+            "Skipping {} items that I cannot trash",
+            count - targets.len()
+        );
+    }
+    if audit.dry_run() {
+        outln!("Would trash {} items", targets.len());
+        return Ok(());
+    }
+    let trashed = api::File {
+        trashed: Some(true),
+        ..Default::default()
+    };
+    let (mut done, mut failed) = (0, 0);
+    for file in targets {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let res = retry
+            .run(|| {
+                drive
+                    .files()
+                    .update(trashed.clone(), &file.id)
+                    .supports_all_drives(true)
+                    .param("fields", "id")
+                    .doit_without_upload()
+            })
+            .await;
+        if let Err(e) = res {
+            error!("Failed to trash {}: {e}", file.id);
+            failed += 1;
+            continue;
+        }
+        audit.record(
+            "trash",
+            &file.id,
+            json!({ "trashed": false }),
+            json!({ "trashed": true }),
+        )?;
+        done += 1;
+    }
+    outln!("Trashed {done} items, failed {failed}");
+    if done > 0 {
+        outln!("Run sync to reflect the changes in the snapshot.");
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    io::BufReader,
+    path::PathBuf,
+};
+
+use futures::{stream, StreamExt};
+use log::info;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{write_atomically, CancellationToken};
+
+/// Number of items fetched between saves of the checkpoint
+const BATCH_SIZE: usize = 100;
+
+/// Fetches something for each of many items with one or more calls per item, such as the
+/// permissions or the revisions of files.  The calls are made `concurrency` at a time and go
+/// through the [`RetryPolicy`](crate::retry::RetryPolicy) of the caller, whose budget limits the
+/// rate.  The results are saved to the checkpoint after every batch, so that an interrupted run
+/// resumes where it stopped.
+pub(crate) struct Pipeline<'a> {
+    pub cancel: &'a CancellationToken,
+    pub concurrency: usize,
+    pub checkpoint: PathBuf,
+}
+
+/// Results by the id of the item
+pub(crate) struct Enriched<T> {
+    pub results: BTreeMap<String, T>,
+    /// Whether every item has been fetched, rather than the run being cancelled
+    pub complete: bool,
+}
+
+impl Pipeline<'_> {
+    fn restore<T: DeserializeOwned>(&self) -> anyhow::Result<BTreeMap<String, T>> {
+        match fs_err::File::open(&self.checkpoint) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e)?,
+        }
+    }
+    fn save<T: Serialize>(&self, results: &BTreeMap<String, T>) -> anyhow::Result<()> {
+        write_atomically(&self.checkpoint, |writer| {
+            Ok(serde_json::to_writer(writer, results)?)
+        })
+    }
+
+    /// Calls `fetch` for each of the items not in the checkpoint.  An error aborts the run after
+    /// saving what has been fetched.
+    pub async fn run<T, Fut>(
+        &self,
+        what: &str,
+        ids: impl IntoIterator<Item = String>,
+        fetch: impl Fn(String) -> Fut,
+    ) -> anyhow::Result<Enriched<T>>
+    where
+        T: Serialize + DeserializeOwned,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut results = self.restore()?;
+        let mut seen = HashSet::new();
+        let pending: Vec<_> = ids
+            .into_iter()
+            .filter(|id| !results.contains_key(id) && seen.insert(id.clone()))
+            .collect();
+        info!(
+            "Fetching the {what} of {} items, {} resumed from {:?}",
+            pending.len(),
+            results.len(),
+            self.checkpoint
+        );
+        let mut done = 0;
+        for batch in pending.chunks(BATCH_SIZE) {
+            if self.cancel.is_cancelled() {
+                info!("Received ctrl-c.  Saving before terminating.");
+                return Ok(Enriched {
+                    results,
+                    complete: false,
+                });
+            }
+            let fetched: Vec<_> = stream::iter(batch)
+                .map(|id| {
+                    let fut = fetch(id.clone());
+                    async move { (id, fut.await) }
+                })
+                .buffer_unordered(self.concurrency.max(1))
+                .collect()
+                .await;
+            let mut error = None;
+            for (id, res) in fetched {
+                match res {
+                    Ok(value) => {
+                        results.insert(id.clone(), value);
+                        done += 1;
+                    }
+                    Err(e) => error = Some(e),
+                }
+            }
+            self.save(&results)?;
+            if let Some(e) = error {
+                return Err(e.context(format!("Failed to fetch the {what}")));
+            }
+            info!("Fetched the {what} of {done}/{} items", pending.len());
+        }
+        Ok(Enriched {
+            results,
+            complete: true,
+        })
+    }
+
+    /// Removes the checkpoint once the results have been saved elsewhere.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match fs_err::remove_file(&self.checkpoint) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+            _ => Ok(()),
+        }
+    }
+}
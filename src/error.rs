@@ -0,0 +1,45 @@
+/// Errors returned by [`Crawler`](crate::Crawler), [`Snapshot`](crate::Snapshot) and
+/// [`TreeIndex`](crate::TreeIndex)
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Drive API error: {0}")]
+    Api(#[from] google_drive3::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("The snapshot is incomplete.  Finish the crawl first.")]
+    Incomplete,
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Recovers the typed error from the internals, which report errors through `anyhow`.
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<google_drive3::Error>() {
+            Ok(error) => return Self::Api(error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return Self::Io(error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<serde_json::Error>() {
+            Ok(error) => return Self::Json(error),
+            Err(error) => error,
+        };
+        match error.downcast::<rusqlite::Error>() {
+            Ok(error) => Self::Sqlite(error),
+            Err(error) => Self::Other(error),
+        }
+    }
+}
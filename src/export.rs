@@ -0,0 +1,221 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+use chrono::Utc;
+use clap::ValueEnum;
+use log::info;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    full_path, is_under, restore_files, root_name, root_names, File, FileIndex, MemoryIndex, Store,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// The folder tree in the JSON export format of ncdu, to be browsed with `ncdu -f`
+    Ncdu,
+    /// Rules including the selected files for `rclone --filter-from`, relative to `--folder` if
+    /// given
+    RcloneFilter,
+}
+
+/// Which files are exported; all of the conditions given have to hold
+pub struct Selection<'a> {
+    /// Only the files under this folder, at any depth
+    pub folder_id: Option<&'a str>,
+    /// Prefix of the mime type, like `image/`
+    pub mime_type: Option<&'a str>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+impl Selection<'_> {
+    fn is_empty(&self) -> bool {
+        self.folder_id.is_none()
+            && self.mime_type.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+    }
+    fn matches(&self, index: &MemoryIndex, file: &File) -> bool {
+        let size = file.quota_bytes_used.unwrap_or(0);
+        self.folder_id.is_none_or(|id| is_under(index, file, id))
+            && self
+                .mime_type
+                .is_none_or(|mime| file.mime_type.starts_with(mime))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// A file flattened out of the paged snapshot
+#[derive(Serialize)]
+struct Row<'a> {
+    id: &'a str,
+    path: String,
+    mime_type: &'a str,
+    size: Option<u64>,
+    quota_bytes_used: Option<u64>,
+    sha256_checksum: Option<&'a str>,
+    parent_id: Option<&'a str>,
+    owner: Option<&'a str>,
+}
+
+/// Writes the selected files into the path, or to the standard output if omitted.
+pub fn export(
+    store: &Store,
+    format: ExportFormat,
+    selection: &Selection,
+    path: Option<&Path>,
+) -> anyhow::Result<()> {
+    if matches!(format, ExportFormat::Ncdu) && !selection.is_empty() {
+        bail!("The ncdu format always covers the whole snapshot");
+    }
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let selected: Vec<_> = files
+        .iter()
+        .filter(|file| selection.matches(&index, file))
+        .collect();
+    let writer: Box<dyn Write> = match path {
+        Some(path) => Box::new(fs_err::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(writer);
+    let rows = selected.iter().map(|file| {
+        anyhow::Ok(Row {
+            id: &file.id,
+            path: full_path(&index, file)?,
+            mime_type: &file.mime_type,
+            size: file.size,
+            quota_bytes_used: file.quota_bytes_used,
+            sha256_checksum: file.sha256_checksum.as_deref(),
+            parent_id: file.parents.first().map(|id| id as &str),
+            owner: file.owners.first().and_then(|o| o.email_address.as_deref()),
+        })
+    });
+    match format {
+        ExportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv.serialize(row?)?;
+            }
+            csv.flush()?;
+        }
+        ExportFormat::Jsonl => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row?)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Ncdu => {
+            serde_json::to_writer(&mut writer, &ncdu(store, &index)?)?;
+            writer.flush()?;
+        }
+        ExportFormat::RcloneFilter => {
+            write_rclone_filter(&mut writer, &index, &selected, selection.folder_id)?;
+            writer.flush()?;
+        }
+    }
+    if let Some(path) = path {
+        info!("Exported {} files to {path:?}", selected.len());
+    }
+    Ok(())
+}
+
+/// Escapes the characters that rclone globs treat specially.
+fn escape_rclone_glob(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes an include rule for each file, anchored at the folder if given or at the root of the
+/// drive otherwise, followed by a rule excluding everything else.  Folders are left out, since
+/// rclone descends into the ones leading to an included file by itself.
+fn write_rclone_filter(
+    writer: &mut impl Write,
+    index: &MemoryIndex,
+    files: &[&File],
+    folder_id: Option<&str>,
+) -> anyhow::Result<()> {
+    let prefix = match folder_id.and_then(|id| index.id_to_file.get(id)) {
+        Some(folder) => full_path(index, folder)?,
+        None => String::new(),
+    };
+    let mut paths = vec![];
+    for file in files {
+        if file.mime_type == "application/vnd.google-apps.folder" {
+            continue;
+        }
+        let path = full_path(index, file)?;
+        // The folder itself may be missing from the snapshot, as for the root of My Drive
+        let path = path.strip_prefix(&prefix).unwrap_or(&path);
+        paths.push(escape_rclone_glob(path));
+    }
+    paths.sort();
+    paths.dedup();
+    writeln!(writer, "# Generated by {}", env!("CARGO_PKG_NAME"))?;
+    for path in &paths {
+        writeln!(writer, "+ {path}")?;
+    }
+    writeln!(writer, "- **")?;
+    Ok(())
+}
+
+/// An ncdu directory is an array of its own info followed by its entries.
+fn ncdu_entry(index: &MemoryIndex, file: &File) -> anyhow::Result<Value> {
+    let children = index.children(&file.id)?;
+    if file.mime_type != "application/vnd.google-apps.folder" && children.is_empty() {
+        return Ok(json!({
+            "name": file.name,
+            "asize": file.size.unwrap_or(0),
+            "dsize": file.quota_bytes_used.unwrap_or(0),
+        }));
+    }
+    let mut entries = vec![json!({ "name": file.name })];
+    for child in &children {
+        entries.push(ncdu_entry(index, child)?);
+    }
+    Ok(Value::Array(entries))
+}
+
+/// The whole snapshot under a directory named `/`, with the roots as its subdirectories.
+fn ncdu(store: &Store, index: &MemoryIndex) -> anyhow::Result<Value> {
+    let root_names = root_names(store)?;
+    let mut entries = vec![json!({ "name": "/" })];
+    for id in index.roots()? {
+        let entry = match index.get(&id)? {
+            Some(file) => ncdu_entry(index, &file)?,
+            None => {
+                let mut entries = vec![json!({ "name": root_name(&root_names, &id) })];
+                for child in index.children(&id)? {
+                    entries.push(ncdu_entry(index, &child)?);
+                }
+                Value::Array(entries)
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(json!([
+        1,
+        2,
+        {
+            "progname": env!("CARGO_PKG_NAME"),
+            "progver": env!("CARGO_PKG_VERSION"),
+            "timestamp": Utc::now().timestamp(),
+        },
+        entries,
+    ]))
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::{full_path, output, restore_files, MemoryIndex, Store};
+
+pub struct Options {
+    /// Paths longer than this many characters are flagged, 260 being `MAX_PATH` of Windows
+    pub max_path: usize,
+    /// Names longer than this many bytes are flagged, the limit of most file systems
+    pub max_name: usize,
+}
+
+/// Characters Windows does not allow in names, on top of the control characters.  `:` is also
+/// shown as `/` by Finder on macOS.
+const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names that Windows reserves regardless of the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// What is wrong with the name, if anything
+fn name_problems(name: &str, options: &Options) -> Vec<&'static str> {
+    let mut problems = vec![];
+    if name
+        .chars()
+        .any(|c| ILLEGAL_CHARS.contains(&c) || c.is_control())
+    {
+        problems.push("Characters illegal on Windows");
+    }
+    if name.ends_with(' ') || name.ends_with('.') {
+        problems.push("Trailing spaces or dots, dropped by Windows");
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(stem.trim_end()))
+    {
+        problems.push("Names reserved by Windows");
+    }
+    if name.len() > options.max_name {
+        problems.push("Names too long");
+    }
+    problems
+}
+
+/// Lists the files whose names or paths would break when mirrored to Windows, macOS or a NAS,
+/// grouped by the problem.
+pub fn show_fs_audit(store: &Store, options: &Options) -> anyhow::Result<()> {
+    let mut files = restore_files(store)?;
+    files.retain(|f| !f.trashed);
+    let index = MemoryIndex::new(&files)?;
+    let mut problems = BTreeMap::<&str, Vec<String>>::new();
+    for file in &files {
+        let path = full_path(&index, file)?;
+        for problem in name_problems(&file.name, options) {
+            problems.entry(problem).or_default().push(path.clone());
+        }
+        if path.chars().count() > options.max_path {
+            problems.entry("Paths too long").or_default().push(path);
+        }
+    }
+    for (problem, paths) in &mut problems {
+        paths.sort();
+        outln!("=== {problem} ({}) ===", paths.len());
+        for path in paths.iter() {
+            outln!("{path}");
+        }
+    }
+    if problems.is_empty() {
+        outln!("No problems found");
+    }
+    output::emit(&problems)
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    decompress, open_pages, output, read_checkpoint, read_provenance, restore_shared_drives,
+    save_pages_to, saved_path, File, FileList, Store, StoreKind, SNAPSHOT_VERSION,
+};
+
+/// Problems found in one snapshot
+#[derive(Default, Serialize)]
+struct Report {
+    path: PathBuf,
+    pages: usize,
+    files: usize,
+    /// Occurrences of ids already seen in earlier pages
+    duplicate_ids: usize,
+    /// Pages followed by the same page as an earlier one, as when a resumed crawl fetched a page
+    /// twice
+    repeated_page_tokens: usize,
+    /// Files listing themselves among their parents
+    self_parents: usize,
+    /// Lines that are not a page, or pages without a list of files
+    unparseable_pages: usize,
+    /// Entries of the lists of files that are not a file
+    unparseable_files: usize,
+}
+impl Report {
+    fn is_clean(&self) -> bool {
+        self.duplicate_ids == 0
+            && self.repeated_page_tokens == 0
+            && self.self_parents == 0
+            && self.unparseable_pages == 0
+            && self.unparseable_files == 0
+    }
+}
+
+/// Pages of the snapshot as JSON values, each of which may have failed to parse
+fn read_raw_pages(path: &Path) -> anyhow::Result<Vec<anyhow::Result<Value>>> {
+    let mut reader = decompress(BufReader::new(fs_err::File::open(path)?))?;
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let version = serde_json::from_str::<Value>(&header)?["version"].as_u64();
+    if version != Some(SNAPSHOT_VERSION) {
+        // Older formats are a single JSON value, which either parses as a whole or not at all
+        return open_pages(path)?
+            .map(|page| -> anyhow::Result<anyhow::Result<Value>> {
+                Ok(Ok(serde_json::to_value(page?)?))
+            })
+            .collect();
+    }
+    let mut pages = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.is_empty() {
+            pages.push(serde_json::from_str(&line).map_err(Into::into));
+        }
+    }
+    Ok(pages)
+}
+
+/// Checks the snapshot, returning the report along with the pages cleaned up: without the
+/// duplicates, which are dropped from all but the last page they appear in, without the
+/// unparseable entries and the pages left empty, and with the files no longer listing
+/// themselves as parents.
+fn check(path: &Path) -> anyhow::Result<(Report, Vec<FileList>)> {
+    let mut report = Report {
+        path: path.to_owned(),
+        ..Default::default()
+    };
+    let mut tokens = HashSet::new();
+    let mut pages = vec![];
+    for page in read_raw_pages(path)? {
+        report.pages += 1;
+        let Some(mut page) = page.ok().filter(|page| page["files"].is_array()) else {
+            report.unparseable_pages += 1;
+            continue;
+        };
+        let next_page_token = page["nextPageToken"].as_str().map(String::from);
+        if let Some(ref token) = next_page_token {
+            if !tokens.insert(token.clone()) {
+                report.repeated_page_tokens += 1;
+            }
+        }
+        let mut files = vec![];
+        for file in page["files"].as_array_mut().into_iter().flatten() {
+            report.files += 1;
+            match serde_json::from_value::<File>(file.take()) {
+                Ok(mut file) => {
+                    let parents = file.parents.len();
+                    file.parents.retain(|id| *id != file.id);
+                    if file.parents.len() != parents {
+                        report.self_parents += 1;
+                    }
+                    files.push(file);
+                }
+                Err(_) => report.unparseable_files += 1,
+            }
+        }
+        pages.push(FileList {
+            files,
+            next_page_token,
+        });
+    }
+
+    // The latest copy of a file is the one to keep
+    let mut seen = HashSet::new();
+    for page in pages.iter_mut().rev() {
+        page.files.retain(|file| {
+            let first = seen.insert(file.id.clone());
+            if !first {
+                report.duplicate_ids += 1;
+            }
+            first
+        });
+    }
+    // The last page tells whether the crawl was complete, so it is kept even if empty
+    let count = pages.len();
+    let mut i = 0;
+    pages.retain(|page| {
+        i += 1;
+        i == count || !page.files.is_empty()
+    });
+    Ok((report, pages))
+}
+
+/// Checks the snapshots of My Drive and the shared drives for duplicate files, pages fetched
+/// twice, files that are their own parents and entries that fail to parse, and rewrites the
+/// broken ones without them if `repair` is set.  The previous snapshots are kept as `.bak`.
+pub fn fsck(store: &Store, repair: bool) -> anyhow::Result<()> {
+    if store.kind == StoreKind::Sqlite {
+        bail!("Only JSON snapshots can be checked, since SQLite keeps a row per file");
+    }
+    let mut paths = vec![store.file_list_path()];
+    for shared_drive in restore_shared_drives(store)? {
+        paths.push(store.shared_drive_data_path(&shared_drive.id));
+    }
+
+    let mut reports = vec![];
+    for path in paths {
+        let saved = saved_path(&path);
+        if !saved.exists() {
+            continue;
+        }
+        let (report, pages) = check(&saved)?;
+        outln!(
+            "{}: {} pages, {} files, {} duplicate ids, {} repeated page tokens, {} files in \
+            themselves, {} unparseable pages, {} unparseable files",
+            saved.display(),
+            report.pages,
+            report.files,
+            report.duplicate_ids,
+            report.repeated_page_tokens,
+            report.self_parents,
+            report.unparseable_pages,
+            report.unparseable_files,
+        );
+        if repair && !report.is_clean() {
+            if read_checkpoint(&path)?.is_some() {
+                warn!("Not repairing {saved:?}, whose crawl was interrupted.  Finish it first.");
+            } else {
+                // Saved compressed or not as it was
+                let compress_level = if saved == path {
+                    0
+                } else {
+                    store.compress_level.max(1)
+                };
+                // Kept as recorded, unless the header is too broken to read it
+                let crawl = read_provenance(&saved).ok().flatten();
+                save_pages_to(
+                    &path,
+                    crawl.as_ref(),
+                    pages.into_iter().map(anyhow::Ok),
+                    compress_level,
+                )?;
+                info!("Repaired {saved:?}");
+            }
+        }
+        reports.push(report);
+    }
+    let broken = reports.iter().filter(|r| !r.is_clean()).count();
+    outln!("{broken} of {} snapshots have problems", reports.len());
+    if broken > 0 && !repair {
+        outln!("Run with --repair to rewrite them without the problems.");
+    }
+    output::emit(&reports)
+}
@@ -0,0 +1,116 @@
+use anyhow::bail;
+use serde_json::json;
+
+use crate::{format_size, full_path, is_under, output, restore_files, types, MemoryIndex, Store};
+
+/// Upper bounds of the buckets but the last, which has none
+const BOUNDS: [u64; 4] = [1 << 20, 10 << 20, 100 << 20, 1 << 30];
+const LABELS: [&str; 5] = [
+    "< 1 MiB",
+    "1-10 MiB",
+    "10-100 MiB",
+    "100 MiB-1 GiB",
+    ">= 1 GiB",
+];
+const BAR_WIDTH: usize = 30;
+
+/// Number of files and bytes per bucket
+#[derive(Default)]
+struct Histogram {
+    files: [u64; 5],
+    bytes: [u64; 5],
+}
+impl Histogram {
+    fn add(&mut self, bytes: u64) {
+        let bucket = BOUNDS.iter().take_while(|&&bound| bytes >= bound).count();
+        self.files[bucket] += 1;
+        self.bytes[bucket] += bytes;
+    }
+    fn print(&self, title: &str) {
+        let bar = |value: u64, values: &[u64; 5]| {
+            let max = values.iter().copied().max().unwrap_or(0).max(1);
+            "#".repeat((value as f64 / max as f64 * BAR_WIDTH as f64).round() as usize)
+        };
+        outln!("=== {title} ===");
+        for (i, label) in LABELS.iter().enumerate() {
+            outln!(
+                "{label:>13}  {:>9} {:<BAR_WIDTH$}  {:>12} {}",
+                self.files[i],
+                bar(self.files[i], &self.files),
+                format_size(self.bytes[i]),
+                bar(self.bytes[i], &self.bytes),
+            );
+        }
+    }
+    fn to_json(&self, title: &str) -> serde_json::Value {
+        json!({
+            "title": title,
+            "buckets": LABELS
+                .iter()
+                .enumerate()
+                .map(|(i, label)| json!({
+                    "bucket": label,
+                    "files": self.files[i],
+                    "bytes": self.bytes[i],
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Prints histograms of the file sizes over buckets from 1 MiB to 1 GiB, for all files and for
+/// each of the folders and extensions, to tell many small files from a few huge ones.  Folders
+/// are not counted.
+pub fn show_histogram(
+    store: &Store,
+    folder_ids: &[String],
+    extensions: &[String],
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut selections = vec![];
+    for id in folder_ids {
+        let Some(folder) = index.id_to_file.get(id as &str) else {
+            bail!("Folder with id {id:?} was not found");
+        };
+        selections.push((full_path(&index, folder)?, Histogram::default()));
+    }
+    let extensions: Vec<_> = extensions
+        .iter()
+        .map(|e| e.trim_start_matches('.').to_lowercase())
+        .collect();
+    let mut by_extension: Vec<_> = extensions
+        .iter()
+        .map(|e| (format!("*.{e}"), Histogram::default()))
+        .collect();
+
+    let mut overall = Histogram::default();
+    for file in &files {
+        if file.mime_type == "application/vnd.google-apps.folder" {
+            continue;
+        }
+        let bytes = file.quota_bytes_used.unwrap_or(0);
+        overall.add(bytes);
+        for (id, (_, histogram)) in folder_ids.iter().zip(&mut selections) {
+            if is_under(&index, file, id) {
+                histogram.add(bytes);
+            }
+        }
+        let extension = types::extension(&file.name);
+        for (e, (_, histogram)) in extensions.iter().zip(&mut by_extension) {
+            if *e == extension {
+                histogram.add(bytes);
+            }
+        }
+    }
+
+    let mut report = vec![];
+    for (title, histogram) in std::iter::once(("All files".to_owned(), overall))
+        .chain(selections)
+        .chain(by_extension)
+    {
+        histogram.print(&title);
+        report.push(histogram.to_json(&title));
+    }
+    output::emit(&report)
+}
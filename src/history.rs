@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use log::info;
+
+use serde_json::json;
+
+use crate::{format_size, output, saved_path, Store};
+
+/// Format of the timestamp naming each archived snapshot, sortable as a string
+pub(crate) const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+fn snapshots_dir(store: &Store) -> PathBuf {
+    store.dir.join("snapshots")
+}
+
+/// Archived snapshots with the times they were taken, oldest first
+pub(crate) fn list(store: &Store) -> anyhow::Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let entries = match fs_err::read_dir(snapshots_dir(store)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => Err(e)?,
+    };
+    let mut ret = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Backups and temporary files of `write_atomically` are not snapshots
+        let Some(stem) = name
+            .strip_suffix(".json")
+            .or_else(|| name.strip_suffix(".json.zst"))
+        else {
+            continue;
+        };
+        if let Ok(time) = NaiveDateTime::parse_from_str(stem, TIMESTAMP_FORMAT) {
+            ret.push((time, path));
+        }
+    }
+    ret.sort();
+    Ok(ret)
+}
+
+/// Copies the complete snapshot just saved into `snapshots/<timestamp>.json`, then deletes the
+/// oldest ones beyond the number to keep.
+pub(crate) fn archive(store: &Store) -> anyhow::Result<()> {
+    if store.keep_snapshots == 0 {
+        return Ok(());
+    }
+    let source = saved_path(&store.file_list_path());
+    let extension = if source.extension().is_some_and(|e| e == "zst") {
+        "json.zst"
+    } else {
+        "json"
+    };
+    let dir = snapshots_dir(store);
+    fs_err::create_dir_all(&dir)?;
+    let target = dir.join(format!(
+        "{}.{extension}",
+        Utc::now().format(TIMESTAMP_FORMAT)
+    ));
+    fs_err::copy(&source, &target)?;
+    info!("Archived the snapshot to {target:?}");
+
+    let snapshots = list(store)?;
+    let excess = snapshots.len().saturating_sub(store.keep_snapshots);
+    for (_, path) in &snapshots[..excess] {
+        fs_err::remove_file(path)?;
+        info!("Deleted the old snapshot {path:?}");
+    }
+    Ok(())
+}
+
+/// Finds the archived snapshot selected by `latest`, a date like `2023-06-01`, which picks the
+/// last one taken by the end of the day in UTC, or the name of the snapshot.
+pub fn resolve(store: &Store, selector: &str) -> anyhow::Result<PathBuf> {
+    let snapshots = list(store)?;
+    let found = if selector == "latest" {
+        snapshots.last()
+    } else if let Ok(time) = NaiveDateTime::parse_from_str(selector, TIMESTAMP_FORMAT) {
+        snapshots.iter().find(|&&(t, _)| t == time)
+    } else if let Ok(date) = NaiveDate::parse_from_str(selector, "%Y-%m-%d") {
+        snapshots.iter().rev().find(|(t, _)| t.date() <= date)
+    } else {
+        bail!("Expected `latest`, a date like `2023-06-01` or the name of a snapshot, got {selector:?}");
+    };
+    let (_, path) = found.with_context(|| format!("No snapshot matches {selector:?}"))?;
+    Ok(path.clone())
+}
+
+/// `path` if it exists, or the archived snapshot selected by it otherwise
+pub fn resolve_path(store: &Store, path: &Path) -> anyhow::Result<PathBuf> {
+    if path.exists() {
+        return Ok(path.to_owned());
+    }
+    let selector = path
+        .to_str()
+        .with_context(|| format!("{path:?} does not exist"))?;
+    resolve(store, selector)
+}
+
+pub fn show_snapshots(store: &Store) -> anyhow::Result<()> {
+    let snapshots = list(store)?;
+    let mut rows = vec![];
+    for (time, path) in &snapshots {
+        let size = fs_err::metadata(path)?.len();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        outln!(
+            "{}  {:>12}  {name}",
+            time.format("%Y-%m-%d %H:%M:%S"),
+            format_size(size)
+        );
+        rows.push(json!({ "time": time, "path": path, "size": size }));
+    }
+    outln!(
+        "{} snapshots in {:?}, keeping the latest {}",
+        snapshots.len(),
+        snapshots_dir(store),
+        store.keep_snapshots
+    );
+    output::emit(&rows)
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::Value;
+
+use crate::{
+    all_paths, format_size, output, permissions, restore_files, retry::RetryPolicy, revisions,
+    Drive, Store,
+};
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or("?".into(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
+
+/// Shows everything about the file: the metadata as the API returns it, the paths and the
+/// shortcuts in the snapshot, the permissions, the revisions and what I can do with it.
+pub async fn inspect(
+    drive: &Drive,
+    store: &Store,
+    retry: &RetryPolicy,
+    file_id: &str,
+) -> anyhow::Result<()> {
+    let (_, file) = retry
+        .run(|| {
+            drive
+                .files()
+                .get(file_id)
+                .supports_all_drives(true)
+                .param("fields", "*")
+                .doit()
+        })
+        .await
+        .with_context(|| format!("Failed to fetch {file_id}"))?;
+    let mut metadata = serde_json::to_value(&file)?;
+    let capabilities = metadata
+        .as_object_mut()
+        .and_then(|m| m.remove("capabilities"));
+    outln!("=== Metadata ===");
+    outln!("{}", serde_json::to_string_pretty(&metadata)?);
+
+    outln!("=== Quota ===");
+    let quota = file.quota_bytes_used.unwrap_or(0) as u64;
+    outln!("{:>12}  used", format_size(quota));
+    outln!("{:>12}  size", format_size(file.size.unwrap_or(0) as u64));
+
+    match restore_files(store) {
+        Ok(files) => {
+            let id_to_file: HashMap<_, _> = files.iter().map(|f| (&f.id as &str, f)).collect();
+            outln!("=== Paths ===");
+            match id_to_file.get(file_id) {
+                Some(file) => {
+                    for path in all_paths(&id_to_file, file)? {
+                        outln!("{path}");
+                    }
+                }
+                None => outln!("(not in the snapshot)"),
+            }
+            if let Some(target_id) = file
+                .shortcut_details
+                .as_ref()
+                .and_then(|d| d.target_id.as_ref())
+            {
+                outln!("=== Shortcut target ===");
+                match id_to_file.get(target_id as &str) {
+                    Some(target) => {
+                        for path in all_paths(&id_to_file, target)? {
+                            outln!("{path}");
+                        }
+                    }
+                    None => outln!("{target_id}  (not in the snapshot)"),
+                }
+            }
+            let shortcuts: Vec<_> = files
+                .iter()
+                .filter(|f| {
+                    f.shortcut_details
+                        .as_ref()
+                        .is_some_and(|d| d.target_id == file_id)
+                })
+                .collect();
+            if !shortcuts.is_empty() {
+                outln!("=== Shortcuts to this ===");
+                for shortcut in shortcuts {
+                    for path in all_paths(&id_to_file, shortcut)? {
+                        outln!("{path}");
+                    }
+                }
+            }
+        }
+        Err(e) => warn!("Failed to load the snapshot, so the paths are not shown: {e:#}"),
+    }
+
+    outln!("=== Permissions ===");
+    match permissions::fetch_permissions(drive, retry, file_id).await {
+        Ok(permissions) => {
+            for permission in &permissions {
+                match permission.expiration_time {
+                    Some(time) => outln!(
+                        "{}  (until {})",
+                        permissions::describe(permission),
+                        format_time(Some(time))
+                    ),
+                    None => outln!("{}", permissions::describe(permission)),
+                }
+            }
+        }
+        Err(e) => outln!("(unavailable: {e:#})"),
+    }
+
+    // Revisions of Google Docs and the like have no size and do not use the quota
+    let native = file
+        .mime_type
+        .as_deref()
+        .is_some_and(|t| t.starts_with("application/vnd.google-apps."));
+    if !native {
+        outln!("=== Revisions ===");
+        match revisions::list_revisions(drive, retry, file_id).await {
+            Ok(revisions) => {
+                let mut total = 0;
+                for revision in &revisions {
+                    let size = revision.size.unwrap_or(0);
+                    total += size;
+                    let pinned = if revision.keep_forever {
+                        "  (kept forever)"
+                    } else {
+                        ""
+                    };
+                    outln!(
+                        "{}  {:>12}  {}{pinned}",
+                        format_time(revision.modified_time),
+                        format_size(size),
+                        revision.id
+                    );
+                }
+                outln!(
+                    "{} revisions using {} in total",
+                    revisions.len(),
+                    format_size(total)
+                );
+            }
+            Err(e) => outln!("(unavailable: {e:#})"),
+        }
+    }
+
+    if let Some(Value::Object(capabilities)) = capabilities {
+        outln!("=== Capabilities ===");
+        for allowed in [true, false] {
+            let names: Vec<_> = capabilities
+                .iter()
+                .filter(|(_, value)| value.as_bool() == Some(allowed))
+                .map(|(name, _)| name as &str)
+                .collect();
+            let label = if allowed { "Allowed" } else { "Denied" };
+            outln!("{label}: {}", names.join(", "));
+        }
+    }
+    output::emit(&file)
+}
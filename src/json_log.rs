@@ -0,0 +1,61 @@
+use std::{io::Write, sync::Mutex};
+
+use chrono::Utc;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use simplelog::{Config, SharedLogger};
+
+/// Logger writing one JSON object per line, with the time, level, target and message, for log
+/// collectors
+pub struct JsonLogger<W> {
+    level: LevelFilter,
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLogger<W> {
+    pub fn new(level: LevelFilter, writer: W) -> Box<Self> {
+        Box::new(Self {
+            level,
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl<W: Write + Send> Log for JsonLogger<W> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = json!({
+            "time": Utc::now(),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        });
+        let mut writer = self.writer.lock().expect("the lock is never poisoned");
+        // Nowhere to report the failure to
+        let _ = writeln!(writer, "{line}").and_then(|()| writer.flush());
+    }
+    fn flush(&self) {
+        let _ = self
+            .writer
+            .lock()
+            .expect("the lock is never poisoned")
+            .flush();
+    }
+}
+
+impl<W: Write + Send + 'static> SharedLogger for JsonLogger<W> {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
@@ -0,0 +1,2899 @@
+//! Crawls the files in Google Drive into a local snapshot and analyzes it.
+//!
+//! [`Crawler`] fetches the snapshot into a [`Store`], and [`Snapshot`] loads it back with a
+//! [`TreeIndex`] to walk the folder hierarchy.
+
+// The calls to Drive pass on `google_drive3::Error` as is, which is large
+#![allow(clippy::result_large_err)]
+
+/// Prints a line of the human-readable output, which goes to the standard error instead when the
+/// standard output carries JSON.
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::print_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print_line(format_args!($($arg)*))
+    };
+}
+
+pub mod activity;
+pub mod audit;
+pub mod bandwidth;
+pub mod browse;
+pub mod budget;
+mod cancel;
+pub mod collisions;
+pub mod computers;
+pub mod connect;
+pub mod dashboard;
+pub mod dedupe;
+pub mod delete;
+pub mod diff;
+pub mod download;
+pub mod drive_api;
+pub mod empty;
+mod enrich;
+mod error;
+pub mod export;
+pub mod fs_audit;
+pub mod fsck;
+pub mod histogram;
+pub mod history;
+pub mod inspect;
+pub mod lock;
+pub mod merge;
+pub mod metrics;
+pub mod notify;
+pub mod orphans;
+pub mod output;
+pub mod parallel;
+pub mod parents;
+pub mod permissions;
+mod progress;
+pub mod rename;
+pub mod report;
+pub mod retry;
+pub mod revisions;
+pub mod search;
+pub mod shared_with_me;
+pub mod shortcuts;
+mod sqlite;
+pub mod stale;
+pub mod status;
+pub mod transfer;
+pub mod trash;
+pub mod tree;
+pub mod trend;
+pub mod types;
+pub mod upload;
+pub mod verify_local;
+pub mod watch;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use drive_api::{DriveApi, ListRequest};
+use google_drive3::{api::Scope, DriveHub};
+use log::{error, info, warn};
+use progress::CrawlProgress;
+use retry::RetryPolicy;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use serde_with::{serde_as, DisplayFromStr};
+
+pub use cancel::CancellationToken;
+pub use error::{Error, Result};
+pub use progress::hide_progress;
+
+/// Crawls the files into a store.  Cancelling the token given on construction makes the running
+/// crawl save what it has fetched so far and stop.
+pub struct Crawler {
+    drive: Drive,
+    store: Store,
+    retry: RetryPolicy,
+    cancel: CancellationToken,
+    query: String,
+    list: ListOptions,
+}
+impl Crawler {
+    pub fn new(drive: Drive, store: Store, cancel: CancellationToken) -> Self {
+        Self {
+            drive,
+            store,
+            retry: RetryPolicy {
+                max_attempts: 5,
+                budget: &budget::UNLIMITED,
+                timeout: None,
+                cancel: Some(cancel.clone()),
+                token_epoch: Default::default(),
+            },
+            cancel,
+            query: DEFAULT_QUERY.into(),
+            list: ListOptions::default(),
+        }
+    }
+    pub fn retry(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+    /// Search query selecting the files to crawl, `'me' in owners` by default
+    pub fn query(self, query: String) -> Self {
+        Self { query, ..self }
+    }
+    /// Page size and fields of the files requested while crawling
+    pub fn list(self, list: ListOptions) -> Self {
+        Self { list, ..self }
+    }
+    /// Crawls the files matching the query, resuming the previous crawl if any.
+    pub async fn crawl(&self) -> Result<()> {
+        Ok(list_files(
+            &self.drive,
+            &self.cancel,
+            &self.store,
+            &self.retry,
+            &self.query,
+            &self.list,
+        )
+        .await?)
+    }
+    /// Crawls only the folder and its descendants from scratch.
+    pub async fn crawl_folder(&self, folder_id: &str) -> Result<()> {
+        Ok(crawl_folder(
+            &self.drive,
+            &self.cancel,
+            &self.store,
+            &self.retry,
+            folder_id,
+            &self.list,
+        )
+        .await?)
+    }
+    /// Crawls the items shared with me from scratch, or resumes the previous crawl if it was
+    /// interrupted.  They are kept apart from the snapshot since they do not use my quota.
+    pub async fn crawl_shared_with_me(&self) -> Result<()> {
+        Ok(list_shared_with_me(
+            &self.drive,
+            &self.cancel,
+            &self.store,
+            &self.retry,
+            &self.list,
+        )
+        .await?)
+    }
+    /// Crawls every shared drive, resuming the previous crawl of each if any.
+    pub async fn crawl_shared_drives(&self) -> Result<()> {
+        Ok(list_shared_drives(
+            &self.drive,
+            &self.cancel,
+            &self.store,
+            &self.retry,
+            &self.list,
+        )
+        .await?)
+    }
+    /// Applies the changes since the last crawl or sync to the snapshot.
+    pub async fn sync(&self) -> Result<()> {
+        Ok(sync_files(&self.drive, &self.cancel, &self.store, &self.retry).await?)
+    }
+}
+
+/// The files of a store, loaded in memory
+pub struct Snapshot {
+    files: Vec<File>,
+}
+impl Snapshot {
+    /// Loads the files of the main snapshot and of all crawled shared drives.
+    pub fn load(store: &Store) -> Result<Self> {
+        Ok(Self {
+            files: restore_files(store)?,
+        })
+    }
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+    pub fn index(&self) -> Result<TreeIndex<'_>> {
+        Ok(TreeIndex(MemoryIndex::new(&self.files)?))
+    }
+}
+
+/// The folder hierarchy of a [`Snapshot`]
+pub struct TreeIndex<'a>(MemoryIndex<'a>);
+impl<'a> TreeIndex<'a> {
+    pub fn get(&self, id: &str) -> Option<&'a File> {
+        self.0.id_to_file.get(id).copied()
+    }
+    pub fn children(&self, id: &str) -> &[&'a File] {
+        self.0
+            .parent_id_to_children
+            .get(id)
+            .map_or(&[], |children| children)
+    }
+    /// Sorted ids of the topmost folders, i.e. parents that are either missing from the snapshot
+    /// or have no parent themselves
+    pub fn roots(&self) -> Result<Vec<String>> {
+        Ok(self.0.roots()?)
+    }
+    /// The `/`-separated path of the file, omitting the root
+    pub fn full_path(&self, file: &File) -> Result<String> {
+        Ok(full_path(&self.0, file)?)
+    }
+}
+
+/// Where the crawled file list is kept
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StoreKind {
+    /// `ignore/file-list.json` (`.json.zst` if compressed), rewritten as a whole on every save
+    Json,
+    /// `ignore/file-list.sqlite3`, updated page by page.  It does not record how and when the
+    /// files were crawled, so reports neither show that nor warn about partial or stale crawls.
+    Sqlite,
+}
+
+/// The snapshot together with the crawl state kept next to it
+#[derive(Clone)]
+pub struct Store {
+    pub kind: StoreKind,
+    pub dir: PathBuf,
+    /// zstd level the JSON snapshots are saved with, where 0 saves them uncompressed
+    pub compress_level: i32,
+    /// Number of complete JSON snapshots archived under `snapshots/`, where 0 archives none
+    pub keep_snapshots: usize,
+    /// Archived snapshot analyzed instead of the current one, which covers only My Drive
+    pub snapshot: Option<PathBuf>,
+}
+impl Store {
+    fn file_list_path(&self) -> PathBuf {
+        self.dir.join("file-list.json")
+    }
+    fn shared_drive_data_path(&self, id: &str) -> PathBuf {
+        self.dir.join("drives").join(format!("{id}.json"))
+    }
+    fn shared_drives_path(&self) -> PathBuf {
+        self.dir.join("drives.json")
+    }
+    fn start_page_token_path(&self) -> PathBuf {
+        self.dir.join("start-page-token.txt")
+    }
+    fn crawl_query_path(&self) -> PathBuf {
+        self.dir.join("crawl-query.txt")
+    }
+    fn crawl_fields_path(&self) -> PathBuf {
+        self.dir.join("crawl-fields.txt")
+    }
+    fn crawl_spaces_path(&self) -> PathBuf {
+        self.dir.join("crawl-spaces.txt")
+    }
+    fn crawl_labels_path(&self) -> PathBuf {
+        self.dir.join("crawl-labels.txt")
+    }
+    /// Always JSON, whichever the kind of the store
+    fn shared_with_me_path(&self) -> PathBuf {
+        self.dir.join("shared-with-me.json")
+    }
+    fn quota_path(&self) -> PathBuf {
+        self.dir.join("quota.json")
+    }
+    /// Appended to on every sync, never rewritten
+    fn change_feed_path(&self) -> PathBuf {
+        self.dir.join("changes.jsonl")
+    }
+    /// Whether anything has been crawled into the store
+    pub(crate) fn has_snapshot(&self) -> bool {
+        match self.kind {
+            StoreKind::Json => saved_path(&self.file_list_path()).exists(),
+            StoreKind::Sqlite => self.dir.join(sqlite::DB_NAME).exists(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileList {
+    pub files: Vec<File>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+const FILE_FIELDS: &str = "id,mimeType,parents,name,size,quotaBytesUsed,sha256Checksum,driveId,\
+    shortcutDetails(targetId),owners(emailAddress),shared,sharedWithMeTime,\
+    lastModifyingUser(emailAddress),modifiedTime,createdTime,md5Checksum,sha1Checksum,trashed,spaces,\
+    capabilities(canTrash,canDelete,canMoveItemWithinDrive),starred,labelInfo(labels(id))";
+
+/// Largest page size accepted by `files.list` and `changes.list`, 10 times the default
+const PAGE_SIZE: i32 = 1000;
+
+/// Collection of files kept apart by Drive, of which the Drive UI shows only `drive`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Space {
+    Drive,
+    /// Hidden data of this app, since apps can only see their own
+    #[value(name = "appDataFolder")]
+    AppDataFolder,
+    /// Legacy uploads of Google Photos
+    Photos,
+}
+impl Space {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Drive => "drive",
+            Self::AppDataFolder => "appDataFolder",
+            Self::Photos => "photos",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Drive, Self::AppDataFolder, Self::Photos]
+            .into_iter()
+            .find(|space| space.name() == name)
+    }
+    /// Scope needed to list the files in the space, which the default one does not cover
+    fn scope(self) -> Option<Scope> {
+        match self {
+            Self::Drive => None,
+            Self::AppDataFolder => Some(Scope::Appdata),
+            Self::Photos => Some(Scope::PhotoReadonly),
+        }
+    }
+}
+
+/// How files are requested from the API
+#[derive(Clone)]
+pub struct ListOptions {
+    /// Number of files per page, at most 1000
+    pub page_size: i32,
+    /// Fields requested on top of the built-in ones, kept in [`File::extra`]
+    pub extra_fields: Vec<String>,
+    /// Spaces crawled, only `drive` by default
+    pub spaces: Vec<Space>,
+    /// Ids of the Drive Labels kept in [`File::label_info`], which the API returns only when
+    /// asked for by id
+    pub labels: Vec<String>,
+}
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PAGE_SIZE,
+            extra_fields: vec![],
+            spaces: vec![Space::Drive],
+            labels: vec![],
+        }
+    }
+}
+impl ListOptions {
+    /// The field mask of each file
+    fn fields(&self) -> String {
+        std::iter::once(FILE_FIELDS)
+            .chain(self.extra_fields.iter().map(|f| f as &str))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+    /// The comma-separated label ids, as `includeLabels` takes them, if any
+    fn include_labels(&self) -> Option<String> {
+        (!self.labels.is_empty()).then(|| self.labels.join(","))
+    }
+    /// The comma-separated spaces, as `files.list` and `changes.list` take them
+    fn spaces(&self) -> String {
+        let names: Vec<_> = self.spaces.iter().map(|s| s.name()).collect();
+        names.join(",")
+    }
+    /// Scopes to request for listing the spaces.  Empty when only `drive` is listed, so that the
+    /// default scope of each call is kept.
+    fn scopes(&self) -> Vec<String> {
+        let extra: Vec<_> = self.spaces.iter().filter_map(|s| s.scope()).collect();
+        if extra.is_empty() {
+            return vec![];
+        }
+        [Scope::MetadataReadonly]
+            .into_iter()
+            .chain(extra)
+            .map(|scope| scope.as_ref().to_owned())
+            .collect()
+    }
+}
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct File {
+    pub id: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    #[serde(deserialize_with = "null_to_default")]
+    pub parents: Vec<String>,
+    pub name: String,
+    #[serde(rename = "quotaBytesUsed")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub quota_bytes_used: Option<u64>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub size: Option<u64>,
+    #[serde(rename = "sha256Checksum")]
+    pub sha256_checksum: Option<String>,
+    /// The shared drive the file belongs to; `None` for My Drive
+    #[serde(rename = "driveId", default)]
+    pub drive_id: Option<String>,
+    /// Present only for shortcuts
+    #[serde(rename = "shortcutDetails", default)]
+    pub shortcut_details: Option<ShortcutDetails>,
+    /// Empty for files in shared drives, which are owned by the drive
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub owners: Vec<User>,
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub shared: bool,
+    /// When the file was shared with me, if it is owned by someone else
+    #[serde(rename = "sharedWithMeTime", default)]
+    pub shared_with_me_time: Option<DateTime<Utc>>,
+    #[serde(rename = "lastModifyingUser", default)]
+    pub last_modifying_user: Option<User>,
+    #[serde(rename = "modifiedTime", default)]
+    pub modified_time: Option<DateTime<Utc>>,
+    #[serde(rename = "createdTime", default)]
+    pub created_time: Option<DateTime<Utc>>,
+    /// Unlike SHA256, present even for files uploaded long ago
+    #[serde(rename = "md5Checksum", default)]
+    pub md5_checksum: Option<String>,
+    #[serde(rename = "sha1Checksum", default)]
+    pub sha1_checksum: Option<String>,
+    /// Trashed files still use the quota until the trash is emptied
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub trashed: bool,
+    /// Who shared the file with me, fetched only by the crawl of the items shared with me
+    #[serde(rename = "sharingUser", default)]
+    pub sharing_user: Option<User>,
+    /// Empty for snapshots crawled before the spaces were recorded, which cover only `drive`
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub spaces: Vec<String>,
+    /// What I can do with the file, or `None` for snapshots crawled before they were recorded
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
+    /// Whether I starred the file, which is false for snapshots crawled before it was recorded
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub starred: bool,
+    /// The labels applied to the file among those crawled with [`ListOptions::labels`]
+    #[serde(rename = "labelInfo", default)]
+    pub label_info: Option<LabelInfo>,
+    /// Fields requested with [`ListOptions::extra_fields`]
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+impl File {
+    /// Whether I can move the file to the trash, assumed when the capabilities are unknown so
+    /// that the attempt tells
+    pub fn can_trash(&self) -> bool {
+        self.capabilities.as_ref().is_none_or(|c| c.can_trash)
+    }
+    /// Whether I can delete the file permanently, assumed when the capabilities are unknown
+    pub fn can_delete(&self) -> bool {
+        self.capabilities.as_ref().is_none_or(|c| c.can_delete)
+    }
+    /// Whether I can move the file to another folder, assumed when the capabilities are unknown
+    pub fn can_move(&self) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_none_or(|c| c.can_move_item_within_drive)
+    }
+    pub fn has_label(&self, id: &str) -> bool {
+        self.label_info
+            .as_ref()
+            .is_some_and(|info| info.labels.iter().any(|label| label.id == id))
+    }
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    #[serde(rename = "canTrash", default)]
+    pub can_trash: bool,
+    #[serde(rename = "canDelete", default)]
+    pub can_delete: bool,
+    #[serde(rename = "canMoveItemWithinDrive", default)]
+    pub can_move_item_within_drive: bool,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LabelInfo {
+    #[serde(default, deserialize_with = "null_to_default")]
+    pub labels: Vec<Label>,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Label {
+    pub id: String,
+}
+
+/// Restricts a report to the starred files or the files with a label, so that the usage can be
+/// told per label
+#[derive(Clone, Default)]
+pub struct MarkFilter {
+    pub starred: bool,
+    /// Id of the label
+    pub label: Option<String>,
+}
+impl MarkFilter {
+    pub fn is_empty(&self) -> bool {
+        !self.starred && self.label.is_none()
+    }
+    pub fn matches(&self, file: &File) -> bool {
+        (!self.starred || file.starred) && self.label.as_ref().is_none_or(|id| file.has_label(id))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShortcutDetails {
+    #[serde(rename = "targetId")]
+    pub target_id: String,
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct User {
+    #[serde(rename = "emailAddress")]
+    pub email_address: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SharedDrive {
+    pub id: String,
+    pub name: String,
+}
+fn null_to_default<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    let opt = Option::deserialize(d)?;
+    let val = opt.unwrap_or_default();
+    Ok(val)
+}
+impl TryFrom<google_drive3::api::FileList> for FileList {
+    type Error = anyhow::Error;
+    fn try_from(value: google_drive3::api::FileList) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&serde_json::to_string(&value)?)?)
+    }
+}
+impl TryFrom<google_drive3::api::File> for File {
+    type Error = anyhow::Error;
+    fn try_from(value: google_drive3::api::File) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&serde_json::to_string(&value)?)?)
+    }
+}
+
+pub type Drive = DriveHub<connect::Connector>;
+
+fn restore_data(store: &Store, allow_not_found: bool) -> anyhow::Result<Pages> {
+    restore_data_from(&store.file_list_path(), allow_not_found)
+}
+/// The files of all the pages, which are read one at a time
+pub(crate) fn collect_files(pages: Pages) -> anyhow::Result<Vec<File>> {
+    let mut files = vec![];
+    for page in pages {
+        files.extend(page?.files);
+    }
+    Ok(files)
+}
+/// The path of the snapshot as saved, which has `.zst` appended if compressed.  If neither
+/// exists, the one with a backup is chosen.
+fn saved_path(path: &Path) -> PathBuf {
+    let candidates = [path.to_owned(), path_with_suffix(path, ".zst")];
+    let found = candidates.iter().find(|path| path.exists()).or_else(|| {
+        candidates
+            .iter()
+            .find(|path| path_with_suffix(path, ".bak").exists())
+    });
+    found.unwrap_or(&candidates[0]).clone()
+}
+/// Pages of the snapshot, read one at a time as they are consumed
+fn restore_data_from(path: &Path, allow_not_found: bool) -> anyhow::Result<Pages> {
+    let Some(checkpoint) = read_checkpoint(path)? else {
+        return restore_snapshot_from(path, allow_not_found);
+    };
+    // A crawl was interrupted, so the pages journaled since then are loaded as well
+    info!("Loading the pages up to the checkpoint");
+    stream_data_with(path, &checkpoint)
+}
+fn restore_snapshot_from(path: &Path, allow_not_found: bool) -> anyhow::Result<Pages> {
+    let path = &saved_path(path);
+    let backup = path_with_suffix(path, ".bak");
+    let not_found = |e: &anyhow::Error| {
+        e.downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    };
+    match open_pages(path) {
+        Ok(pages) => Ok(pages),
+        // The process may have died between the two renames in `write_atomically`
+        Err(e) if not_found(&e) && backup.exists() => {
+            warn!("{path:?} not found.  Falling back to {backup:?}");
+            open_pages(&backup)
+        }
+        Err(e) if not_found(&e) && allow_not_found => {
+            info!("Starting from scratch: {e} (not found)");
+            Ok(Box::new(std::iter::empty()))
+        }
+        // Other errors, such as a snapshot from a newer version, must not be papered over
+        Err(e) => Err(e.context(format!("Failed to load {path:?}"))),
+    }
+}
+/// Version of the JSON snapshot format, bumped whenever a change to it needs a migration.
+/// Fields added to [`File`] with a default do not need one.
+const SNAPSHOT_VERSION: u64 = 2;
+
+/// First line of a snapshot, followed by one page per line
+#[derive(Serialize)]
+struct SnapshotHeader<'a> {
+    version: u64,
+    /// Absent from snapshots saved before it was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crawl: Option<&'a Provenance>,
+}
+
+/// How and when the snapshot was crawled, so that reports can tell a partial or stale one
+#[derive(Clone, Serialize, Deserialize)]
+struct Provenance {
+    /// Email address of the account crawled, or `None` if it could not be fetched
+    account: Option<String>,
+    /// Query the files were listed with, or `None` for a crawl by folder
+    query: Option<String>,
+    started_at: DateTime<Utc>,
+    /// When the last page was fetched
+    finished_at: DateTime<Utc>,
+    /// When the changes since the crawl were last applied
+    #[serde(default)]
+    synced_at: Option<DateTime<Utc>>,
+    tool_version: String,
+    pages: usize,
+    /// Whether every page has been fetched, which is not the case while a crawl is interrupted
+    complete: bool,
+}
+impl Provenance {
+    /// When the snapshot last caught up with Drive
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.synced_at.unwrap_or(self.finished_at)
+    }
+}
+
+/// Snapshots not crawled or synced for this many days are warned about
+const STALE_AFTER_DAYS: i64 = 30;
+
+/// The provenance of the snapshot at `path`, taken from the checkpoint if its crawl was
+/// interrupted, or `None` if it was saved before the provenance was recorded
+fn restore_provenance(path: &Path) -> anyhow::Result<Option<Provenance>> {
+    if let Some(checkpoint) = read_checkpoint(path)? {
+        return Ok(Some(checkpoint.provenance()));
+    }
+    let saved = saved_path(path);
+    if !saved.exists() {
+        return Ok(None);
+    }
+    read_provenance(&saved)
+}
+/// The provenance in the header of the snapshot saved at `path`
+fn read_provenance(path: &Path) -> anyhow::Result<Option<Provenance>> {
+    let mut reader = decompress(BufReader::new(fs_err::File::open(path)?))?;
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let header: serde_json::Value = serde_json::from_str(&header)?;
+    match header.get("crawl") {
+        Some(crawl) => Ok(Some(serde_json::from_value(crawl.clone())?)),
+        None => Ok(None),
+    }
+}
+/// Warns if the reports on the snapshot at `path` are based on an interrupted or old crawl.
+fn warn_if_unreliable(path: &Path) -> anyhow::Result<()> {
+    let Some(crawl) = restore_provenance(path)? else {
+        return Ok(());
+    };
+    if !crawl.complete {
+        warn!(
+            "The snapshot is based on a partial crawl of {} pages, interrupted at {}.  \
+            Resume the crawl to complete it.",
+            crawl.pages, crawl.finished_at
+        );
+    }
+    let days = (Utc::now() - crawl.updated_at()).num_days();
+    if days >= STALE_AFTER_DAYS {
+        warn!("The snapshot has not been crawled or synced for {days} days.  Sync it first.");
+    }
+    Ok(())
+}
+
+/// Pages read one at a time, so that the whole snapshot need not be in memory at once
+type Pages = Box<dyn Iterator<Item = anyhow::Result<FileList>>>;
+
+/// First bytes of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// The content of a snapshot, decompressed if it starts with a zstd frame
+fn decompress(mut reader: impl BufRead + 'static) -> anyhow::Result<Box<dyn BufRead>> {
+    Ok(if reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Box::new(reader)
+    })
+}
+
+fn open_pages(path: &Path) -> anyhow::Result<Pages> {
+    parse_pages(BufReader::new(fs_err::File::open(path)?))
+}
+
+/// Parses a snapshot saved by this or an older version, migrating it to the current format.
+/// Compression is detected from the content, so that backups are read regardless of the name.
+fn parse_pages(reader: impl BufRead + 'static) -> anyhow::Result<Pages> {
+    let mut reader = decompress(reader)?;
+    // Snapshots before version 2 are a single JSON value, which this reads in its entirety
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let mut value: serde_json::Value = serde_json::from_str(&header)?;
+    // Snapshots saved before versioning are bare arrays of pages
+    if value.is_array() {
+        value = json!({ "version": 0, "pages": value });
+    }
+    let version = value["version"]
+        .as_u64()
+        .context("The snapshot has no version")?;
+    if version > SNAPSHOT_VERSION {
+        bail!(
+            "The snapshot is in version {version}, while this binary supports up to \
+            {SNAPSHOT_VERSION}.  Update drive-crawl instead of dropping what it does not know."
+        );
+    }
+    if version == SNAPSHOT_VERSION {
+        let pages = reader
+            .lines()
+            .filter_map(|line| -> Option<anyhow::Result<FileList>> {
+                match line {
+                    Ok(line) if line.is_empty() => None,
+                    Ok(line) => Some(serde_json::from_str(&line).map_err(Into::into)),
+                    Err(e) => Some(Err(e.into())),
+                }
+            });
+        return Ok(Box::new(pages));
+    }
+    for version in version..SNAPSHOT_VERSION {
+        migrate_data(&mut value, version);
+        info!("Migrated the snapshot from version {version}");
+    }
+    let pages: Vec<FileList> = serde_json::from_value(value["pages"].take())?;
+    Ok(Box::new(pages.into_iter().map(Ok)))
+}
+
+/// Converts the snapshot in `version` into `version + 1`.
+fn migrate_data(value: &mut serde_json::Value, version: u64) {
+    match version {
+        // Only wrapped into the versioned object above
+        0 => {}
+        // Only the layout changed to a page per line, which `parse_pages` takes care of
+        1 => {}
+        _ => unreachable!("no migration from version {version}"),
+    }
+    value["version"] = (version + 1).into();
+}
+
+/// Saves a complete snapshot of my files and archives it.
+fn save_data(store: &Store, list: &[FileList], crawl: Option<&Provenance>) -> anyhow::Result<()> {
+    save_data_to(&store.file_list_path(), list, crawl, store.compress_level)?;
+    history::archive(store)
+}
+fn save_data_to(
+    path: &Path,
+    list: &[FileList],
+    crawl: Option<&Provenance>,
+    compress_level: i32,
+) -> anyhow::Result<()> {
+    save_pages_to(path, crawl, list.iter().map(anyhow::Ok), compress_level)
+}
+/// Saves the snapshot to the path, or to the path with `.zst` appended if compressed, writing
+/// the pages one by one as they are read.
+fn save_pages_to<P: Serialize>(
+    path: &Path,
+    crawl: Option<&Provenance>,
+    pages: impl IntoIterator<Item = anyhow::Result<P>>,
+    compress_level: i32,
+) -> anyhow::Result<()> {
+    (|| {
+        let compressed = path_with_suffix(path, ".zst");
+        let (path, other) = if compress_level > 0 {
+            (compressed.as_path(), path)
+        } else {
+            (path, compressed.as_path())
+        };
+        // Switching the compression turns the previous snapshot into the backup of the new one
+        if !path.exists() && other.exists() {
+            fs_err::rename(other, path)?;
+        }
+        write_atomically(path, |writer| {
+            if compress_level > 0 {
+                let mut encoder = zstd::Encoder::new(writer, compress_level)?;
+                write_pages(&mut encoder, crawl, pages)?;
+                encoder.finish()?;
+            } else {
+                write_pages(writer, crawl, pages)?;
+            }
+            Ok(())
+        })?;
+        info!("Saved list to {path:?}");
+        anyhow::Ok(())
+    })()
+    .context(
+        "Unfortunately, we failed to save data and the accumulated data was permanently losed.",
+    )
+}
+fn write_pages<P: Serialize>(
+    mut writer: impl Write,
+    crawl: Option<&Provenance>,
+    pages: impl IntoIterator<Item = anyhow::Result<P>>,
+) -> anyhow::Result<()> {
+    let header = SnapshotHeader {
+        version: SNAPSHOT_VERSION,
+        crawl,
+    };
+    serde_json::to_writer(&mut writer, &header)?;
+    for page in pages {
+        writer.write_all(b"\n")?;
+        serde_json::to_writer(&mut writer, &page?)?;
+    }
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Writes the file through a temporary file and renames it over the target, keeping the previous
+/// version as `.bak`, so that a crash in the middle never leaves only a truncated file behind.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<fs_err::File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let tmp = path_with_suffix(path, ".tmp");
+    let mut writer = BufWriter::new(fs_err::File::create(&tmp)?);
+    write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    if path.exists() {
+        fs_err::rename(path, path_with_suffix(path, ".bak"))?;
+    }
+    fs_err::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Destination of crawled pages, which also remembers where to resume from
+trait PageStore {
+    /// Token of the next page to fetch (empty for the first page), or `None` if complete
+    fn next_page_token(&self) -> Option<&str>;
+    fn page_count(&self) -> usize;
+    /// Quota used by the files fetched so far
+    fn crawled_bytes(&self) -> anyhow::Result<u64>;
+    fn push(&mut self, page: FileList) -> anyhow::Result<()>;
+    fn save(&mut self) -> anyhow::Result<()>;
+}
+
+/// Cursor of an interrupted crawl, kept next to the snapshot and rewritten after every page.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    query: String,
+    /// Absent from checkpoints saved before it was recorded
+    #[serde(default)]
+    account: Option<String>,
+    started_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// Number of pages of the snapshot the journal continues from
+    snapshot_pages: usize,
+    /// Number of pages in the journal, which may have a partial line after them
+    journal_pages: usize,
+    next_page_token: Option<String>,
+}
+fn checkpoint_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".checkpoint.json")
+}
+fn journal_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".pages.jsonl")
+}
+fn read_checkpoint(path: &Path) -> anyhow::Result<Option<Checkpoint>> {
+    let path = checkpoint_path(path);
+    let backup = path_with_suffix(&path, ".bak");
+    // The process may have died between the two renames in `write_atomically`
+    for path in [path, backup] {
+        match fs_err::read(&path) {
+            Ok(data) => {
+                let checkpoint = serde_json::from_slice(&data)
+                    .with_context(|| format!("Failed to parse {path:?}"))?;
+                return Ok(Some(checkpoint));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => Err(e)?,
+        }
+    }
+    Ok(None)
+}
+impl Checkpoint {
+    fn provenance(&self) -> Provenance {
+        Provenance {
+            account: self.account.clone(),
+            query: Some(self.query.clone()),
+            started_at: self.started_at,
+            finished_at: self.updated_at,
+            synced_at: None,
+            tool_version: env!("CARGO_PKG_VERSION").into(),
+            pages: self.snapshot_pages + self.journal_pages,
+            complete: self.next_page_token.is_none(),
+        }
+    }
+}
+/// Pages of the snapshot the journal continues from, followed by the journaled ones
+fn stream_data_with(path: &Path, checkpoint: &Checkpoint) -> anyhow::Result<Pages> {
+    let snapshot: Pages = match checkpoint.snapshot_pages {
+        0 => Box::new(std::iter::empty()),
+        pages => Box::new(open_pages(&saved_path(path))?.take(pages)),
+    };
+    let path = journal_path(path);
+    let pages = checkpoint.journal_pages;
+    let mut lines = BufReader::new(fs_err::File::open(&path)?).lines();
+    let journal = (0..pages).map(move |i| -> anyhow::Result<FileList> {
+        let line = lines
+            .next()
+            .with_context(|| format!("{path:?} has only {i} of {pages} pages"))??;
+        Ok(serde_json::from_str(&line)?)
+    });
+    Ok(Box::new(snapshot.chain(journal)))
+}
+
+/// Pages of a JSON snapshot.  Pages are appended to a journal as they arrive, and the whole
+/// snapshot is only rewritten once the crawl is complete, so that only the cursor is kept in
+/// memory.
+struct JsonPages {
+    path: PathBuf,
+    compress_level: i32,
+    query: String,
+    /// Email address of the account being crawled, if known
+    account: Option<String>,
+    started_at: DateTime<Utc>,
+    snapshot_pages: usize,
+    journal_pages: usize,
+    next_page_token: Option<String>,
+    crawled_bytes: u64,
+    /// Opened on the first page pushed in this run
+    journal: Option<BufWriter<fs_err::File>>,
+}
+impl JsonPages {
+    fn open(
+        path: PathBuf,
+        compress_level: i32,
+        query: &str,
+        account: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let checkpoint = read_checkpoint(&path)?;
+        let pages: Pages = match checkpoint {
+            Some(ref checkpoint) if checkpoint.query != query => bail!(
+                "The interrupted crawl in {path:?} was for {:?}, not {query:?}",
+                checkpoint.query
+            ),
+            Some(ref checkpoint) => {
+                info!("Resuming from the checkpoint of {}", checkpoint.updated_at);
+                stream_data_with(&path, checkpoint)?
+            }
+            None => restore_snapshot_from(&path, true)?,
+        };
+        let mut page_count = 0;
+        let mut crawled_bytes = 0;
+        let mut next_page_token = Some(String::new());
+        for page in pages {
+            let page = page?;
+            page_count += 1;
+            crawled_bytes += page.crawled_bytes();
+            next_page_token = page.next_page_token;
+        }
+        let (started_at, snapshot_pages) = match checkpoint {
+            Some(ref checkpoint) => (checkpoint.started_at, checkpoint.snapshot_pages),
+            None => (Utc::now(), page_count),
+        };
+        Ok(Self {
+            path,
+            compress_level,
+            query: query.to_owned(),
+            account,
+            started_at,
+            snapshot_pages,
+            journal_pages: page_count - snapshot_pages,
+            next_page_token,
+            crawled_bytes,
+            journal: None,
+        })
+    }
+    /// Discards the pages crawled so far, once the crawl is under way again.
+    fn restart(&mut self) {
+        self.started_at = Utc::now();
+        self.snapshot_pages = 0;
+        self.journal_pages = 0;
+        self.next_page_token = Some(String::new());
+        self.crawled_bytes = 0;
+        self.journal = None;
+    }
+    /// Opens the journal for appending, dropping any partial line after the journaled pages.
+    fn open_journal(&self) -> anyhow::Result<BufWriter<fs_err::File>> {
+        let path = journal_path(&self.path);
+        let mut len = 0;
+        if self.journal_pages > 0 {
+            let mut reader = BufReader::new(fs_err::File::open(&path)?);
+            let mut line = vec![];
+            for i in 0..self.journal_pages {
+                line.clear();
+                reader.read_until(b'\n', &mut line)?;
+                if !line.ends_with(b"\n") {
+                    bail!("{path:?} has only {i} of {} pages", self.journal_pages);
+                }
+                len += line.len() as u64;
+            }
+        }
+        let mut file = fs_err::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)?;
+        file.set_len(len)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(BufWriter::new(file))
+    }
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            query: self.query.clone(),
+            account: self.account.clone(),
+            started_at: self.started_at,
+            updated_at: Utc::now(),
+            snapshot_pages: self.snapshot_pages,
+            journal_pages: self.journal_pages,
+            next_page_token: self.next_page_token.clone(),
+        }
+    }
+}
+impl FileList {
+    fn crawled_bytes(&self) -> u64 {
+        self.files.iter().filter_map(|f| f.quota_bytes_used).sum()
+    }
+}
+impl PageStore for JsonPages {
+    fn next_page_token(&self) -> Option<&str> {
+        self.next_page_token.as_deref()
+    }
+    fn page_count(&self) -> usize {
+        self.snapshot_pages + self.journal_pages
+    }
+    fn crawled_bytes(&self) -> anyhow::Result<u64> {
+        Ok(self.crawled_bytes)
+    }
+    fn push(&mut self, page: FileList) -> anyhow::Result<()> {
+        let journal = match self.journal {
+            Some(ref mut journal) => journal,
+            None => {
+                let journal = self.open_journal()?;
+                self.journal.insert(journal)
+            }
+        };
+        serde_json::to_writer(&mut *journal, &page)?;
+        journal.write_all(b"\n")?;
+        journal.flush()?;
+        self.journal_pages += 1;
+        self.crawled_bytes += page.crawled_bytes();
+        self.next_page_token = page.next_page_token;
+        let checkpoint = self.checkpoint();
+        write_atomically(&checkpoint_path(&self.path), |writer| {
+            serde_json::to_writer_pretty(writer, &checkpoint)?;
+            Ok(())
+        })
+    }
+    fn save(&mut self) -> anyhow::Result<()> {
+        let checkpoint_file = checkpoint_path(&self.path);
+        // The journal is flushed on every page, so the checkpoint is already up to date
+        if self.next_page_token.is_some() || !checkpoint_file.exists() {
+            return Ok(());
+        }
+        self.journal = None;
+        let checkpoint = self.checkpoint();
+        let crawl = Provenance {
+            finished_at: Utc::now(),
+            ..checkpoint.provenance()
+        };
+        let pages = stream_data_with(&self.path, &checkpoint)?;
+        save_pages_to(&self.path, Some(&crawl), pages, self.compress_level)?;
+        // The checkpoint goes first, as a journal without one is ignored
+        for path in [
+            path_with_suffix(&checkpoint_file, ".bak"),
+            checkpoint_file,
+            journal_path(&self.path),
+        ] {
+            if path.exists() {
+                fs_err::remove_file(path)?;
+            }
+        }
+        self.snapshot_pages += self.journal_pages;
+        self.journal_pages = 0;
+        Ok(())
+    }
+}
+
+/// Loads the files of the main snapshot and of all crawled shared drives.
+fn restore_files(store: &Store) -> anyhow::Result<Vec<File>> {
+    if let Some(ref path) = store.snapshot {
+        return collect_files(restore_data_from(path, false)?);
+    }
+    if store.kind == StoreKind::Sqlite {
+        return sqlite::load_files(&sqlite::open(&store.dir)?);
+    }
+    warn_if_unreliable(&store.file_list_path())?;
+    let mut files = collect_files(restore_data(store, false)?)?;
+    for shared_drive in restore_shared_drives(store)? {
+        let path = store.shared_drive_data_path(&shared_drive.id);
+        files.extend(collect_files(restore_data_from(&path, true)?)?);
+    }
+    Ok(files)
+}
+
+fn restore_shared_drives(store: &Store) -> anyhow::Result<Vec<SharedDrive>> {
+    Ok(match fs_err::File::open(store.shared_drives_path()) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => Err(e)?,
+    })
+}
+fn save_shared_drives(store: &Store, drives: &[SharedDrive]) -> anyhow::Result<()> {
+    let path = store.shared_drives_path();
+    write_atomically(&path, |writer| Ok(serde_json::to_writer(writer, drives)?))?;
+    info!("Saved shared drive list to {path:?}");
+    Ok(())
+}
+
+fn restore_start_page_token(store: &Store) -> anyhow::Result<String> {
+    let path = store.start_page_token_path();
+    let token = fs_err::read_to_string(path)
+        .context("No start page token found.  Run a full crawl from scratch first.")?;
+    Ok(token.trim().to_owned())
+}
+fn save_start_page_token(store: &Store, token: &str) -> anyhow::Result<()> {
+    let path = store.start_page_token_path();
+    fs_err::write(&path, token)?;
+    info!("Saved start page token to {path:?}");
+    Ok(())
+}
+
+/// The query the snapshot of my files was crawled with, or `None` if it was crawled by folder
+pub fn restore_crawl_query(store: &Store) -> anyhow::Result<Option<String>> {
+    match fs_err::read_to_string(store.crawl_query_path()) {
+        Ok(query) => Ok(Some(query).filter(|q| !q.is_empty())),
+        // Snapshots from before the query was recorded
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Some(DEFAULT_QUERY.into())),
+        Err(e) => Err(e)?,
+    }
+}
+/// Records the query, where an empty one stands for a crawl by folder since a missing file
+/// means the default query.
+fn save_crawl_query(store: &Store, query: &str) -> anyhow::Result<()> {
+    fs_err::write(store.crawl_query_path(), query)?;
+    Ok(())
+}
+
+/// The extra fields the snapshot of my files was crawled with
+fn restore_crawl_fields(store: &Store) -> anyhow::Result<Vec<String>> {
+    match fs_err::read_to_string(store.crawl_fields_path()) {
+        Ok(fields) => Ok(fields
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_owned())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e)?,
+    }
+}
+/// Records the extra fields so that syncing keeps them up to date.
+fn save_crawl_fields(store: &Store, fields: &[String]) -> anyhow::Result<()> {
+    fs_err::write(store.crawl_fields_path(), fields.join(","))?;
+    Ok(())
+}
+
+/// The spaces the snapshot of my files was crawled in
+fn restore_crawl_spaces(store: &Store) -> anyhow::Result<Vec<Space>> {
+    match fs_err::read_to_string(store.crawl_spaces_path()) {
+        Ok(spaces) => spaces
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| Space::from_name(s).with_context(|| format!("Unknown space {s:?}")))
+            .collect(),
+        // Snapshots from before the spaces were recorded
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![Space::Drive]),
+        Err(e) => Err(e)?,
+    }
+}
+/// Records the spaces so that syncing keeps following them.
+fn save_crawl_spaces(store: &Store, list: &ListOptions) -> anyhow::Result<()> {
+    fs_err::write(store.crawl_spaces_path(), list.spaces())?;
+    Ok(())
+}
+
+/// The ids of the labels the snapshot of my files was crawled with
+fn restore_crawl_labels(store: &Store) -> anyhow::Result<Vec<String>> {
+    match fs_err::read_to_string(store.crawl_labels_path()) {
+        Ok(labels) => Ok(labels
+            .split(',')
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_owned())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e)?,
+    }
+}
+/// Records the labels so that syncing keeps them up to date.
+fn save_crawl_labels(store: &Store, list: &ListOptions) -> anyhow::Result<()> {
+    fs_err::write(store.crawl_labels_path(), list.labels.join(","))?;
+    Ok(())
+}
+
+/// The items shared with me, or an empty list if they have not been crawled
+pub(crate) fn restore_shared_with_me(store: &Store) -> anyhow::Result<Vec<File>> {
+    collect_files(restore_data_from(&store.shared_with_me_path(), true)?)
+}
+
+/// Items shared with me directly, as opposed to those in the folders shared with me
+const SHARED_WITH_ME_QUERY: &str = "sharedWithMe = true and trashed = false";
+
+async fn list_shared_with_me(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    list: &ListOptions,
+) -> anyhow::Result<()> {
+    let mut pages = JsonPages::open(
+        store.shared_with_me_path(),
+        store.compress_level,
+        SHARED_WITH_ME_QUERY,
+        fetch_account(drive, retry).await,
+    )?;
+    if pages.next_page_token().is_none() {
+        info!("Starting over since the previous crawl was complete");
+        pages.restart();
+    }
+    let list = ListOptions {
+        extra_fields: [
+            &["sharingUser(emailAddress)".to_owned()],
+            &list.extra_fields[..],
+        ]
+        .concat(),
+        ..list.clone()
+    };
+    crawl(
+        drive,
+        cancel,
+        retry,
+        Corpus::User(SHARED_WITH_ME_QUERY),
+        &mut pages,
+        &list,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Files crawled unless another query is given
+const DEFAULT_QUERY: &str = "'me' in owners";
+
+enum Corpus<'a> {
+    /// My Drive, limited to the files matching the query
+    User(&'a str),
+    SharedDrive(&'a str),
+}
+
+async fn list_files(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    query: &str,
+    list: &ListOptions,
+) -> anyhow::Result<()> {
+    let mut conn;
+    let mut pages: Box<dyn PageStore> = match store.kind {
+        StoreKind::Json => Box::new(JsonPages::open(
+            store.file_list_path(),
+            store.compress_level,
+            query,
+            fetch_account(drive, retry).await,
+        )?),
+        StoreKind::Sqlite => {
+            conn = sqlite::open(&store.dir)?;
+            Box::new(sqlite::SqlitePages::open(&mut conn, "user")?)
+        }
+    };
+    if pages.next_page_token() == Some("") {
+        // Obtained before the first page so that changes made during the crawl are not missed
+        fetch_start_page_token(drive, store, retry).await?;
+        save_crawl_query(store, query)?;
+        save_crawl_fields(store, &list.extra_fields)?;
+        save_crawl_spaces(store, list)?;
+        save_crawl_labels(store, list)?;
+    } else if pages.next_page_token().is_some() {
+        let previous = restore_crawl_query(store)?;
+        if previous.as_deref() != Some(query) {
+            bail!(
+                "The crawl being resumed was started with the query {:?}.  \
+                Resume it with the same query or delete the snapshot to start over.",
+                previous.unwrap_or_default()
+            );
+        }
+        let previous = restore_crawl_fields(store)?;
+        if previous != list.extra_fields {
+            bail!(
+                "The crawl being resumed was started with the fields {:?}.  \
+                Resume it with the same fields or delete the snapshot to start over.",
+                previous.join(",")
+            );
+        }
+        let previous = restore_crawl_spaces(store)?;
+        if previous != list.spaces {
+            bail!(
+                "The crawl being resumed was started in the spaces {:?}.  \
+                Resume it with the same spaces or delete the snapshot to start over.",
+                previous
+                    .iter()
+                    .map(|s| s.name())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+        }
+        let previous = restore_crawl_labels(store)?;
+        if previous != list.labels {
+            bail!(
+                "The crawl being resumed was started with the labels {:?}.  \
+                Resume it with the same labels or delete the snapshot to start over.",
+                previous.join(",")
+            );
+        }
+    }
+    let resumed = pages.next_page_token().is_some();
+    let corpus = Corpus::User(query);
+    let complete = crawl(drive, cancel, retry, corpus, &mut *pages, list).await?;
+    if resumed && complete && store.kind == StoreKind::Json {
+        history::archive(store)?;
+    }
+    Ok(())
+}
+
+/// Replaces the snapshot of my files with a complete one consisting of `files`.
+fn save_complete_snapshot(
+    store: &Store,
+    files: Vec<File>,
+    crawl: &Provenance,
+) -> anyhow::Result<()> {
+    match store.kind {
+        StoreKind::Json => save_data(
+            store,
+            &[FileList {
+                files,
+                next_page_token: None,
+            }],
+            Some(crawl),
+        ),
+        StoreKind::Sqlite => sqlite::replace_user_files(&mut sqlite::open(&store.dir)?, &files),
+    }
+}
+
+async fn crawl_folder(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    folder_id: &str,
+    list: &ListOptions,
+) -> anyhow::Result<()> {
+    let started_at = Utc::now();
+    // Obtained before the crawl so that changes made during the crawl are not missed
+    fetch_start_page_token(drive, store, retry).await?;
+    let files = fetch_subtree(drive, cancel, retry, folder_id, list).await?;
+    info!("Crawled {} files", files.len());
+    let crawl = Provenance {
+        account: fetch_account(drive, retry).await,
+        query: None,
+        started_at,
+        finished_at: Utc::now(),
+        synced_at: None,
+        tool_version: env!("CARGO_PKG_VERSION").into(),
+        pages: 1,
+        complete: true,
+    };
+    save_complete_snapshot(store, files, &crawl)?;
+    save_crawl_query(store, "")?;
+    save_crawl_fields(store, &list.extra_fields)?;
+    save_crawl_spaces(store, list)?;
+    save_crawl_labels(store, list)
+}
+
+async fn fetch_start_page_token(
+    drive: &Drive,
+    store: &Store,
+    retry: &RetryPolicy,
+) -> anyhow::Result<()> {
+    let (_, res) = retry
+        .run(|| drive.changes().get_start_page_token().doit())
+        .await?;
+    let token = res
+        .start_page_token
+        .context("The API did not return a start page token")?;
+    save_start_page_token(store, &token)
+}
+
+async fn list_shared_drives(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    list: &ListOptions,
+) -> anyhow::Result<()> {
+    let mut shared_drives = vec![];
+    let mut token = None::<String>;
+    loop {
+        let (_, res) = retry
+            .run(|| {
+                let mut call = drive
+                    .drives()
+                    .list()
+                    .page_size(100)
+                    .param("fields", "nextPageToken,drives(id,name)");
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await?;
+        for shared_drive in res.drives.unwrap_or_default() {
+            let (Some(id), Some(name)) = (shared_drive.id, shared_drive.name) else {
+                continue;
+            };
+            shared_drives.push(SharedDrive { id, name });
+        }
+        token = res.next_page_token;
+        if token.is_none() {
+            break;
+        }
+    }
+    info!("Found {} shared drives", shared_drives.len());
+    save_shared_drives(store, &shared_drives)?;
+
+    let mut conn = match store.kind {
+        StoreKind::Json => {
+            fs_err::create_dir_all(store.dir.join("drives"))?;
+            None
+        }
+        StoreKind::Sqlite => Some(sqlite::open(&store.dir)?),
+    };
+    let account = match conn {
+        None => fetch_account(drive, retry).await,
+        Some(_) => None,
+    };
+    for shared_drive in &shared_drives {
+        info!(
+            "Crawling shared drive {:?} ({})",
+            shared_drive.name, shared_drive.id
+        );
+        let mut pages: Box<dyn PageStore> = match conn {
+            None => Box::new(JsonPages::open(
+                store.shared_drive_data_path(&shared_drive.id),
+                store.compress_level,
+                &format!("drive:{}", shared_drive.id),
+                account.clone(),
+            )?),
+            Some(ref mut conn) => Box::new(sqlite::SqlitePages::open(
+                conn,
+                &format!("drive:{}", shared_drive.id),
+            )?),
+        };
+        let corpus = Corpus::SharedDrive(&shared_drive.id);
+        if !crawl(drive, cancel, retry, corpus, &mut *pages, list).await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Email address of the account crawled, or `None` if unavailable
+async fn fetch_account(drive: &Drive, retry: &RetryPolicy) -> Option<String> {
+    let res = retry
+        .run(|| {
+            drive
+                .about()
+                .get()
+                .param("fields", "user(emailAddress)")
+                .doit()
+        })
+        .await;
+    match res {
+        Ok((_, about)) => about.user?.email_address,
+        Err(e) => {
+            warn!("Failed to fetch the account to record in the snapshot: {e}");
+            None
+        }
+    }
+}
+
+/// Usage of Drive reported by the API, including the trash, or `None` if unavailable
+async fn fetch_usage_in_drive(drive: &Drive, retry: &RetryPolicy) -> Option<u64> {
+    let res = retry
+        .run(|| drive.about().get().param("fields", "storageQuota").doit())
+        .await;
+    match res {
+        Ok((_, about)) => about.storage_quota?.usage_in_drive.map(|x| x as u64),
+        Err(e) => {
+            warn!("Failed to fetch the usage to estimate the progress: {e}");
+            None
+        }
+    }
+}
+
+/// Crawls the corpus page by page, resuming from where `pages` left off.
+/// Returns whether the crawl has been completed.
+async fn crawl(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    corpus: Corpus<'_>,
+    pages: &mut dyn PageStore,
+    list: &ListOptions,
+) -> anyhow::Result<bool> {
+    // Shared drives do not count towards the usage of the user, and other queries may cover only
+    // part of it
+    let total_bytes = match corpus {
+        Corpus::User(DEFAULT_QUERY) if pages.next_page_token().is_some() => {
+            fetch_usage_in_drive(drive, retry).await
+        }
+        _ => None,
+    };
+    let mut progress = CrawlProgress::new(total_bytes, pages.crawled_bytes()?);
+    let complete = crawl_pages(drive, cancel, retry, corpus, pages, list, &mut progress).await;
+    progress.finish();
+    complete
+}
+
+/// Crawls the files matching the query through `api` into the JSON snapshot at `path`, resuming
+/// the previous crawl if any.  Unlike [`Crawler::crawl`], it records nothing needed for syncing,
+/// which makes it suitable for crawling a `drive_api::MockDrive`.  Returns whether the crawl
+/// has been completed.
+pub async fn crawl_snapshot(
+    api: &impl DriveApi,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    path: &Path,
+    query: &str,
+    list: &ListOptions,
+) -> Result<bool> {
+    let mut pages = JsonPages::open(path.to_owned(), 0, query, None)?;
+    let mut progress = CrawlProgress::new(None, pages.crawled_bytes()?);
+    let complete = crawl_pages(
+        api,
+        cancel,
+        retry,
+        Corpus::User(query),
+        &mut pages,
+        list,
+        &mut progress,
+    )
+    .await;
+    progress.finish();
+    Ok(complete?)
+}
+
+/// The files in the JSON snapshot at `path`, including those of an unfinished crawl
+pub fn load_snapshot(path: &Path) -> Result<Vec<File>> {
+    Ok(collect_files(restore_data_from(path, false)?)?)
+}
+
+async fn crawl_pages(
+    api: &impl DriveApi,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    corpus: Corpus<'_>,
+    pages: &mut dyn PageStore,
+    list: &ListOptions,
+    progress: &mut CrawlProgress,
+) -> anyhow::Result<bool> {
+    let base = ListRequest {
+        page_size: list.page_size,
+        fields: format!("nextPageToken,files({})", list.fields()),
+        include_labels: list.include_labels(),
+        ..Default::default()
+    };
+    let base = match corpus {
+        Corpus::User(query) => ListRequest {
+            // Includes all owned files plus shared roots (not shared children)?
+            corpora: Some("user".into()), // "user" by default, but setting it explicitly
+            q: Some(query.into()),
+            spaces: Some(list.spaces()),
+            scopes: list.scopes(),
+            ..base
+        },
+        Corpus::SharedDrive(id) => ListRequest {
+            corpora: Some("drive".into()),
+            drive_id: Some(id.into()),
+            all_drives: true,
+            ..base
+        },
+    };
+    loop {
+        let Some(token) = pages.next_page_token() else {
+            pages.save()?;
+            info!("Complete after {} pages.", pages.page_count());
+            return Ok(true);
+        };
+        let request = ListRequest {
+            page_token: Some(token.into()),
+            ..base.clone()
+        };
+        let res = retry.run(|| api.list_files(&request)).await;
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Aborting due to an API error: {e}");
+                pages.save()?;
+                return Ok(false);
+            }
+        };
+        let Ok(res) = FileList::try_from(res) else {
+            error!("Aborting due to a conversion error.");
+            pages.save()?;
+            return Ok(false);
+        };
+        progress.page(pages.page_count() + 1, &res.files);
+        pages.push(res)?;
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Saving before terminating.");
+            pages.save()?;
+            return Ok(false);
+        }
+    }
+}
+
+/// Fetches the folder and all of its descendants from the API.
+async fn fetch_subtree(
+    api: &impl DriveApi,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    folder_id: &str,
+    list: &ListOptions,
+) -> anyhow::Result<Vec<File>> {
+    let fields = list.fields();
+    let root = retry.run(|| api.get_file(folder_id, &fields)).await?;
+    let mut files = vec![File::try_from(root)?];
+    let mut que = VecDeque::from([folder_id.to_owned()]);
+    while let Some(parent) = que.pop_front() {
+        if cancel.is_cancelled() {
+            bail!("Cancelled before fetching the whole subtree");
+        }
+        info!(
+            "Listing children of {parent} ({} files so far)",
+            files.len()
+        );
+        let mut request = ListRequest {
+            q: Some(format!("'{parent}' in parents")),
+            all_drives: true,
+            page_size: list.page_size,
+            fields: format!("nextPageToken,files({fields})"),
+            include_labels: list.include_labels(),
+            ..Default::default()
+        };
+        loop {
+            let res = retry.run(|| api.list_files(&request)).await?;
+            let res = FileList::try_from(res)?;
+            for file in res.files {
+                if file.mime_type == "application/vnd.google-apps.folder" {
+                    que.push_back(file.id.clone());
+                }
+                files.push(file);
+            }
+            request.page_token = res.next_page_token;
+            if request.page_token.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the crawl of my files has been completed
+fn is_complete(store: &Store) -> anyhow::Result<bool> {
+    Ok(match store.kind {
+        // Only the header is read unless the snapshot predates the provenance
+        StoreKind::Json => match restore_provenance(&store.file_list_path())? {
+            Some(crawl) => crawl.complete,
+            None => {
+                let mut complete = false;
+                for page in restore_data(store, false)? {
+                    complete = page?.next_page_token.is_none();
+                }
+                complete
+            }
+        },
+        StoreKind::Sqlite => sqlite::is_complete(&sqlite::open(&store.dir)?, "user")?,
+    })
+}
+
+/// Refuses to analyze a partial snapshot of my files, whose totals would be silently wrong, or
+/// only warns if `allow_partial` is set.  Either way, shows how much of the usage it covers.
+pub(crate) fn ensure_complete(store: &Store, allow_partial: bool) -> anyhow::Result<()> {
+    // Archived snapshots are only taken of complete crawls
+    if store.snapshot.is_some() || is_complete(store)? {
+        return Ok(());
+    }
+    let crawled: u64 = restore_files(store)?
+        .iter()
+        .filter(|f| f.drive_id.is_none())
+        .filter_map(|f| f.quota_bytes_used)
+        .sum();
+    let usage = restore_quota(store)?.and_then(|q| q.usage_in_drive);
+    let coverage = match usage {
+        Some(usage) if usage > 0 => format!(
+            "{} of the {} used in Drive ({:.1}%)",
+            format_size(crawled),
+            format_size(usage),
+            crawled as f64 * 100.0 / usage as f64
+        ),
+        _ => format!(
+            "{} so far, of a usage unknown until `quota` is run",
+            format_size(crawled)
+        ),
+    };
+    if !allow_partial {
+        bail!(
+            "The snapshot is incomplete, covering {coverage}.  Finish the crawl first, or pass \
+            --allow-partial to analyze it anyway."
+        );
+    }
+    warn!("The snapshot is incomplete, covering {coverage}.  The totals below are too small.");
+    Ok(())
+}
+
+async fn sync_files(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+) -> anyhow::Result<()> {
+    if !is_complete(store)? {
+        return Err(Error::Incomplete.into());
+    }
+    match restore_crawl_query(store)?.as_deref() {
+        Some(DEFAULT_QUERY) => {}
+        // Telling which changes are inside the folder would take fetching its subtree again
+        None => bail!(
+            "The snapshot covers only a folder, to which the changes cannot be limited.  Crawl \
+            the folder again instead."
+        ),
+        // Search queries can only be evaluated by Drive
+        Some(query) => bail!(
+            "The snapshot covers only the files matching {query:?}, to which the changes cannot \
+            be limited.  Crawl again instead."
+        ),
+    }
+    let mut token = restore_start_page_token(store)?;
+    let list = ListOptions {
+        extra_fields: restore_crawl_fields(store)?,
+        spaces: restore_crawl_spaces(store)?,
+        labels: restore_crawl_labels(store)?,
+        ..Default::default()
+    };
+    let fields = list.fields();
+    let spaces = list.spaces();
+    let include_labels = list.include_labels();
+
+    // `None` means the file has been removed (or is no longer owned by me)
+    let mut changes = HashMap::<String, Option<File>>::new();
+    let mut times = HashMap::<String, DateTime<Utc>>::new();
+    loop {
+        info!("Fetching changes ({} so far)", changes.len());
+        let res = retry
+            .run(|| {
+                let mut call = drive
+                    .changes()
+                    .list(&token)
+                    .page_size(list.page_size)
+                    .spaces(&spaces)
+                    .add_scopes(list.scopes())
+                    .param(
+                        "fields",
+                        &format!(
+                            "nextPageToken,newStartPageToken,changes(removed,fileId,time,file(ownedByMe,{fields}))"
+                        ),
+                    );
+                if let Some(ref labels) = include_labels {
+                    call = call.include_labels(labels);
+                }
+                call.doit()
+            })
+            .await;
+        let res = match res {
+            Ok((_, res)) => res,
+            Err(e) => {
+                error!("Aborting due to an API error: {e}");
+                break;
+            }
+        };
+        for change in res.changes.unwrap_or_default() {
+            let Some(id) = change.file_id else { continue };
+            if let Some(time) = change.time {
+                times.insert(id.clone(), time);
+            }
+            let file = match change.file {
+                Some(file) if change.removed != Some(true) && file.owned_by_me == Some(true) => {
+                    // Otherwise it would be kept as an extra field
+                    let file = File::try_from(google_drive3::api::File {
+                        owned_by_me: None,
+                        ..file
+                    })?;
+                    info!("Changed: {} ({id})", file.name);
+                    Some(file)
+                }
+                _ => {
+                    info!("Removed: {id}");
+                    None
+                }
+            };
+            changes.insert(id, file);
+        }
+        match (res.next_page_token, res.new_start_page_token) {
+            (Some(next), _) => token = next,
+            (None, Some(new)) => {
+                token = new;
+                info!("Complete.");
+                break;
+            }
+            (None, None) => {
+                error!("The API returned neither a next page token nor a new start page token.");
+                break;
+            }
+        }
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Saving before terminating.");
+            break;
+        }
+    }
+
+    match store.kind {
+        // Saving would only archive yet another copy of the same snapshot
+        _ if changes.is_empty() => info!("No changes"),
+        StoreKind::Json => {
+            let before = load_changed_files(store, &changes)?;
+            append_change_feed(store, &changes, &before, &times)?;
+            apply_changes(store, &changes)?;
+        }
+        StoreKind::Sqlite => {
+            let mut conn = sqlite::open(&store.dir)?;
+            let before = sqlite::load_files_by_id(&conn, changes.keys().map(|id| id as &str))?;
+            append_change_feed(store, &changes, &before, &times)?;
+            let tx = conn.transaction()?;
+            let removed = sqlite::delete_files(
+                &tx,
+                changes
+                    .iter()
+                    .filter(|(_, file)| file.is_none())
+                    .map(|(id, _)| id as &str),
+            )?;
+            sqlite::upsert_files(&tx, changes.values().flatten())?;
+            tx.commit()?;
+            info!(
+                "Upserted {}, removed {removed} files",
+                changes.values().flatten().count()
+            );
+        }
+    }
+    save_start_page_token(store, &token)?;
+    Ok(())
+}
+
+/// The versions of the changed files in the JSON snapshot, before the changes are applied
+fn load_changed_files(
+    store: &Store,
+    changes: &HashMap<String, Option<File>>,
+) -> anyhow::Result<Vec<File>> {
+    let mut before = vec![];
+    for page in restore_data(store, false)? {
+        let files = page?.files.into_iter();
+        before.extend(files.filter(|file| changes.contains_key(&file.id)));
+    }
+    Ok(before)
+}
+
+/// Applies the changes to the JSON snapshot.
+fn apply_changes(store: &Store, changes: &HashMap<String, Option<File>>) -> anyhow::Result<()> {
+    let crawl = restore_provenance(&store.file_list_path())?.map(|crawl| Provenance {
+        synced_at: Some(Utc::now()),
+        ..crawl
+    });
+    let (mut added, mut modified, mut removed) = (0, 0, 0);
+    // Rewritten page by page as the snapshot is read, like a crawl being completed
+    let pages = restore_data(store, false)?.map(|page| {
+        let mut page = page?;
+        page.files.retain(|file| match changes.get(&file.id) {
+            None => true,
+            Some(change) => {
+                match change {
+                    None => removed += 1,
+                    Some(_) => modified += 1,
+                }
+                false
+            }
+        });
+        // The last page, since the snapshot is complete
+        if page.next_page_token.is_none() {
+            for file in changes.values().flatten() {
+                page.files.push(file.clone());
+                added += 1;
+            }
+        }
+        anyhow::Ok(page)
+    });
+    save_pages_to(
+        &store.file_list_path(),
+        crawl.as_ref(),
+        pages,
+        store.compress_level,
+    )?;
+    history::archive(store)?;
+    // Files that were modified were removed above and re-added
+    added -= modified;
+    info!("Added {added}, modified {modified}, removed {removed} files");
+    Ok(())
+}
+
+/// One line of `changes.jsonl`
+#[derive(Serialize)]
+struct ChangeRecord<'a> {
+    /// When Drive recorded the change, or when it was synced if unknown
+    time: DateTime<Utc>,
+    file_id: &'a str,
+    /// `added`, `modified` or `removed`
+    change: &'static str,
+    name: &'a str,
+    before_size: Option<u64>,
+    after_size: Option<u64>,
+}
+
+/// Appends the changes that touched the snapshot to `changes.jsonl`, for tools following the
+/// history of the Drive without calling the API themselves.  Written before the changes are
+/// applied, so that a sync interrupted in between repeats the records rather than losing them.
+fn append_change_feed(
+    store: &Store,
+    changes: &HashMap<String, Option<File>>,
+    before: &[File],
+    times: &HashMap<String, DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let before: HashMap<_, _> = before.iter().map(|f| (&f.id as &str, f)).collect();
+    let now = Utc::now();
+    let mut records: Vec<_> = changes
+        .iter()
+        .filter_map(|(id, after)| {
+            let old = before.get(id as &str).copied();
+            let (change, name) = match (old, after) {
+                (None, Some(new)) => ("added", &new.name),
+                (Some(_), Some(new)) => ("modified", &new.name),
+                (Some(old), None) => ("removed", &old.name),
+                // Not in the snapshot before or after, as for a file of someone else
+                (None, None) => return None,
+            };
+            Some(ChangeRecord {
+                time: times.get(id).copied().unwrap_or(now),
+                file_id: id,
+                change,
+                name,
+                before_size: old.and_then(|f| f.quota_bytes_used),
+                after_size: after.as_ref().and_then(|f| f.quota_bytes_used),
+            })
+        })
+        .collect();
+    records.sort_by(|x, y| x.time.cmp(&y.time).then_with(|| x.file_id.cmp(y.file_id)));
+    let mut writer = BufWriter::new(
+        fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(store.change_feed_path())?,
+    );
+    for record in &records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The space the file is counted in, which is the one other than `drive` if any since those
+/// files are hidden from the Drive UI
+fn space_of(spaces: &[String]) -> &str {
+    spaces
+        .iter()
+        .find(|s| *s != Space::Drive.name())
+        .map_or(Space::Drive.name(), |s| s)
+}
+
+/// Aggregates shown by `--show-overview`
+struct Overview {
+    total_quota_bytes: u64,
+    quota_bytes_per_drive: HashMap<Option<String>, u64>,
+    /// Keyed by the email address of the first owner
+    quota_bytes_per_owner: HashMap<Option<String>, u64>,
+    /// Keyed by [`space_of`] the file
+    quota_bytes_per_space: HashMap<String, u64>,
+    shortcuts: u64,
+    /// Shortcuts whose targets are missing from the snapshot
+    broken_shortcuts: u64,
+    /// Used by the targets of the shortcuts in the snapshot, each counted once.  Shortcuts use no
+    /// storage of their own, so this is only what they give access to.
+    shortcut_target_bytes: u64,
+    without_single_parent: Vec<File>,
+    with_foreign_parents: Vec<File>,
+}
+impl Overview {
+    fn from_files(files: Vec<File>) -> Self {
+        let total_quota_bytes = files.iter().filter_map(|f| f.quota_bytes_used).sum();
+        let mut quota_bytes_per_drive = HashMap::<_, u64>::new();
+        let mut quota_bytes_per_owner = HashMap::<_, u64>::new();
+        let mut quota_bytes_per_space = HashMap::<_, u64>::new();
+        for file in &files {
+            let bytes = file.quota_bytes_used.unwrap_or(0);
+            *quota_bytes_per_drive
+                .entry(file.drive_id.clone())
+                .or_default() += bytes;
+            let owner = file.owners.first().and_then(|o| o.email_address.clone());
+            *quota_bytes_per_owner.entry(owner).or_default() += bytes;
+            *quota_bytes_per_space
+                .entry(space_of(&file.spaces).to_owned())
+                .or_default() += bytes;
+        }
+        let ids: HashSet<&str> = files.iter().map(|f| &f.id as &str).collect();
+        let targets = files.iter().filter_map(|f| f.shortcut_details.as_ref());
+        let shortcuts = targets.clone().count() as u64;
+        let broken_shortcuts = targets
+            .clone()
+            .filter(|d| !ids.contains(&d.target_id as &str))
+            .count() as u64;
+        let target_ids: HashSet<&str> = targets.map(|d| &d.target_id as &str).collect();
+        let shortcut_target_bytes = files
+            .iter()
+            .filter(|f| target_ids.contains(&f.id as &str))
+            .filter_map(|f| f.quota_bytes_used)
+            .sum();
+        let with_foreign_parents = files
+            .iter()
+            .filter(|f| {
+                f.parents
+                    .iter()
+                    .any(|id| !ids.contains(id as &str) && Some(id) != f.drive_id.as_ref())
+                    && f.quota_bytes_used.unwrap_or(0) > 1024
+            })
+            .cloned()
+            .collect();
+        let without_single_parent = files.into_iter().filter(|f| f.parents.len() != 1).collect();
+        Self {
+            total_quota_bytes,
+            quota_bytes_per_drive,
+            quota_bytes_per_owner,
+            quota_bytes_per_space,
+            shortcuts,
+            broken_shortcuts,
+            shortcut_target_bytes,
+            without_single_parent,
+            with_foreign_parents,
+        }
+    }
+}
+
+impl Overview {
+    fn load(store: &Store, include_trashed: bool) -> anyhow::Result<Self> {
+        match store.kind {
+            StoreKind::Json => {
+                let mut files = restore_files(store)?;
+                if !include_trashed {
+                    files.retain(|f| !f.trashed);
+                }
+                // A folder crawled in a shared drive is in the snapshot of the drive as well
+                let mut seen = HashSet::new();
+                files.retain(|f| seen.insert(f.id.clone()));
+                Ok(Self::from_files(files))
+            }
+            StoreKind::Sqlite => sqlite::overview(&sqlite::open(&store.dir)?, include_trashed),
+        }
+    }
+}
+
+pub fn show_overview(
+    store: &Store,
+    owners: bool,
+    include_trashed: bool,
+    allow_partial: bool,
+) -> anyhow::Result<()> {
+    ensure_complete(store, allow_partial)?;
+    let overview = Overview::load(store, include_trashed)?;
+    outln!("{}", overview.total_quota_bytes);
+    match restore_crawl_query(store)? {
+        Some(query) if query == DEFAULT_QUERY => {}
+        Some(query) => outln!("Crawled the files matching {query:?}"),
+        None => outln!("Crawled a single folder"),
+    }
+    let snapshot = store
+        .snapshot
+        .clone()
+        .unwrap_or_else(|| store.file_list_path());
+    if let Some(crawl) = restore_provenance(&snapshot)? {
+        outln!(
+            "Crawled {} from {} to {} ({} pages{}) with drive-crawl {}{}",
+            crawl.account.as_deref().unwrap_or("an unknown account"),
+            crawl.started_at,
+            crawl.finished_at,
+            crawl.pages,
+            if crawl.complete { "" } else { ", incomplete" },
+            crawl.tool_version,
+            crawl
+                .synced_at
+                .map_or(String::new(), |at| format!(", synced at {at}")),
+        );
+    }
+
+    let my_drive_bytes = overview
+        .quota_bytes_per_drive
+        .get(&None)
+        .copied()
+        .unwrap_or(0);
+    let shared_drive_bytes = overview.total_quota_bytes - my_drive_bytes;
+    if shared_drive_bytes > 0 || overview.shortcuts > 0 {
+        outln!("=== Storage ===");
+        outln!(
+            "{:>12}  My quota, used in My Drive",
+            format_size(my_drive_bytes)
+        );
+        if shared_drive_bytes > 0 {
+            outln!(
+                "{:>12}  Shared drives, which use their own storage instead",
+                format_size(shared_drive_bytes)
+            );
+        }
+        if overview.shortcuts > 0 {
+            outln!(
+                "{:>12}  Reachable through shortcuts (already counted above where crawled; \
+                shortcuts use no storage)",
+                format_size(overview.shortcut_target_bytes)
+            );
+        }
+    }
+
+    // Also the shared drives that only a folder crawl has reached into
+    let mut drives: Vec<_> = overview
+        .quota_bytes_per_drive
+        .iter()
+        .filter_map(|(id, &bytes)| Some((id.as_deref()?, bytes)))
+        .collect();
+    if !drives.is_empty() {
+        drives.sort_by(|(x, a), (y, b)| b.cmp(a).then_with(|| x.cmp(y)));
+        let names: HashMap<_, _> = restore_shared_drives(store)?
+            .into_iter()
+            .map(|d| (d.id, d.name))
+            .collect();
+        outln!("=== Usage per drive ===");
+        outln!("{:>12}  My Drive", format_size(my_drive_bytes));
+        for (id, bytes) in drives {
+            match names.get(id) {
+                Some(name) => outln!("{:>12}  {name} ({id})", format_size(bytes)),
+                None => outln!("{:>12}  {id}", format_size(bytes)),
+            }
+        }
+    }
+
+    let mut spaces: Vec<_> = overview
+        .quota_bytes_per_space
+        .iter()
+        .filter(|&(space, _)| space != Space::Drive.name())
+        .collect();
+    if !spaces.is_empty() {
+        spaces.sort();
+        outln!("=== Usage in spaces hidden from the Drive UI ===");
+        for (space, &bytes) in spaces {
+            outln!("{:>12}  {space}", format_size(bytes));
+        }
+    }
+
+    if owners {
+        outln!("=== Usage per owner ===");
+        let mut owners: Vec<_> = overview.quota_bytes_per_owner.iter().collect();
+        owners.sort_by_key(|&(_, &bytes)| std::cmp::Reverse(bytes));
+        for (owner, &bytes) in owners {
+            let owner = owner
+                .as_deref()
+                .unwrap_or("(no owner, i.e. in shared drives)");
+            outln!("{:>12}  {owner}", format_size(bytes));
+        }
+    }
+
+    if overview.shortcuts > 0 {
+        outln!("=== Shortcuts ===");
+        outln!(
+            "{} shortcuts, {} of which point to files missing from the snapshot.  \
+            Run broken-shortcuts to list them.",
+            overview.shortcuts,
+            overview.broken_shortcuts
+        );
+    }
+
+    let print_file = |file: &File| outln!("{:?} {:50} {}", file.parents, file.mime_type, file.name);
+
+    outln!("=== Files without a parent (or with multiple parents) ===");
+    for file in &overview.without_single_parent {
+        print_file(file);
+    }
+
+    outln!("=== Files with parents not owned by me ===");
+    let foreign_parents = parents::restore(store)?;
+    for file in &overview.with_foreign_parents {
+        let parents: Vec<_> = file
+            .parents
+            .iter()
+            .map(|id| match foreign_parents.get(id) {
+                Some(folder) => format!("{} ({id})", folder.label()),
+                None => id.clone(),
+            })
+            .collect();
+        outln!("{parents:?} {:50} {}", file.mime_type, file.name);
+    }
+    if foreign_parents.is_empty() && !overview.with_foreign_parents.is_empty() {
+        outln!("Run resolve-parents to look up the names of the parents.");
+    }
+
+    let describe = |file: &File| {
+        json!({
+            "id": file.id,
+            "name": file.name,
+            "mime_type": file.mime_type,
+            "parents": file.parents,
+        })
+    };
+    let mut report = json!({
+        "total_quota_bytes": overview.total_quota_bytes,
+        "per_drive": overview
+            .quota_bytes_per_drive
+            .iter()
+            .map(|(drive_id, bytes)| json!({ "drive_id": drive_id, "bytes": bytes }))
+            .collect::<Vec<_>>(),
+        "my_drive_bytes": my_drive_bytes,
+        "shared_drive_bytes": shared_drive_bytes,
+        "per_space": overview.quota_bytes_per_space,
+        "shortcuts": overview.shortcuts,
+        "broken_shortcuts": overview.broken_shortcuts,
+        "shortcut_target_bytes": overview.shortcut_target_bytes,
+        "without_single_parent": overview
+            .without_single_parent
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>(),
+        "with_foreign_parents": overview
+            .with_foreign_parents
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>(),
+    });
+    if owners {
+        report["per_owner"] = overview
+            .quota_bytes_per_owner
+            .iter()
+            .map(|(owner, bytes)| json!({ "owner": owner, "bytes": bytes }))
+            .collect::<Vec<_>>()
+            .into();
+    }
+    output::emit(&report)
+}
+
+/// The storage quota as of the last `quota`, for reports made without the API
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedQuota {
+    /// `None` for unlimited
+    pub limit: Option<u64>,
+    pub usage: Option<u64>,
+    pub usage_in_drive: Option<u64>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// The storage quota last saved by `quota`, if any
+pub(crate) fn restore_quota(store: &Store) -> anyhow::Result<Option<SavedQuota>> {
+    match fs_err::read(store.quota_path()) {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)?,
+    }
+}
+
+pub async fn show_quota(drive: &Drive, store: &Store, retry: &RetryPolicy) -> anyhow::Result<()> {
+    let (_, about) = retry
+        .run(|| drive.about().get().param("fields", "storageQuota").doit())
+        .await?;
+    let quota = about
+        .storage_quota
+        .context("The API did not return the storage quota")?;
+    let saved = SavedQuota {
+        limit: quota.limit.map(|x| x as u64),
+        usage: quota.usage.map(|x| x as u64),
+        usage_in_drive: quota.usage_in_drive.map(|x| x as u64),
+        fetched_at: Utc::now(),
+    };
+    fs_err::write(store.quota_path(), serde_json::to_vec(&saved)?)?;
+    let bytes = |x: Option<i64>| x.map_or("unknown".into(), |x| format_size(x as u64));
+    let usage = quota.usage.unwrap_or(0);
+    let usage_in_drive = quota.usage_in_drive.unwrap_or(0);
+    match quota.limit {
+        Some(limit) => outln!("Limit:           {:>12}", format_size(limit as u64)),
+        None => outln!("Limit:           {:>12}", "unlimited"),
+    }
+    outln!("Usage:           {:>12}", bytes(quota.usage));
+    outln!("  Drive:         {:>12}", bytes(quota.usage_in_drive));
+    outln!("    Trash:       {:>12}", bytes(quota.usage_in_drive_trash));
+    // The API does not break the rest down any further
+    outln!(
+        "  Gmail, Photos: {:>12}",
+        format_size((usage - usage_in_drive).max(0) as u64)
+    );
+
+    // Files in shared drives do not count towards the quota of the user, while those in the
+    // trash do
+    let crawled = Overview::load(store, true)?
+        .quota_bytes_per_drive
+        .get(&None)
+        .copied()
+        .unwrap_or(0);
+    outln!("Crawled:         {:>12}", format_size(crawled));
+    let difference = crawled.abs_diff(usage_in_drive as u64);
+    if difference > usage_in_drive as u64 / 100 {
+        warn!(
+            "The crawled files account for {} while the API reports {} in Drive.  \
+            The snapshot may be incomplete or outdated.",
+            format_size(crawled),
+            format_size(usage_in_drive as u64)
+        );
+    }
+    output::emit(&json!({
+        "limit": quota.limit,
+        "usage": quota.usage,
+        "usage_in_drive": quota.usage_in_drive,
+        "usage_in_drive_trash": quota.usage_in_drive_trash,
+        "crawled": crawled,
+    }))
+}
+
+/// Lookups needed to walk the folder hierarchy of a snapshot
+trait FileIndex {
+    fn get(&self, id: &str) -> anyhow::Result<Option<File>>;
+    /// Files whose first parent is the folder, so that each file appears only once in the tree
+    fn children(&self, id: &str) -> anyhow::Result<Vec<File>>;
+    /// Files with the folder as any of their parents
+    fn all_children(&self, id: &str) -> anyhow::Result<Vec<File>>;
+    /// Legacy files placed in several folders, which Drive no longer allows for new ones
+    fn with_multiple_parents(&self) -> anyhow::Result<Vec<File>>;
+    /// Sorted ids of the topmost folders, i.e. parents that are either missing from the snapshot
+    /// or have no parent themselves
+    fn roots(&self) -> anyhow::Result<Vec<String>>;
+}
+
+struct MemoryIndex<'a> {
+    id_to_file: HashMap<&'a str, &'a File>,
+    /// By the first parent only
+    parent_id_to_children: HashMap<&'a str, Vec<&'a File>>,
+    /// Files with multiple parents under the other parents
+    parent_id_to_extra_children: HashMap<&'a str, Vec<&'a File>>,
+}
+impl<'a> MemoryIndex<'a> {
+    fn new(files: &'a [File]) -> anyhow::Result<Self> {
+        let mut parent_id_to_extra_children = HashMap::<_, Vec<_>>::new();
+        for file in files {
+            for parent in file.parents.iter().skip(1) {
+                parent_id_to_extra_children
+                    .entry(parent as &str)
+                    .or_default()
+                    .push(file);
+            }
+        }
+        Ok(Self {
+            id_to_file: files.iter().map(|file| (&file.id as &str, file)).collect(),
+            parent_id_to_children: get_parent_id_to_children(files),
+            parent_id_to_extra_children,
+        })
+    }
+}
+impl FileIndex for MemoryIndex<'_> {
+    fn get(&self, id: &str) -> anyhow::Result<Option<File>> {
+        Ok(self.id_to_file.get(id).map(|&file| file.clone()))
+    }
+    fn children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        Ok(self
+            .parent_id_to_children
+            .get(id)
+            .map_or(vec![], |children| {
+                children.iter().map(|&file| file.clone()).collect()
+            }))
+    }
+    fn all_children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        let mut children = self.children(id)?;
+        if let Some(extra) = self.parent_id_to_extra_children.get(id) {
+            children.extend(extra.iter().map(|&file| file.clone()));
+        }
+        Ok(children)
+    }
+    fn with_multiple_parents(&self) -> anyhow::Result<Vec<File>> {
+        let mut files: Vec<_> = self
+            .id_to_file
+            .values()
+            .filter(|file| file.parents.len() > 1)
+            .map(|&file| file.clone())
+            .collect();
+        files.sort_by(|x, y| x.id.cmp(&y.id));
+        Ok(files)
+    }
+    fn roots(&self) -> anyhow::Result<Vec<String>> {
+        let mut roots: Vec<_> = self
+            .parent_id_to_children
+            .keys()
+            .filter(|&id| {
+                self.id_to_file
+                    .get(id)
+                    .is_none_or(|file| file.parents.is_empty())
+            })
+            .map(|&id| id.to_owned())
+            .collect();
+        roots.sort();
+        Ok(roots)
+    }
+}
+
+/// Index hiding the trashed files, except from `get` so that their paths can still be built
+struct WithoutTrashed<'a>(&'a dyn FileIndex);
+impl FileIndex for WithoutTrashed<'_> {
+    fn get(&self, id: &str) -> anyhow::Result<Option<File>> {
+        self.0.get(id)
+    }
+    fn children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        let mut children = self.0.children(id)?;
+        children.retain(|f| !f.trashed);
+        Ok(children)
+    }
+    fn all_children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        let mut children = self.0.all_children(id)?;
+        children.retain(|f| !f.trashed);
+        Ok(children)
+    }
+    fn with_multiple_parents(&self) -> anyhow::Result<Vec<File>> {
+        let mut files = self.0.with_multiple_parents()?;
+        files.retain(|f| !f.trashed);
+        Ok(files)
+    }
+    fn roots(&self) -> anyhow::Result<Vec<String>> {
+        self.0.roots()
+    }
+}
+
+/// Runs `f` with an index over the snapshot of the given store.
+fn with_index<T>(
+    store: &Store,
+    f: impl FnOnce(&dyn FileIndex) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    match store.kind {
+        StoreKind::Json => f(&MemoryIndex::new(&restore_files(store)?)?),
+        StoreKind::Sqlite => f(&sqlite::SqliteIndex(&sqlite::open(&store.dir)?)),
+    }
+}
+
+/// Names of the shared drives and of the folders resolved by `resolve-parents`, by id
+fn root_names(store: &Store) -> anyhow::Result<HashMap<String, String>> {
+    let mut names: HashMap<_, _> = parents::restore(store)?
+        .into_iter()
+        .map(|(id, folder)| (id, folder.label()))
+        .collect();
+    for shared_drive in restore_shared_drives(store)? {
+        names.insert(
+            shared_drive.id,
+            format!("Shared drive {}", shared_drive.name),
+        );
+    }
+    Ok(names)
+}
+
+/// Display name of a root that is missing from the snapshot
+fn root_name(root_names: &HashMap<String, String>, id: &str) -> String {
+    match root_names.get(id) {
+        Some(name) => format!("{name} ({id})"),
+        None => format!("Root ({id})"),
+    }
+}
+
+/// Files with multiple parents are placed under the first one, as in [`full_path`].
+fn get_parent_id_to_children(files: &[File]) -> HashMap<&str, Vec<&File>> {
+    let mut parent_id_to_children = HashMap::<_, Vec<_>>::new();
+    for file in files {
+        if let Some(parent) = file.parents.first() {
+            parent_id_to_children
+                .entry(parent as &str)
+                .or_default()
+                .push(file);
+        }
+    }
+    parent_id_to_children
+}
+
+pub fn format_size(size: u64) -> String {
+    let prefix = ["", "Ki", "Mi", "Gi"];
+    prefix
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, prefix)| {
+            let base = 1 << (i * 10);
+            (size >= base).then(|| format!("{:.2} {prefix}B", size as f64 / base as f64))
+        })
+        .unwrap_or("0 B".into())
+}
+
+/// Parses sizes like `1048576`, `500M` or `1.5GiB` in bytes, always with binary prefixes.
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size {s:?}"))?;
+    let unit = unit
+        .trim()
+        .trim_end_matches(['B', 'b'])
+        .trim_end_matches('i')
+        .to_ascii_uppercase();
+    let exponent = match &unit as &str {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => bail!("Invalid unit in size {s:?}"),
+    };
+    Ok((number * (1u64 << (exponent * 10)) as f64) as u64)
+}
+
+pub fn check_duplicates(store: &Store, id: &str) -> anyhow::Result<()> {
+    with_index(store, |index| {
+        let Some(root) = index.get(id)? else {
+            bail!("File with id {id:?} was not found");
+        };
+        let parent = root
+            .parents
+            .first()
+            .context("Specified root does not have a parent")?;
+        let parent = index
+            .get(parent)?
+            .with_context(|| format!("File with id={parent:?} was not found"))?;
+        let mut sha_to_files = HashMap::<_, Vec<_>>::new();
+        let mut without_backup = vec![];
+        for file in bfs_children(index, parent.clone())? {
+            if let Some(sha256) = file.sha256_checksum.clone() {
+                sha_to_files.entry(sha256).or_default().push(file);
+            }
+        }
+        for file in bfs_children(index, root)? {
+            if file.mime_type != "application/vnd.google-apps.folder" {
+                let sha256 = file.sha256_checksum.as_ref().with_context(|| {
+                    format!("File with id={:?} does not have SHA256", parent.id)
+                })?;
+                let candidates = sha_to_files.get(sha256).map_or(&[][..], |x| x);
+                // if let Some(backup) = candidates.iter().find(|f| f.id != file.id) {
+                //     println!("{file:?}\n\t{backup:?}\n");
+                // }
+                if !candidates.iter().any(|f| f.id != file.id) {
+                    outln!("This file does not have a backup: {file:?}");
+                    without_backup.push(file);
+                }
+            }
+        }
+        output::emit(&without_backup)
+    })
+}
+
+/// Groups of files sharing the SHA256 and the size as `(reclaimable bytes, SHA256, size, files)`,
+/// most reclaimable first.  Only the copies I can trash are reclaimable, and one copy is kept.
+fn duplicate_clusters(files: &[File]) -> Vec<(u64, &str, u64, Vec<&File>)> {
+    let mut clusters = HashMap::<_, Vec<_>>::new();
+    for file in files {
+        if let (Some(sha256), Some(size)) = (&file.sha256_checksum, file.size) {
+            clusters
+                .entry((sha256 as &str, size))
+                .or_default()
+                .push(file);
+        }
+    }
+    let mut clusters: Vec<_> = clusters
+        .into_iter()
+        .filter(|(_, files)| files.len() >= 2)
+        .map(|((sha256, size), files)| {
+            let trashable = files.iter().filter(|f| f.can_trash()).count();
+            let reclaimable = trashable.min(files.len() - 1) as u64;
+            (size * reclaimable, sha256, size, files)
+        })
+        .collect();
+    clusters.sort_by(|x, y| y.0.cmp(&x.0).then_with(|| x.1.cmp(y.1)));
+    clusters
+}
+
+pub fn show_duplicates(store: &Store) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let clusters = duplicate_clusters(&files);
+
+    let mut total_wasted = 0;
+    let mut rows = vec![];
+    for (wasted, sha256, size, files) in &clusters {
+        outln!(
+            "=== {} reclaimable: {} copies of {} (SHA256 {sha256}) ===",
+            format_size(*wasted),
+            files.len(),
+            format_size(*size),
+        );
+        let (mut paths, mut untrashable) = (vec![], vec![]);
+        for file in files {
+            let path = full_path(&index, file)?;
+            if file.can_trash() {
+                outln!("{path}");
+            } else {
+                outln!("{path}  (cannot trash)");
+                untrashable.push(path.clone());
+            }
+            paths.push(path);
+        }
+        total_wasted += wasted;
+        rows.push(json!({
+            "sha256": sha256,
+            "size": size,
+            "wasted": wasted,
+            "paths": paths,
+            "cannot_trash": untrashable,
+        }));
+    }
+    outln!(
+        "{} clusters, {} reclaimable in total",
+        clusters.len(),
+        format_size(total_wasted)
+    );
+    output::emit(&rows)
+}
+
+/// Builds the `/`-separated path of the file by following the first parents up to a root.
+/// The root itself is omitted, so that files in My Drive look like `/folder/file`.
+fn full_path(index: &dyn FileIndex, file: &File) -> anyhow::Result<String> {
+    let mut names = vec![file.name.clone()];
+    let mut visited = HashSet::new();
+    let mut parent = file.parents.first().cloned();
+    while let Some(id) = parent {
+        if !visited.insert(id.clone()) {
+            bail!("Cyclic parents: {id}");
+        }
+        let Some(file) = index.get(&id)? else { break };
+        names.push(file.name);
+        parent = file.parents.first().cloned();
+    }
+    names.push(String::new());
+    names.reverse();
+    Ok(names.join("/"))
+}
+
+/// Whether the file is under the folder, following the first parents
+pub(crate) fn is_under(index: &MemoryIndex, file: &File, folder_id: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut current = file.parents.first();
+    while let Some(id) = current.filter(|&id| visited.insert(id)) {
+        if id == folder_id {
+            return true;
+        }
+        current = index
+            .id_to_file
+            .get(id as &str)
+            .and_then(|parent| parent.parents.first());
+    }
+    false
+}
+
+/// Builds the paths of the file through every parent, in the same format as [`full_path`].
+fn all_paths(id_to_file: &HashMap<&str, &File>, file: &File) -> anyhow::Result<Vec<String>> {
+    fn dfs(
+        id_to_file: &HashMap<&str, &File>,
+        file: &File,
+        visiting: &mut Vec<String>,
+        suffix: &str,
+        ret: &mut Vec<String>,
+    ) -> anyhow::Result<()> {
+        if visiting.contains(&file.id) {
+            bail!("Cyclic parents: {}", file.id);
+        }
+        let path = format!("/{}{suffix}", file.name);
+        let parents: Vec<_> = file
+            .parents
+            .iter()
+            .filter_map(|id| id_to_file.get(id as &str))
+            .collect();
+        if parents.is_empty() {
+            ret.push(path);
+            return Ok(());
+        }
+        visiting.push(file.id.clone());
+        for parent in parents {
+            dfs(id_to_file, parent, visiting, &path, ret)?;
+        }
+        visiting.pop();
+        Ok(())
+    }
+    let mut ret = vec![];
+    dfs(id_to_file, file, &mut vec![], "", &mut ret)?;
+    Ok(ret)
+}
+
+/// Prints where the files with the id or name are, along with the shortcuts pointing to them.
+pub fn show_paths(store: &Store, query: &str) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let id_to_file: HashMap<_, _> = files.iter().map(|f| (&f.id as &str, f)).collect();
+    let mut target_to_shortcuts = HashMap::<_, Vec<_>>::new();
+    for file in &files {
+        if let Some(ref details) = file.shortcut_details {
+            target_to_shortcuts
+                .entry(&details.target_id as &str)
+                .or_default()
+                .push(file);
+        }
+    }
+
+    let matches: Vec<_> = match id_to_file.get(query) {
+        Some(&file) => vec![file],
+        None => files.iter().filter(|f| f.name == query).collect(),
+    };
+    if matches.is_empty() {
+        bail!("No file has the id or name {query:?}");
+    }
+    let mut rows = vec![];
+    for file in matches {
+        outln!(
+            "=== {} ({}, {}, {}) ===",
+            file.name,
+            file.id,
+            file.mime_type,
+            format_size(file.quota_bytes_used.unwrap_or(0))
+        );
+        let paths = all_paths(&id_to_file, file)?;
+        for path in &paths {
+            outln!("{path}");
+        }
+        let mut target_paths = vec![];
+        if let Some(ref details) = file.shortcut_details {
+            match id_to_file.get(&details.target_id as &str) {
+                Some(target) => {
+                    target_paths = all_paths(&id_to_file, target)?;
+                    for path in &target_paths {
+                        outln!("  points to {path}");
+                    }
+                }
+                None => outln!("  points to {} (not in the snapshot)", details.target_id),
+            }
+        }
+        let mut shortcut_paths = vec![];
+        for shortcut in target_to_shortcuts
+            .get(&file.id as &str)
+            .into_iter()
+            .flatten()
+        {
+            for path in all_paths(&id_to_file, shortcut)? {
+                outln!("  shortcut at {path}");
+                shortcut_paths.push(path);
+            }
+        }
+        rows.push(json!({
+            "id": file.id,
+            "name": file.name,
+            "mime_type": file.mime_type,
+            "quota_bytes_used": file.quota_bytes_used,
+            "paths": paths,
+            "points_to": target_paths,
+            "shortcuts": shortcut_paths,
+        }));
+    }
+    output::emit(&rows)
+}
+
+fn bfs_children(index: &dyn FileIndex, root: File) -> anyhow::Result<VecDeque<File>> {
+    let mut que = VecDeque::new();
+    que.push_back(root);
+    for i in 0.. {
+        let Some(file) = que.get(i) else { break };
+        for child in index.children(&file.id)? {
+            que.push_back(child);
+        }
+    }
+    Ok(que)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn file(value: serde_json::Value) -> File {
+        let mut file = json!({
+            "mimeType": "application/octet-stream",
+            "parents": ["root"],
+            "name": "name",
+        });
+        file.as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(file).unwrap()
+    }
+
+    #[test]
+    fn migrates_every_version_up_to_the_current_one() {
+        let pages = json!([{ "files": [] }]);
+        for version in 0..SNAPSHOT_VERSION {
+            let mut value = json!({ "version": version, "pages": pages });
+            for version in version..SNAPSHOT_VERSION {
+                migrate_data(&mut value, version);
+            }
+            assert_eq!(value["version"], SNAPSHOT_VERSION);
+            assert_eq!(value["pages"], pages);
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1048576").unwrap(), 1048576);
+        assert_eq!(parse_size("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_size("2 kb").unwrap(), 2048);
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("1P").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn aggregates_the_overview() {
+        let overview = Overview::from_files(vec![
+            file(json!({
+                "id": "a",
+                "quotaBytesUsed": "2000",
+                "owners": [{ "emailAddress": "me@example.com" }],
+            })),
+            file(json!({
+                "id": "b",
+                "parents": ["a"],
+                "quotaBytesUsed": "3000",
+                "owners": [{ "emailAddress": "you@example.com" }],
+                "spaces": ["drive", "appDataFolder"],
+            })),
+            // In a folder missing from the snapshot
+            file(json!({ "id": "c", "parents": ["elsewhere"], "quotaBytesUsed": "5000" })),
+            // Too small to be listed
+            file(json!({ "id": "d", "parents": ["elsewhere"], "quotaBytesUsed": "1000" })),
+            // At the root of a shared drive
+            file(json!({
+                "id": "e",
+                "parents": ["drive"],
+                "driveId": "drive",
+                "quotaBytesUsed": "4000",
+            })),
+            file(json!({ "id": "f", "parents": [], "shortcutDetails": { "targetId": "b" } })),
+            file(json!({ "id": "g", "shortcutDetails": { "targetId": "b" } })),
+            file(json!({ "id": "h", "shortcutDetails": { "targetId": "missing" } })),
+        ]);
+        assert_eq!(overview.total_quota_bytes, 15000);
+        assert_eq!(overview.quota_bytes_per_drive[&None], 11000);
+        assert_eq!(overview.quota_bytes_per_drive[&Some("drive".into())], 4000);
+        assert_eq!(
+            overview.quota_bytes_per_owner[&Some("me@example.com".into())],
+            2000
+        );
+        assert_eq!(overview.quota_bytes_per_owner[&None], 10000);
+        assert_eq!(overview.quota_bytes_per_space["drive"], 12000);
+        assert_eq!(overview.quota_bytes_per_space["appDataFolder"], 3000);
+        assert_eq!(overview.shortcuts, 3);
+        assert_eq!(overview.broken_shortcuts, 1);
+        assert_eq!(overview.shortcut_target_bytes, 3000);
+        let ids = |files: &[File]| files.iter().map(|f| f.id.clone()).collect::<Vec<_>>();
+        // The root is not in the snapshot either
+        assert_eq!(ids(&overview.with_foreign_parents), ["a", "c"]);
+        assert_eq!(ids(&overview.without_single_parent), ["f"]);
+    }
+}
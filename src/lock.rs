@@ -0,0 +1,101 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Who holds the lock, as written in the lock file
+#[derive(Serialize, Deserialize)]
+struct Holder {
+    pid: u32,
+    command: String,
+    started_at: DateTime<Utc>,
+}
+
+/// Whether the process is still running.  Assumed so where this cannot be told.
+fn is_running(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else if cfg!(unix) {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map_or(true, |status| status.success())
+    } else {
+        true
+    }
+}
+
+/// Lock file in the data directory, held while a command writes the snapshot or the state next
+/// to it so that two runs do not interleave their saves.  Removed when dropped.
+pub struct StoreLock {
+    path: PathBuf,
+}
+
+impl StoreLock {
+    /// Takes the lock, replacing one left behind by a process that is gone, or any with `force`.
+    pub fn acquire(dir: &Path, command: &str, force: bool) -> anyhow::Result<Self> {
+        let path = dir.join("lock.json");
+        let holder = Holder {
+            pid: std::process::id(),
+            command: command.to_owned(),
+            started_at: Utc::now(),
+        };
+        // Retried once after removing a stale lock
+        for _ in 0..2 {
+            match fs_err::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    serde_json::to_writer(&mut file, &holder)?;
+                    file.flush()?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => Err(e)?,
+            }
+            // The other run may have just created it without writing it yet
+            let other = fs_err::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<Holder>(&data).ok());
+            match other {
+                _ if force => warn!("Overriding the lock {path:?} as forced"),
+                Some(other) if !is_running(other.pid) => info!(
+                    "Removing the stale lock of `{}` (pid {}), which is no longer running",
+                    other.command, other.pid
+                ),
+                Some(other) => bail!(
+                    "Another run of `{}` (pid {}) started at {} is using {dir:?}.  Wait for it \
+                    to finish, or pass --force if it is not running.",
+                    other.command,
+                    other.pid,
+                    other.started_at.format("%Y-%m-%d %H:%M:%S")
+                ),
+                None => bail!(
+                    "Another run is using {dir:?}, or left {path:?} behind.  Wait for it to \
+                    finish, or pass --force if none is running."
+                ),
+            }
+            fs_err::remove_file(&path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })?;
+        }
+        bail!("Another run took {path:?} in the meantime")
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs_err::remove_file(&self.path) {
+            warn!("Failed to remove the lock: {e}");
+        }
+    }
+}
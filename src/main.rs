@@ -1,375 +1,1535 @@
+mod config;
+mod json_log;
+mod read_only;
+mod reauth;
+mod token_store;
+
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    io::{BufReader, BufWriter},
-    sync::mpsc,
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
-use clap::Parser;
+use chrono::Utc;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use drive_crawl::{
+    activity,
+    audit::{self, AuditLog},
+    bandwidth::Schedule,
+    browse,
+    budget::ApiBudget,
+    check_duplicates, collisions, computers, connect, dashboard, dedupe, delete, diff, download,
+    empty, export, fs_audit, fsck, histogram, history, inspect,
+    lock::StoreLock,
+    merge, notify, orphans,
+    output::{self, OutputFormat},
+    parallel, parents, parse_size, permissions, rename, report,
+    retry::{RetryPolicy, TokenEpoch},
+    revisions, search, shared_with_me, shortcuts, show_duplicates, show_overview, show_paths,
+    show_quota, stale,
+    status::Status,
+    transfer, trash, tree, trend, types, upload, verify_local, watch, CancellationToken, Crawler,
+    Drive, ListOptions, MarkFilter, Space, Store, StoreKind,
+};
 use google_drive3::{
-    hyper::{self, client::HttpConnector},
-    hyper_rustls::{HttpsConnector, HttpsConnectorBuilder},
-    oauth2::{self, InstalledFlowAuthenticator, InstalledFlowReturnMethod},
+    oauth2::{
+        self,
+        authenticator_delegate::{DeviceAuthResponse, DeviceFlowDelegate, InstalledFlowDelegate},
+        DeviceFlowAuthenticator, InstalledFlowAuthenticator, InstalledFlowReturnMethod,
+        ServiceAccountAuthenticator,
+    },
     DriveHub,
 };
-use log::{error, info, warn};
-use serde::{Deserialize, Deserializer, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
+use log::{error, warn, LevelFilter};
+use read_only::ReadOnlyAuth;
+use reauth::Reauthenticating;
+use regex::Regex;
+use token_store::{SecureStorage, TokenStoreKind};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logger()?;
-    let args = Args::parse();
-    let drive = init_drive().await?;
-    let ctrlc_handler = init_ctrlc()?;
+    let started = Utc::now();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let config = config::load(&args.paths)?;
+    // The directories of a new profile do not exist yet
+    fs_err::create_dir_all(&config.data_dir)?;
+    if let Some(dir) = config.log_file.parent() {
+        fs_err::create_dir_all(dir)?;
+    }
+    init_logger(&config.log_file, &args)?;
+    if args.non_interactive {
+        drive_crawl::hide_progress();
+    }
+    output::set_output_format(args.output_format);
+    let read_only = config.read_only || !args.read_write;
+    let client = connect::client(&connect::Options {
+        ca_bundle: args.connect.ca_bundle.clone(),
+        http1: args.connect.http1,
+        connect_timeout: Some(Duration::from_secs(args.connect.connect_timeout)),
+        idle_timeout: Duration::from_secs(args.connect.idle_timeout),
+        pool_size: args.connect.pool_size,
+    })?;
+    let token_epoch = TokenEpoch::default();
+    let drive = || {
+        init_drive(
+            &config,
+            &args.auth,
+            client.clone(),
+            token_epoch.clone(),
+            args.non_interactive,
+            read_only,
+        )
+    };
+    let cancel = init_ctrlc()?;
+    let retry = RetryPolicy {
+        max_attempts: args.max_attempts,
+        // Lives as long as the process, shared by every call
+        budget: Box::leak(Box::new(match args.bwlimit {
+            Some(ref schedule) => {
+                ApiBudget::new(args.max_requests_per_sec).with_bandwidth(schedule.clone())
+            }
+            None => ApiBudget::new(args.max_requests_per_sec),
+        })),
+        timeout: (args.request_timeout > 0).then(|| Duration::from_secs(args.request_timeout)),
+        cancel: Some(cancel.clone()),
+        token_epoch: token_epoch.clone(),
+    };
 
-    if args.list {
-        list_files(&drive, &ctrlc_handler).await?;
-    } else if args.show_overview {
-        show_overview()?;
-    } else if args.tree {
-        show_tree()?;
-    } else if let Some(ref id) = args.check_duplicates {
-        check_duplicates(&id)?;
+    let mut store = Store {
+        kind: args.store,
+        dir: config.data_dir.clone(),
+        compress_level: args.compress_level,
+        keep_snapshots: args.keep_snapshots,
+        snapshot: None,
+    };
+    if let Some(ref selector) = args.snapshot {
+        // Archived snapshots are always JSON
+        store.snapshot = Some(history::resolve(&store, selector)?);
+        store.kind = StoreKind::Json;
+    }
+    let store = &store;
+    let audit = || AuditLog::open(store, args.dry_run);
+    let notification = match args.command {
+        Command::Crawl { ref notify, .. } => Some(("crawl", notify)),
+        Command::Sync { ref notify } => Some(("sync", notify)),
+        _ => None,
+    }
+    .and_then(|(command, args)| Some((command, args.notify.as_deref()?, args.notify_format)));
+    // A missing or unreadable snapshot just has no delta to report
+    let before = notification.and_then(|_| notify::Totals::load(store).ok());
+    let result = async {
+        if config.read_only && args.read_write {
+            bail!("The config file enforces the read-only mode, so --read-write is refused");
+        }
+        if read_only && !args.dry_run && args.command.mutates() {
+            bail!(
+                "`{}` modifies files, which the read-only mode refuses.  Pass --read-write to \
+                allow it, or --dry-run to preview it.",
+                matches.subcommand_name().unwrap_or_default()
+            );
+        }
+        let _lock = if args.command.writes_store() && store.snapshot.is_none() {
+            let command = matches.subcommand_name().unwrap_or_default();
+            Some(StoreLock::acquire(&store.dir, command, args.force)?)
+        } else {
+            None
+        };
+        match args.command {
+            Command::Crawl {
+                shared_with_me: true,
+                page_size,
+                ref fields,
+                ..
+            } => {
+                Crawler::new(drive().await?, store.clone(), cancel.clone())
+                    .retry(retry.clone())
+                    .list(ListOptions {
+                        page_size,
+                        extra_fields: fields.clone(),
+                        ..Default::default()
+                    })
+                    .crawl_shared_with_me()
+                    .await?
+            }
+            Command::Crawl {
+                folder: Some(ref folder),
+                page_size,
+                ref fields,
+                ref labels,
+                ..
+            } => {
+                Crawler::new(drive().await?, store.clone(), cancel.clone())
+                    .retry(retry.clone())
+                    .list(ListOptions {
+                        page_size,
+                        extra_fields: fields.clone(),
+                        labels: labels.clone(),
+                        ..Default::default()
+                    })
+                    .crawl_folder(folder)
+                    .await?
+            }
+            Command::Crawl {
+                partitions: None,
+                ref query,
+                page_size,
+                ref fields,
+                ref spaces,
+                ref labels,
+                ..
+            } => {
+                Crawler::new(drive().await?, store.clone(), cancel.clone())
+                    .retry(retry.clone())
+                    .query(query.clone())
+                    .list(ListOptions {
+                        page_size,
+                        extra_fields: fields.clone(),
+                        spaces: spaces.clone(),
+                        labels: labels.clone(),
+                    })
+                    .crawl()
+                    .await?
+            }
+            Command::Crawl {
+                partitions: Some(partitions),
+                concurrency,
+                ref query,
+                page_size,
+                ref fields,
+                ref spaces,
+                ref labels,
+                ..
+            } => {
+                parallel::crawl_in_parallel(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    query,
+                    &parallel::Options {
+                        partitions,
+                        concurrency,
+                        list: ListOptions {
+                            page_size,
+                            extra_fields: fields.clone(),
+                            spaces: spaces.clone(),
+                            labels: labels.clone(),
+                        },
+                    },
+                )
+                .await?
+            }
+            Command::Sync { .. } => {
+                Crawler::new(drive().await?, store.clone(), cancel.clone())
+                    .retry(retry.clone())
+                    .sync()
+                    .await?
+            }
+            Command::ResolveParents { concurrency } => {
+                parents::resolve_parents(&drive().await?, &cancel, store, &retry, concurrency)
+                    .await?
+            }
+            Command::Watch {
+                interval,
+                listen,
+                ref webhook_url,
+                metrics,
+            } => {
+                let webhook = listen.map(|listen| watch::Webhook {
+                    listen,
+                    url: webhook_url.clone().expect("required by clap"),
+                });
+                watch::watch(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    &watch::Options {
+                        interval: Duration::from_secs(interval),
+                        webhook,
+                        metrics,
+                    },
+                )
+                .await?
+            }
+            Command::Drives => {
+                Crawler::new(drive().await?, store.clone(), cancel.clone())
+                    .retry(retry.clone())
+                    .crawl_shared_drives()
+                    .await?
+            }
+            Command::Permissions { concurrency } => {
+                let drive = drive().await?;
+                permissions::list_permissions(&drive, &cancel, store, &retry, concurrency).await?
+            }
+            Command::Quota => show_quota(&drive().await?, store, &retry).await?,
+            Command::Trash => trash::show_trash(&drive().await?, &retry).await?,
+            Command::Revisions {
+                threshold,
+                purge,
+                concurrency,
+            } => {
+                revisions::audit_revisions(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    threshold,
+                    purge,
+                    concurrency,
+                    &mut audit()?,
+                )
+                .await?
+            }
+            Command::Inspect { ref id } => {
+                inspect::inspect(&drive().await?, store, &retry, id).await?
+            }
+            Command::Activity {
+                ref folder_id,
+                days,
+                limit,
+            } => {
+                let drive = drive().await?;
+                activity::show_activity(&drive, store, &retry, folder_id, days, limit).await?
+            }
+            Command::EmptyTrash => {
+                trash::empty_trash(&drive().await?, &retry, &mut audit()?).await?
+            }
+            Command::DeleteFrom {
+                ref list,
+                permanent,
+            } => {
+                delete::delete_from(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    &mut audit()?,
+                    list,
+                    permanent,
+                )
+                .await?
+            }
+            Command::Rename {
+                ref folder,
+                ref matching,
+                regex,
+                ref strip_prefix,
+                ref replace,
+                ref with,
+                normalize_whitespace,
+            } => {
+                let replace = match (replace, with) {
+                    (Some(pattern), Some(template)) => {
+                        Some((Regex::new(pattern)?, template.clone()))
+                    }
+                    _ => None,
+                };
+                if strip_prefix.is_none() && replace.is_none() && !normalize_whitespace {
+                    bail!("Give --strip-prefix, --replace or --normalize-whitespace");
+                }
+                let pattern = matching
+                    .as_deref()
+                    .map(|p| search::Pattern::new(p, regex))
+                    .transpose()?;
+                rename::rename(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    &mut audit()?,
+                    &rename::Transform {
+                        strip_prefix: strip_prefix.clone(),
+                        replace,
+                        normalize_whitespace,
+                    },
+                    &rename::Scope {
+                        folder_id: folder.as_deref(),
+                        pattern: pattern.as_ref(),
+                    },
+                )
+                .await?
+            }
+            Command::Overview {
+                owners,
+                include_trashed,
+                allow_partial,
+            } => show_overview(store, owners, include_trashed, allow_partial)?,
+            Command::AdoptOrphans {
+                ref target_id,
+                confirm,
+            } => {
+                orphans::adopt_orphans(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    target_id,
+                    &orphans::Options { confirm },
+                    &mut audit()?,
+                )
+                .await?
+            }
+            Command::Tree {
+                min_size,
+                max_depth,
+                sort,
+                show_counts,
+                shortcut_sizes,
+                multi_parent,
+                include_trashed,
+                bars,
+                format,
+                ref marks,
+                allow_partial,
+            } => tree::show_tree(
+                store,
+                &tree::Options {
+                    min_size,
+                    max_depth,
+                    sort,
+                    show_counts,
+                    shortcut_sizes,
+                    multi_parent,
+                    include_trashed,
+                    bars,
+                    format,
+                    marks: marks.filter(),
+                    allow_partial,
+                },
+            )?,
+            Command::BrokenShortcuts {
+                resolve,
+                concurrency,
+            } => {
+                let drive = if resolve { Some(drive().await?) } else { None };
+                let resolve = drive.as_ref().map(|api| shortcuts::Resolve {
+                    api,
+                    cancel: &cancel,
+                    retry: &retry,
+                    concurrency,
+                });
+                shortcuts::show_broken_shortcuts(store, resolve).await?
+            }
+            Command::Browse => browse::browse(store)?,
+            Command::CheckDuplicates { ref id } => check_duplicates(store, id)?,
+            Command::Duplicates { ref merge } if merge.is_empty() => show_duplicates(store)?,
+            Command::Duplicates { merge: ref others } => {
+                let mut accounts = vec![merge::Account {
+                    name: args.paths.profile().unwrap_or("default").to_owned(),
+                    store: store.clone(),
+                }];
+                for value in others {
+                    let path = Path::new(value);
+                    accounts.push(if path.exists() {
+                        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                        merge::Account::open(name.into_owned(), path)
+                    } else {
+                        merge::Account::open(
+                            value.clone(),
+                            &config::data_dir_of(&args.paths, value)?,
+                        )
+                    });
+                }
+                merge::show_cross_account_duplicates(&accounts)?
+            }
+            Command::Dashboard { top, days } => {
+                let accounts: Vec<_> = config::all_data_dirs(&args.paths)?
+                    .into_iter()
+                    .map(|(name, dir)| merge::Account::open(name, &dir))
+                    .collect();
+                dashboard::show_dashboard(&accounts, top, days)?
+            }
+            Command::Dedupe { apply } => {
+                let mut audit = AuditLog::open(store, args.dry_run || !apply)?;
+                dedupe::dedupe(&drive().await?, &cancel, store, &retry, &mut audit).await?
+            }
+            Command::Undo { count } => {
+                audit::undo(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    &mut audit()?,
+                    count,
+                )
+                .await?
+            }
+            Command::Path { ref query } => show_paths(store, query)?,
+            Command::ByType {
+                mismatched_types: true,
+                ..
+            } => types::show_mismatched_types(store)?,
+            Command::ByType { extension, .. } => types::show_by_type(store, extension)?,
+            Command::Computers => computers::show_computers(store)?,
+            Command::Fsck { repair } => fsck::fsck(store, repair)?,
+            Command::Histogram {
+                ref folders,
+                ref extensions,
+            } => histogram::show_histogram(store, folders, extensions)?,
+            Command::Stale { years, min_size } => stale::show_stale(store, years, min_size)?,
+            Command::Collisions { ignore_case } => collisions::show_collisions(store, ignore_case)?,
+            Command::FsAudit { max_path, max_name } => {
+                fs_audit::show_fs_audit(store, &fs_audit::Options { max_path, max_name })?
+            }
+            Command::Empty { clean: false, .. } => empty::show_empty(store)?,
+            Command::Empty { apply, .. } => {
+                let mut audit = AuditLog::open(store, args.dry_run || !apply)?;
+                empty::clean_empty(&drive().await?, &cancel, store, &retry, &mut audit).await?
+            }
+            Command::Search {
+                ref pattern,
+                regex,
+                full_path,
+                ref mime_type,
+                min_size,
+                max_size,
+                ref marks,
+                ref owner,
+            } => search::search(
+                store,
+                &search::Pattern::new(pattern, regex)?,
+                &search::Filters {
+                    full_path,
+                    mime_type: mime_type.as_deref(),
+                    min_size,
+                    max_size,
+                    marks: marks.filter(),
+                    owner: owner.as_deref(),
+                },
+            )?,
+            Command::SharingReport { ref domain } => {
+                permissions::show_sharing(store, domain.as_deref())?
+            }
+            Command::Public => permissions::show_public(store)?,
+            Command::SharedWithMe { top } => shared_with_me::show_shared_with_me(store, top)?,
+            Command::Diff { ref old, ref new } => diff::diff(
+                &history::resolve_path(store, old)?,
+                &history::resolve_path(store, new)?,
+            )?,
+            Command::Snapshots => history::show_snapshots(store)?,
+            Command::Trend { format, top } => trend::show_trend(store, format, top)?,
+            Command::Report {
+                format,
+                ref output,
+                min_size,
+                depth,
+            } => report::write_report(store, format, output, min_size, depth)?,
+            Command::Export {
+                format,
+                ref output,
+                ref folder,
+                ref mime_type,
+                min_size,
+                max_size,
+            } => {
+                let selection = export::Selection {
+                    folder_id: folder.as_deref(),
+                    mime_type: mime_type.as_deref(),
+                    min_size,
+                    max_size,
+                };
+                export::export(store, format, &selection, output.as_deref())?
+            }
+            Command::Profiles {
+                command: ProfilesCommand::List,
+            } => config::show_profiles(&args.paths)?,
+            Command::Download {
+                ref folder_id,
+                ref local_path,
+                live,
+                concurrency,
+                export_native,
+            } => {
+                download::download_folder(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    folder_id,
+                    local_path,
+                    &download::Options {
+                        live,
+                        concurrency,
+                        export_native,
+                        delete_extraneous: false,
+                    },
+                )
+                .await?
+            }
+            Command::VerifyLocal {
+                ref folder_id,
+                ref local_path,
+                threads,
+            } => verify_local::verify_local(
+                store,
+                &cancel,
+                folder_id,
+                local_path,
+                &verify_local::Options {
+                    threads: threads.unwrap_or_else(|| {
+                        std::thread::available_parallelism().map_or(4, |n| n.get())
+                    }),
+                },
+            )?,
+            Command::Get {
+                ref target,
+                ref dest,
+                export_native,
+            } => {
+                download::download_one(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    target,
+                    dest.as_deref(),
+                    export_native,
+                )
+                .await?
+            }
+            Command::TransferOwnership {
+                ref folder_id,
+                ref email,
+                live,
+                concurrency,
+                notify,
+            } => {
+                transfer::transfer_ownership(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    &mut audit()?,
+                    folder_id,
+                    &transfer::Options {
+                        email: email.clone(),
+                        live,
+                        concurrency,
+                        notify,
+                    },
+                )
+                .await?
+            }
+            Command::Upload {
+                ref local_path,
+                ref folder_id,
+            } => {
+                upload::upload(
+                    &drive().await?,
+                    &cancel,
+                    &retry,
+                    &mut audit()?,
+                    local_path,
+                    folder_id,
+                )
+                .await?
+            }
+            Command::Mirror {
+                ref folder_id,
+                ref local_path,
+                delete_extraneous,
+                concurrency,
+                export_native,
+            } => {
+                download::download_folder(
+                    &drive().await?,
+                    &cancel,
+                    store,
+                    &retry,
+                    folder_id,
+                    local_path,
+                    &download::Options {
+                        live: true,
+                        concurrency,
+                        export_native,
+                        delete_extraneous,
+                    },
+                )
+                .await?
+            }
+        }
+        anyhow::Ok(())
     }
+    .await;
+    retry.budget.report();
 
-    Ok(())
+    if let Some((command, url, format)) = notification {
+        let errors = retry.budget.stats().errors;
+        let summary = notify::Summary::new(command, store, before, errors, &result);
+        if let Err(e) = notify::send(&client, url, format, &summary).await {
+            error!("Failed to send the notification to {url}: {e:#}");
+        }
+    }
+    let sign_in_required = SIGN_IN_REQUIRED.load(Ordering::SeqCst);
+    if args.non_interactive {
+        let stats = retry.budget.stats();
+        let status = Status {
+            command: matches.subcommand_name().unwrap_or_default().to_owned(),
+            started,
+            finished: Utc::now(),
+            exit_code: match result {
+                Ok(()) => 0,
+                Err(_) if sign_in_required => EXIT_SIGN_IN_REQUIRED,
+                Err(_) => 1,
+            },
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            api_calls: stats.calls,
+            api_errors: stats.errors,
+        };
+        if let Err(e) = status.save(store) {
+            error!("Failed to save the status: {e:#}");
+        }
+    }
+    if let Err(ref e) = result {
+        if sign_in_required {
+            error!("{e:#}");
+            std::process::exit(EXIT_SIGN_IN_REQUIRED);
+        }
+    }
+    result
 }
 
+/// Exit code when signing in is needed in non-interactive mode, so that schedulers can tell it
+/// from other failures
+const EXIT_SIGN_IN_REQUIRED: i32 = 3;
+
+/// Set once the authenticator has asked for the consent of the user
+static SIGN_IN_REQUIRED: AtomicBool = AtomicBool::new(false);
+
+/// Crawls Google Drive into a local snapshot to tell what uses the storage quota
 #[derive(Parser)]
 struct Args {
-    #[clap(long)]
-    list: bool,
-    #[clap(long)]
-    show_overview: bool,
-    #[clap(long)]
-    tree: bool,
-    #[clap(long)]
-    check_duplicates: Option<String>,
+    #[clap(subcommand)]
+    command: Command,
+    #[clap(long, global = true, value_enum, default_value_t = StoreKind::Json)]
+    store: StoreKind,
+    /// zstd level to save the JSON snapshots with, as `file-list.json.zst`.  0 saves them
+    /// uncompressed.  Either kind is loaded regardless.
+    #[clap(long, global = true, default_value_t = 3)]
+    compress_level: i32,
+    /// Number of complete snapshots to keep under `snapshots/` in the data directory
+    #[clap(long, global = true, default_value_t = 10)]
+    keep_snapshots: usize,
+    /// Analyze an archived snapshot of My Drive instead of the current one: `latest`, a date
+    /// like `2023-06-01` or the name of the snapshot
+    #[clap(long, global = true)]
+    snapshot: Option<String>,
+    /// Number of attempts for each API call before giving up
+    #[clap(long, global = true, default_value_t = 5)]
+    max_attempts: u32,
+    /// Seconds an API call may take before it is abandoned and retried, where 0 waits forever.
+    /// Downloads and uploads of content are not limited.
+    #[clap(long, global = true, default_value_t = 120)]
+    request_timeout: u64,
+    /// Limit on the rate of API calls, to stay under the quota during long runs
+    #[clap(long, global = true)]
+    max_requests_per_sec: Option<f64>,
+    /// Limit on the transfer rate of downloads and uploads in bytes per second, like `512k`, or
+    /// a schedule by the local time like `"08:00,512k 23:00,off"`
+    #[clap(long, global = true, value_parser = Schedule::parse)]
+    bwlimit: Option<Schedule>,
+    /// Only show what mutating commands would do.  Otherwise their changes are recorded in
+    /// `audit-log.jsonl` in the data directory.
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// Allow the commands that modify files, and ask for the scopes they need.  Otherwise only
+    /// read-only scopes are requested and such commands refuse to run except with --dry-run.
+    #[clap(long, global = true)]
+    read_write: bool,
+    /// Run even if the lock file says another run is using the same data directory
+    #[clap(long, global = true)]
+    force: bool,
+    /// Run unattended, as from cron: fail with exit code 3 instead of opening the browser when
+    /// signing in is needed, log only warnings and errors to the terminal without progress bars,
+    /// and write the outcome to `status.json` in the data directory.
+    #[clap(long, global = true)]
+    non_interactive: bool,
+    /// Log more to the terminal and the log file: debug messages, or trace messages with -vv
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Log less to the terminal: only warnings and errors, or only errors with -qq
+    #[clap(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        conflicts_with = "verbose"
+    )]
+    quiet: u8,
+    /// Format of the log in the terminal and the log file
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Format of the results of the reports.  Progress and other text go to the standard error
+    /// when it is JSON.
+    #[clap(long = "output", global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+    #[clap(flatten)]
+    connect: ConnectArgs,
+    #[clap(flatten)]
+    auth: AuthArgs,
+    #[clap(flatten)]
+    paths: config::PathArgs,
 }
 
-#[derive(Serialize, Deserialize)]
-struct FileList {
-    files: Vec<File>,
-    #[serde(rename = "nextPageToken")]
-    next_page_token: Option<String>,
-}
-#[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
-struct File {
-    id: String,
-    #[serde(rename = "mimeType")]
-    mime_type: String,
-    #[serde(deserialize_with = "null_to_default")]
-    parents: Vec<String>,
-    name: String,
-    #[serde(rename = "quotaBytesUsed")]
-    #[serde_as(as = "Option<DisplayFromStr>")]
-    quota_bytes_used: Option<u64>,
-    #[serde_as(as = "Option<DisplayFromStr>")]
-    size: Option<u64>,
-    #[serde(rename = "sha256Checksum")]
-    sha256_checksum: Option<String>,
-}
-fn null_to_default<'de, D, T>(d: D) -> Result<T, D::Error>
-where
-    D: Deserializer<'de>,
-    T: Default + Deserialize<'de>,
-{
-    let opt = Option::deserialize(d)?;
-    let val = opt.unwrap_or_default();
-    Ok(val)
+#[derive(Subcommand)]
+enum Command {
+    /// Crawl the files owned by me, resuming the previous crawl if any
+    Crawl {
+        /// Crawl the files matching this search query instead, like `mimeType contains 'video/'`.
+        /// Such a snapshot cannot be synced, but only crawled again.
+        #[clap(long, default_value = "'me' in owners", conflicts_with = "folder")]
+        query: String,
+        /// Split the files into this many ranges of modified time and crawl them concurrently.
+        /// Such a crawl starts from scratch and is saved only once all ranges are complete.
+        #[clap(long)]
+        partitions: Option<usize>,
+        /// Number of ranges crawled at the same time
+        #[clap(long, default_value_t = 4, requires = "partitions")]
+        concurrency: usize,
+        /// Crawl only this folder and its descendants from scratch.  Such a snapshot cannot be
+        /// synced, but only crawled again.
+        #[clap(long, conflicts_with = "partitions")]
+        folder: Option<String>,
+        /// Number of files fetched per request
+        #[clap(long, default_value_t = 1000, value_parser = clap::value_parser!(i32).range(1..=1000))]
+        page_size: i32,
+        /// Fields of the files to keep on top of the built-in ones, like
+        /// `description,viewedByMeTime`.  Syncing keeps requesting them.
+        #[clap(long, value_delimiter = ',')]
+        fields: Vec<String>,
+        /// Ids of the Drive Labels to record on the files, for `--label`.  Syncing keeps
+        /// requesting them.
+        #[clap(
+            long,
+            value_name = "LABEL_IDS",
+            value_delimiter = ',',
+            conflicts_with = "shared_with_me"
+        )]
+        labels: Vec<String>,
+        /// Spaces to crawl, like `drive,appDataFolder,photos`, so that the quota used by app data
+        /// and legacy Photos uploads shows up in `overview`.  Only the app data of this app is
+        /// visible.  Syncing keeps following them.
+        #[clap(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "drive",
+            conflicts_with_all = ["folder", "shared_with_me"]
+        )]
+        spaces: Vec<Space>,
+        /// Crawl the items shared with me into `shared-with-me.json` instead, for
+        /// `shared-with-me`.  They are kept apart from the snapshot.
+        #[clap(long, conflicts_with_all = ["query", "partitions", "folder"])]
+        shared_with_me: bool,
+        #[clap(flatten)]
+        notify: NotifyArgs,
+    },
+    /// Apply the changes since the last crawl or sync to the snapshot, and append them to
+    /// `changes.jsonl` in the data directory for other tools to follow
+    Sync {
+        #[clap(flatten)]
+        notify: NotifyArgs,
+    },
+    /// Fetch the names and owners of the folders outside of the snapshot that files, including
+    /// those shared with me, are placed in, so that reports show them instead of the ids
+    ResolveParents {
+        /// Number of folders fetched at the same time
+        #[clap(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Keep syncing until ctrl-c, logging each change
+    Watch {
+        /// Seconds between syncs, even when notifications are received
+        #[clap(long, default_value_t = 60)]
+        interval: u64,
+        /// Address to receive the push notifications of Drive on, like `127.0.0.1:8080`
+        #[clap(long, requires = "webhook_url")]
+        listen: Option<SocketAddr>,
+        /// Public HTTPS URL forwarded to the address given by --listen
+        #[clap(long, requires = "listen")]
+        webhook_url: Option<String>,
+        /// Address to serve the metrics for Prometheus on, like `127.0.0.1:9100`
+        #[clap(long)]
+        metrics: Option<SocketAddr>,
+    },
+    /// Crawl the shared drives
+    Drives,
+    /// Fetch who each of my files and the files in shared drives are shared with
+    Permissions {
+        /// Number of files in shared drives whose permissions are fetched at the same time
+        #[clap(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Compare the storage quota reported by the API with the crawled files, recording it for
+    /// `dashboard`
+    Quota,
+    /// Summarize the latest snapshots of all profiles side by side, with the quota recorded by
+    /// `quota` of each
+    Dashboard {
+        /// Number of the largest top-level folders shown per account
+        #[clap(long, default_value_t = 3)]
+        top: usize,
+        /// Days over which the growth is measured, from the archived snapshots
+        #[clap(long, default_value_t = 30)]
+        days: u32,
+    },
+    /// List the trashed items with their quota usage and how long ago they were trashed
+    Trash,
+    /// Permanently delete all trashed files
+    EmptyTrash,
+    /// Trash the files listed by id in a text file, one per line, or in the `id` column of a CSV
+    /// such as one written by `export`
+    DeleteFrom {
+        list: PathBuf,
+        /// Permanently delete them instead, which cannot be undone
+        #[clap(long)]
+        permanent: bool,
+    },
+    /// List the revisions of files using much more quota than their size
+    Revisions {
+        /// Minimum excess of quota usage over the size, like `100M`
+        #[clap(value_parser = parse_size)]
+        threshold: u64,
+        /// Delete all revisions but the head, except for those kept forever
+        #[clap(long)]
+        purge: bool,
+        /// Number of files whose revisions are fetched at the same time
+        #[clap(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Show everything about one file: the metadata, paths, permissions, revisions and
+    /// capabilities
+    Inspect { id: String },
+    /// Show who recently created, uploaded or moved items into the folder, using the Drive
+    /// Activity API
+    Activity {
+        folder_id: String,
+        /// How far back to look
+        #[clap(long, default_value_t = 7)]
+        days: i64,
+        /// Most activities shown, the latest first
+        #[clap(long, default_value_t = 200)]
+        limit: usize,
+    },
+    /// Rename the items in a folder or matching a pattern, showing the new names and asking
+    /// before applying them
+    Rename {
+        /// Only the items under this folder, at any depth
+        #[clap(long, required_unless_present = "matching")]
+        folder: Option<String>,
+        /// Only the items whose names match this glob pattern, or regular expression with --regex
+        #[clap(long)]
+        matching: Option<String>,
+        #[clap(long, requires = "matching")]
+        regex: bool,
+        /// Remove this from the start of the names
+        #[clap(long)]
+        strip_prefix: Option<String>,
+        /// Regular expression whose matches in the names are replaced with --with
+        #[clap(long, requires = "with")]
+        replace: Option<String>,
+        /// Replacement of --replace, where `$1` refers to the first group
+        #[clap(long, requires = "replace")]
+        with: Option<String>,
+        /// Collapse runs of whitespace into a single space and trim the names
+        #[clap(long)]
+        normalize_whitespace: bool,
+    },
+    /// Show the total usage and files with unusual parents, along with how and when the files were
+    /// crawled (not recorded by `--store sqlite`)
+    Overview {
+        /// Also break down the usage per owner
+        #[clap(long)]
+        owners: bool,
+        /// Count the files in the trash too
+        #[clap(long)]
+        include_trashed: bool,
+        /// Analyze an incomplete snapshot anyway, whose totals are too small
+        #[clap(long)]
+        allow_partial: bool,
+    },
+    /// Move the files without a parent or in folders of others, as listed by `overview`, into
+    /// the folder.  The folders of others have to be looked up by `resolve-parents` first.
+    AdoptOrphans {
+        target_id: String,
+        /// Ask before moving each file.  Nothing is asked with --dry-run.
+        #[clap(long)]
+        confirm: bool,
+    },
+    /// Show the folder tree with the cumulative sizes
+    Tree {
+        /// Hide the items using less than this, like `50M`
+        #[clap(long, value_parser = parse_size, default_value = "50M")]
+        min_size: u64,
+        /// Show only this many levels below the roots
+        #[clap(long)]
+        max_depth: Option<usize>,
+        #[clap(long, value_enum, default_value_t = tree::SortKey::Size)]
+        sort: tree::SortKey,
+        /// Show the number of items in each folder
+        #[clap(long)]
+        show_counts: bool,
+        /// Show the size of the target next to each shortcut, without adding it to the totals
+        #[clap(long)]
+        shortcut_sizes: bool,
+        /// Where to count the legacy files placed in several folders
+        #[clap(long, value_enum, default_value_t = tree::MultiParent::First)]
+        multi_parent: tree::MultiParent,
+        /// Show the files in the trash too
+        #[clap(long)]
+        include_trashed: bool,
+        /// Show a bar proportional to the share of the parent next to the percentages of the
+        /// parent and of the total
+        #[clap(long)]
+        bars: bool,
+        #[clap(long, value_enum, default_value_t = tree::TreeFormat::Text)]
+        format: tree::TreeFormat,
+        #[clap(flatten)]
+        marks: MarkArgs,
+        /// Show the tree of an incomplete snapshot anyway, whose sizes are too small
+        #[clap(long)]
+        allow_partial: bool,
+    },
+    /// List the shortcuts whose targets are missing from the snapshot
+    BrokenShortcuts {
+        /// Ask Drive whether each target still exists, and who owns it
+        #[clap(long)]
+        resolve: bool,
+        /// Number of targets looked up at the same time
+        #[clap(long, default_value_t = 8, requires = "resolve")]
+        concurrency: usize,
+    },
+    /// Explore the folder tree interactively, ncdu-style
+    Browse,
+    /// List the files under the folder that have no copy elsewhere under its parent
+    CheckDuplicates { id: String },
+    /// List clusters of identical files, sorted by reclaimable space
+    Duplicates {
+        /// Also load the snapshots of these profiles, or snapshot files or data directories, and
+        /// list only the files stored in more than one account
+        #[clap(long, num_args = 1..)]
+        merge: Vec<String>,
+    },
+    /// Replace duplicates with shortcuts to one of the copies, trashing the others
+    Dedupe {
+        /// Actually make the changes, recording them in `dedupe-log.jsonl`; only previews them
+        /// otherwise
+        #[clap(long)]
+        apply: bool,
+    },
+    /// Revert the last mutations recorded in the audit log, such as trashing and moving files
+    Undo {
+        /// Number of mutations to revert
+        #[clap(default_value_t = 1)]
+        count: usize,
+    },
+    /// Find files in the snapshot by name or path
+    Search {
+        /// Glob pattern like `*.mp4`, or a regular expression with --regex
+        pattern: String,
+        #[clap(long)]
+        regex: bool,
+        /// Match against the full path like `/folder/file` instead of the name
+        #[clap(long)]
+        full_path: bool,
+        /// Prefix of the mime type, like `image/`
+        #[clap(long)]
+        mime_type: Option<String>,
+        #[clap(long, value_parser = parse_size)]
+        min_size: Option<u64>,
+        #[clap(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+        #[clap(flatten)]
+        marks: MarkArgs,
+        /// Email address of an owner, such as the colleague whose files to find
+        #[clap(long, value_name = "EMAIL")]
+        owner: Option<String>,
+    },
+    /// Show where the file is, through all of its parents and shortcuts
+    Path {
+        /// Id or exact name of the file
+        query: String,
+    },
+    /// List shared files grouped by audience and folder, from the result of `permissions`
+    SharingReport {
+        /// Domain of the organization; sharing with other domains is reported separately
+        #[clap(long)]
+        domain: Option<String>,
+    },
+    /// List the files accessible to anyone with the link, largest first, with the role granted
+    /// and the expiration, from the permissions fetched by `permissions`
+    Public,
+    /// Show who shares the most items with me and the folders they are in, after
+    /// `crawl --shared-with-me`
+    SharedWithMe {
+        /// Number of rows in each table
+        #[clap(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Break down the usage by mime type, largest first
+    ByType {
+        /// Group by the extension of the name instead
+        #[clap(long)]
+        extension: bool,
+        /// List the files whose extension disagrees with the detected mime type instead
+        #[clap(long, conflicts_with = "extension")]
+        mismatched_types: bool,
+    },
+    /// Show the usage of each computer backed up by Drive for Desktop, after resolve-parents has
+    /// found them
+    Computers,
+    /// Check the snapshots for files listed twice, pages fetched twice by resumed crawls, files
+    /// that are their own parents and entries that fail to parse
+    Fsck {
+        /// Rewrite the snapshots with problems without them, keeping the previous ones as `.bak`
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Show how many files and how much of the usage fall into each size range, to tell many small
+    /// files from a few huge ones
+    Histogram {
+        /// Also show the histogram of the files inside this folder, by id.  Can be repeated.
+        #[clap(long = "folder")]
+        folders: Vec<String>,
+        /// Also show the histogram of the files with this extension, like `mp4`.  Can be repeated.
+        #[clap(long = "extension")]
+        extensions: Vec<String>,
+    },
+    /// List the items with the same name in the same folder, which break most sync tools
+    Collisions {
+        /// Also count names differing only in case, as case-insensitive file systems do
+        #[clap(long)]
+        ignore_case: bool,
+    },
+    /// List the names and paths that would break when mirrored to Windows, macOS or a NAS
+    FsAudit {
+        /// Flag the paths longer than this many characters
+        #[clap(long, default_value_t = 260)]
+        max_path: usize,
+        /// Flag the names longer than this many bytes
+        #[clap(long, default_value_t = 255)]
+        max_name: usize,
+    },
+    /// List the folders containing nothing but empty folders, and the files of zero bytes
+    Empty {
+        /// Trash them, only previewing unless `--apply` is also given
+        #[clap(long)]
+        clean: bool,
+        /// Actually trash them, recording it in the audit log
+        #[clap(long, requires = "clean")]
+        apply: bool,
+    },
+    /// List the large files untouched for years, grouped by folder
+    Stale {
+        #[clap(default_value_t = 2)]
+        years: u32,
+        #[clap(long, value_parser = parse_size, default_value = "10M")]
+        min_size: u64,
+    },
+    /// Compare two saved JSON snapshots, given by path or as with --snapshot
+    Diff { old: PathBuf, new: PathBuf },
+    /// List the archived snapshots
+    Snapshots,
+    /// Show how the usage of the top-level folders changed across the archived snapshots
+    Trend {
+        #[clap(long, value_enum, default_value_t = trend::TrendFormat::Sparkline)]
+        format: trend::TrendFormat,
+        /// Number of folders shown, those that grew the most first
+        #[clap(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Render the folder tree as a treemap that can be shared or as a per-folder CSV, or archive
+    /// the standard reports into a directory
+    Report {
+        #[clap(value_enum)]
+        format: report::ReportFormat,
+        output: PathBuf,
+        /// Items smaller than this are merged into one per folder
+        #[clap(long, value_parser = parse_size, default_value = "1M")]
+        min_size: u64,
+        /// Deepest folders listed in `folder-csv`, the roots being at depth 0
+        #[clap(long, default_value_t = 2)]
+        depth: usize,
+    },
+    /// Write the files out for other tools
+    Export {
+        #[clap(value_enum)]
+        format: export::ExportFormat,
+        /// Defaults to the standard output
+        output: Option<PathBuf>,
+        /// Export only the files under the folder, at any depth
+        #[clap(long)]
+        folder: Option<String>,
+        /// Prefix of the mime type, like `image/`
+        #[clap(long)]
+        mime_type: Option<String>,
+        #[clap(long, value_parser = parse_size)]
+        min_size: Option<u64>,
+        #[clap(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+    },
+    /// Manage the profiles selected with `--profile`
+    Profiles {
+        #[clap(subcommand)]
+        command: ProfilesCommand,
+    },
+    /// Download all files under the folder into the local path
+    Download {
+        folder_id: String,
+        local_path: PathBuf,
+        /// Fetch the folder tree from the API instead of the snapshot
+        #[clap(long)]
+        live: bool,
+        /// Number of files downloaded at the same time
+        #[clap(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Save converted copies of Google Docs, Sheets, Slides and Drawings, which are skipped
+        /// otherwise
+        #[clap(long, value_enum)]
+        export_native: Option<download::ExportFormat>,
+    },
+    /// Check a local copy of the folder, such as a backup, against the checksums and sizes in the
+    /// snapshot without downloading anything, listing the missing, extra and corrupted files
+    VerifyLocal {
+        folder_id: String,
+        local_path: PathBuf,
+        /// Number of files hashed at the same time; the number of CPUs by default
+        #[clap(long)]
+        threads: Option<usize>,
+    },
+    /// Download a single file, given by id or by a path like `/folder/file` in the snapshot,
+    /// verifying its checksum
+    Get {
+        target: String,
+        /// File or directory to save into; the current directory by default
+        dest: Option<PathBuf>,
+        /// Format Google Docs, Sheets, Slides and Drawings are converted to
+        #[clap(long, value_enum, default_value_t = download::ExportFormat::Docx)]
+        export_native: download::ExportFormat,
+    },
+    /// Ask the new owner to take over every file I own under the folder.  Interrupted runs resume
+    /// from where they stopped.
+    TransferOwnership {
+        folder_id: String,
+        email: String,
+        /// Fetch the folder tree from the API instead of the snapshot
+        #[clap(long)]
+        live: bool,
+        /// Number of requests made at the same time
+        #[clap(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Email the new owner about every file, which may mean thousands of emails
+        #[clap(long)]
+        notify: bool,
+    },
+    /// Upload the file, or the directory with its contents, into the folder, skipping the files
+    /// already there with the same name and content
+    Upload {
+        local_path: PathBuf,
+        folder_id: String,
+    },
+    /// Make the local path match the folder on Drive as of now, downloading only the files added
+    /// or changed since the last run and moving those moved or renamed
+    Mirror {
+        folder_id: String,
+        local_path: PathBuf,
+        /// Delete the local files and folders that are not on Drive, such as those deleted since
+        /// the last run
+        #[clap(long)]
+        delete_extraneous: bool,
+        /// Number of files downloaded at the same time
+        #[clap(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Save converted copies of Google Docs, Sheets, Slides and Drawings, which are skipped
+        /// otherwise
+        #[clap(long, value_enum)]
+        export_native: Option<download::ExportFormat>,
+    },
 }
-impl TryFrom<google_drive3::api::FileList> for FileList {
-    type Error = anyhow::Error;
-    fn try_from(value: google_drive3::api::FileList) -> anyhow::Result<Self> {
-        Ok(serde_json::from_str(&serde_json::to_string(&value)?)?)
+
+impl Command {
+    /// Whether the command modifies files on Drive, as opposed to only reading them
+    fn mutates(&self) -> bool {
+        match *self {
+            Command::EmptyTrash
+            | Command::DeleteFrom { .. }
+            | Command::AdoptOrphans { .. }
+            | Command::Rename { .. }
+            | Command::Undo { .. }
+            | Command::TransferOwnership { .. }
+            | Command::Upload { .. } => true,
+            Command::Revisions { purge, .. } => purge,
+            Command::Dedupe { apply } => apply,
+            Command::Empty { apply, .. } => apply,
+            _ => false,
+        }
+    }
+    /// Whether the command writes the snapshot or the state next to it, which two runs must not
+    /// do at the same time
+    fn writes_store(&self) -> bool {
+        match *self {
+            Command::Crawl { .. }
+            | Command::Sync { .. }
+            | Command::ResolveParents { .. }
+            | Command::Watch { .. }
+            | Command::Drives
+            | Command::Quota
+            | Command::Permissions { .. }
+            | Command::Revisions { .. } => true,
+            Command::BrokenShortcuts { resolve, .. } => resolve,
+            Command::Fsck { repair } => repair,
+            _ => self.mutates(),
+        }
     }
 }
 
-fn init_logger() -> anyhow::Result<()> {
-    use simplelog::*;
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            fs_err::File::create("ignore/log.log").unwrap(),
-        ),
-    ])?;
-    Ok(())
+#[derive(Subcommand)]
+enum ProfilesCommand {
+    /// List the profiles along with whether they are signed in
+    List,
 }
 
-fn init_ctrlc() -> anyhow::Result<mpsc::Receiver<()>> {
-    let (sender, receiver) = mpsc::channel();
-    ctrlc::set_handler(move || {
-        warn!("Ctrl-C detected!");
-        if let Err(e) = sender.send(()) {
-            error!("Failed to send ctrl-c signal.  Main thread dead?  {e}");
-        }
-    })?;
-    Ok(receiver)
+#[derive(clap::Args)]
+struct NotifyArgs {
+    /// URL to post a summary to when the command finishes or fails, for unattended runs
+    #[clap(long)]
+    notify: Option<String>,
+    #[clap(long, value_enum, default_value_t = notify::NotifyFormat::Json, requires = "notify")]
+    notify_format: notify::NotifyFormat,
 }
 
-type Drive = DriveHub<HttpsConnector<HttpConnector>>;
-async fn init_drive() -> anyhow::Result<Drive> {
-    let hyper = hyper::Client::builder().build(
-        HttpsConnectorBuilder::new()
-            .with_native_roots()
-            .https_only()
-            .enable_http2()
-            .build(),
-    );
-    let auth = {
-        let secret = oauth2::read_application_secret("ignore/clientsecret.json").await?;
-        InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-            .persist_tokens_to_disk("ignore/tokencache.json")
-            .build()
-            .await?
-    };
-    Ok(DriveHub::new(hyper, auth))
+#[derive(clap::Args)]
+struct MarkArgs {
+    /// Count only the starred files
+    #[clap(long)]
+    starred: bool,
+    /// Count only the files with the label, given by the id recorded with `crawl --labels`.  Label
+    /// names are not resolved.
+    #[clap(long, value_name = "LABEL_ID")]
+    label: Option<String>,
 }
-
-fn restore_data(allow_not_found: bool) -> anyhow::Result<Vec<FileList>> {
-    Ok(match fs_err::File::open("ignore/file-list.json") {
-        Ok(file) => {
-            let res: Vec<FileList> = serde_json::from_reader(BufReader::new(file))?;
-            info!("Loaded {} pages", res.len());
-            res
+impl MarkArgs {
+    fn filter(&self) -> MarkFilter {
+        MarkFilter {
+            starred: self.starred,
+            label: self.label.clone(),
         }
-        Err(error) if error.kind() == std::io::ErrorKind::NotFound && allow_not_found => {
-            info!("Starting from scratch: {error} (not found)");
-            vec![]
-        }
-        Err(e) => Err(e)?,
-    })
-}
-fn save_data(list: &[FileList]) -> anyhow::Result<()> {
-    (|| {
-        let path = "ignore/file-list.json";
-        let file = fs_err::File::create(path)?;
-        serde_json::to_writer(BufWriter::new(file), list)?;
-        info!("Saved list to {path:?}");
-        anyhow::Ok(())
-    })()
-    .context(
-        "Unfortunately, we failed to save data and the accumulated data was permanently losed.",
-    )
+    }
 }
 
-async fn list_files(drive: &Drive, ctrlc_handler: &mpsc::Receiver<()>) -> anyhow::Result<()> {
-    let mut list = restore_data(true)?;
-    loop {
-        let token = match list.last() {
-            None => "",
-            Some(last) => match &last.next_page_token {
-                None => {
-                    save_data(&list)?;
-                    info!("Complete.");
-                    break;
-                }
-                Some(ref token) => token,
-            },
-        };
-        info!("Page {}", list.len());
-        let Ok(res) = drive
-            .files()
-            .list()
-            // Includes all owned files plus shared roots (not shared children)?
-            .corpora("user") // "user" by default, but setting it explicitly
-            .q("'me' in owners")
-            .page_token(token)
-            .param("fields", "nextPageToken,files(id,mimeType,parents,name,size,quotaBytesUsed,sha256Checksum)")
-            .doit()
-            .await else {
-            error!("Aborting due to an API error.");
-            break save_data(&list)?
-        };
-        let Ok(res) = FileList::try_from(res.1) else {
-            error!("Aborting due to a conversion error.");
-            break save_data(&list)?
-        };
-        list.push(res);
-        if let Ok(()) = ctrlc_handler.try_recv() {
-            info!("Received ctrl-c.  Saving before terminating.");
-            save_data(&list)?;
-            break;
-        }
-        if list.len() % 10 == 0 {
-            save_data(&list)?;
-        }
-    }
-    Ok(())
+#[derive(clap::Args)]
+struct ConnectArgs {
+    /// PEM file of root certificates to trust besides those of the OS, such as that of a proxy
+    /// intercepting TLS.  The proxy is taken from `HTTPS_PROXY`, bypassed for `NO_PROXY`.
+    #[clap(long, global = true)]
+    ca_bundle: Option<PathBuf>,
+    /// Speak only HTTP/1.1, for proxies and middleboxes that break HTTP/2
+    #[clap(long, global = true)]
+    http1: bool,
+    /// Seconds to wait for a connection to be established
+    #[clap(long, global = true, default_value_t = 30)]
+    connect_timeout: u64,
+    /// Seconds an idle connection is kept for reuse
+    #[clap(long, global = true, default_value_t = 90)]
+    idle_timeout: u64,
+    /// Most idle connections kept per host; unlimited by default
+    #[clap(long, global = true)]
+    pool_size: Option<usize>,
 }
 
-fn show_overview() -> anyhow::Result<()> {
-    let list = restore_data(false)?;
-    let files: Vec<_> = list.into_iter().flat_map(|e| e.files).collect();
-    let sum: u64 = files.iter().filter_map(|f| f.quota_bytes_used).sum();
-    println!("{sum}");
+#[derive(clap::Args)]
+struct AuthArgs {
+    #[clap(long, global = true, value_enum, default_value_t = AuthKind::Installed)]
+    auth: AuthKind,
+    /// User to impersonate through domain-wide delegation of the service account
+    #[clap(long, global = true)]
+    subject: Option<String>,
+    /// Where to keep the tokens of `--auth installed` and `--auth device-code`.  Tokens in the
+    /// plaintext cache are moved into the keyring or the encrypted file on first use.
+    #[clap(long, global = true, value_enum, default_value_t = TokenStoreKind::Plaintext)]
+    token_store: TokenStoreKind,
+}
 
-    let print_file =
-        |file: &File| println!("{:?} {:50} {}", file.parents, file.mime_type, file.name);
+/// How to obtain the access token
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AuthKind {
+    /// Ask the user for consent in the browser, with the client secret
+    Installed,
+    /// Show a code to enter on another device, for machines without a browser.  Needs a client
+    /// secret of the "TVs and Limited Input devices" type, and Google grants such clients only
+    /// some of the Drive scopes.
+    DeviceCode,
+    /// Sign in as the service account without user interaction
+    ServiceAccount,
+}
 
-    println!("=== Files without a parent (or with multiple parents) ===");
-    for file in files.iter().filter(|f| f.parents.len() != 1) {
-        print_file(file);
-    }
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text,
+    /// One JSON object per line with the time, level, target and message
+    Json,
+}
 
-    let ids: HashSet<&str> = files.iter().map(|f| &f.id as &str).collect();
-    println!("=== Files with parents not owned by me ===");
-    for file in files.iter().filter(|f| {
-        f.parents.iter().any(|id| !ids.contains(id as &str))
-            && f.quota_bytes_used.unwrap_or(0) > 1024
-    }) {
-        print_file(file);
-    }
+/// The level `base` raised by `verbose` steps and lowered by `quiet` steps
+fn shift_level(base: LevelFilter, verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let index = (base as usize + verbose as usize).saturating_sub(quiet as usize);
+    LEVELS[index.min(LEVELS.len() - 1)]
+}
 
+fn init_logger(path: &Path, args: &Args) -> anyhow::Result<()> {
+    use simplelog::*;
+    let (base, color) = if args.non_interactive {
+        (LevelFilter::Warn, ColorChoice::Never)
+    } else {
+        (LevelFilter::Info, ColorChoice::Auto)
+    };
+    // Quieting applies to the terminal only, so that the log file keeps the whole story
+    let level = shift_level(base, args.verbose, args.quiet);
+    let file_level = shift_level(LevelFilter::Info, args.verbose, 0);
+    let file = fs_err::File::create(path)?;
+    let loggers: Vec<Box<dyn SharedLogger>> = match args.log_format {
+        LogFormat::Text => vec![
+            TermLogger::new(level, Config::default(), TerminalMode::Mixed, color),
+            WriteLogger::new(file_level, Config::default(), file),
+        ],
+        LogFormat::Json => vec![
+            json_log::JsonLogger::new(level, std::io::stderr()),
+            json_log::JsonLogger::new(file_level, file),
+        ],
+    };
+    CombinedLogger::init(loggers)?;
     Ok(())
 }
 
-fn show_tree() -> anyhow::Result<()> {
-    let list = restore_data(false)?;
-    let files: Vec<_> = list.into_iter().flat_map(|e| e.files).collect();
-
-    let id_to_file: HashMap<_, _> = files.iter().map(|file| (&file.id as &str, file)).collect();
-    let parent_id_to_children = get_parent_id_to_children(&files)?;
-
-    enum Node<'a> {
-        File(&'a File),
-        Root { id: &'a str, name: String },
-    }
-    fn dfs(id_to_children: &HashMap<&str, Vec<&File>>, this: Node, depth: usize) -> u64 {
-        let mut size_sum = match this {
-            Node::File(&File {
-                quota_bytes_used: Some(bytes),
-                ..
-            }) => bytes,
-            _ => 0,
-        };
-        let (id, name) = match this {
-            Node::File(file) => (&file.id as &str, &file.name),
-            Node::Root { id, ref name } => (id, name),
-        };
-        for child in id_to_children.get(id).iter().flat_map(|&x| x) {
-            size_sum += dfs(id_to_children, Node::File(child), depth + 1);
-        }
-        if size_sum >= 50 * (1 << 20) {
-            println!("{}o {}  {name}", " ".repeat(depth), format_size(size_sum));
+/// The first ctrl-c asks the running operation to save and stop; the second one quits at once.
+fn init_ctrlc() -> anyhow::Result<CancellationToken> {
+    let cancel = CancellationToken::default();
+    let handle = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handle.is_cancelled() {
+            error!("Ctrl-C detected again!  Quitting without saving.");
+            std::process::exit(130);
         }
-        size_sum
-    }
-    let mut roots: Vec<_> = files
-        .iter()
-        .flat_map(|f| &f.parents)
-        .filter_map(|id| match id_to_file.get(id as &str) {
-            None => Some((
-                id,
-                Node::Root {
-                    id,
-                    name: format!("Root ({id})"),
-                },
-            )),
-            Some(file) => (file.parents.is_empty()).then_some((id, Node::File(file))),
+        warn!("Ctrl-C detected!  Saving before terminating.  Press again to quit immediately.");
+        handle.cancel();
+    })?;
+    Ok(cancel)
+}
+
+/// Fails the sign-in instead of presenting the consent page to nobody
+struct NonInteractiveDelegate;
+impl InstalledFlowDelegate for NonInteractiveDelegate {
+    fn present_user_url<'a>(
+        &'a self,
+        _url: &'a str,
+        _need_code: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>> {
+        SIGN_IN_REQUIRED.store(true, Ordering::SeqCst);
+        Box::pin(async {
+            Err("Signing in is required.  Run once without --non-interactive.".to_owned())
         })
-        .collect();
-    roots.sort_by_key(|x| x.0);
-    roots.dedup_by_key(|x| x.0);
-    for (_, file) in roots {
-        dfs(&parent_id_to_children, file, 0);
     }
-
-    Ok(())
 }
 
-fn get_parent_id_to_children(files: &[File]) -> anyhow::Result<HashMap<&str, Vec<&File>>> {
-    let mut parent_id_to_children = HashMap::<_, Vec<_>>::new();
-    for file in files {
-        match &file.parents[..] {
-            [] => {}
-            [parent] => parent_id_to_children
-                .entry(parent as &str)
-                .or_default()
-                .push(file),
-            _ => bail!("Multiple parents: {file:?}"),
-        }
+/// Fails the sign-in instead of waiting for the code to be entered.  The device flow cannot be
+/// aborted from the delegate, so the token is cancelled for [`Reauthenticating`] to give up on
+/// the flow.
+struct NonInteractiveDeviceDelegate(CancellationToken);
+impl DeviceFlowDelegate for NonInteractiveDeviceDelegate {
+    fn present_user_code<'a>(
+        &'a self,
+        _response: &'a DeviceAuthResponse,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        SIGN_IN_REQUIRED.store(true, Ordering::SeqCst);
+        self.0.cancel();
+        Box::pin(std::future::pending())
     }
-    Ok(parent_id_to_children)
 }
 
-fn format_size(size: u64) -> String {
-    let prefix = ["", "Ki", "Mi", "Gi"];
-    prefix
-        .iter()
-        .enumerate()
-        .rev()
-        .find_map(|(i, prefix)| {
-            let base = 1 << (i * 10);
-            (size >= base).then(|| format!("{:.2} {prefix}B", size as f64 / base as f64))
+async fn init_drive(
+    config: &config::Config,
+    args: &AuthArgs,
+    client: connect::Client,
+    token_epoch: TokenEpoch,
+    non_interactive: bool,
+    read_only: bool,
+) -> anyhow::Result<Drive> {
+    let open_storage = || {
+        anyhow::Ok(match args.token_store {
+            TokenStoreKind::Plaintext => None,
+            kind => Some(Box::new(SecureStorage::open(
+                kind,
+                &config.token_cache,
+                non_interactive,
+            )?)),
         })
-        .unwrap_or("0 B".into())
-}
-
-fn check_duplicates(id: &str) -> anyhow::Result<()> {
-    let list = restore_data(false)?;
-    let files: Vec<_> = list.into_iter().flat_map(|e| e.files).collect();
-    let id_to_file: HashMap<_, _> = files.iter().map(|file| (&file.id as &str, file)).collect();
-    let parent_id_to_children = get_parent_id_to_children(&files)?;
-
-    let Some(root) = id_to_file.get(id) else {
-        bail!("File with id {id:?} was not found");
     };
-    let parent = root
-        .parents
-        .get(0)
-        .context("Specified root does not have a parent")?;
-    let parent = id_to_file
-        .get(parent as &str)
-        .with_context(|| format!("File with id={parent:?} was not found"))?;
-    let mut sha_to_files = HashMap::<_, Vec<_>>::new();
-    for file in bfs_children(&parent_id_to_children, parent) {
-        if let Some(ref sha256) = file.sha256_checksum {
-            sha_to_files.entry(&sha256[..]).or_default().push(file);
+    let read_secret = || async {
+        oauth2::read_application_secret(&config.client_secret)
+            .await
+            .with_context(|| format!("Failed to read {:?}", config.client_secret))
+    };
+    let sign_in_aborted = CancellationToken::default();
+    let auth = match args.auth {
+        AuthKind::Installed => {
+            let builder = InstalledFlowAuthenticator::builder(
+                read_secret().await?,
+                InstalledFlowReturnMethod::HTTPRedirect,
+            )
+            .hyper_client(client.clone());
+            let mut builder = match open_storage()? {
+                None => builder.persist_tokens_to_disk(&config.token_cache),
+                Some(storage) => builder.with_storage(storage),
+            };
+            if non_interactive {
+                builder = builder.flow_delegate(Box::new(NonInteractiveDelegate));
+            }
+            builder.build().await?
         }
-    }
-    for file in bfs_children(&parent_id_to_children, root) {
-        if file.mime_type != "application/vnd.google-apps.folder" {
-            let sha256 = file
-                .sha256_checksum
-                .as_ref()
-                .with_context(|| format!("File with id={parent:?} does not have SHA256"))?;
-            let candidates = sha_to_files.get(&sha256[..]).map_or(&[][..], |x| x);
-            // if let Some(backup) = candidates.iter().find(|f| f.id != file.id) {
-            //     println!("{file:?}\n\t{backup:?}\n");
-            // }
-            if !candidates.iter().any(|f| f.id != file.id) {
-                println!("This file does not have a backup: {file:?}");
+        AuthKind::DeviceCode => {
+            let builder =
+                DeviceFlowAuthenticator::builder(read_secret().await?).hyper_client(client.clone());
+            let mut builder = match open_storage()? {
+                None => builder.persist_tokens_to_disk(&config.token_cache),
+                Some(storage) => builder.with_storage(storage),
+            };
+            if non_interactive {
+                builder = builder.flow_delegate(Box::new(NonInteractiveDeviceDelegate(
+                    sign_in_aborted.clone(),
+                )));
             }
+            builder.build().await?
         }
-    }
-
-    Ok(())
-}
-
-fn bfs_children<'a>(
-    parent_id_to_children: &HashMap<&str, Vec<&'a File>>,
-    root: &'a File,
-) -> VecDeque<&'a File> {
-    let mut que = VecDeque::new();
-    que.push_back(root);
-    for i in 0.. {
-        let Some(file) = que.get(i) else { break };
-        for child in parent_id_to_children
-            .get(&file.id as &str)
-            .map_or(&[][..], |x| x)
-        {
-            que.push_back(child);
+        AuthKind::ServiceAccount => {
+            let key = oauth2::read_service_account_key(&config.service_account_key)
+                .await
+                .with_context(|| format!("Failed to read {:?}", config.service_account_key))?;
+            let mut builder =
+                ServiceAccountAuthenticator::builder(key).hyper_client(client.clone());
+            if let Some(ref subject) = args.subject {
+                builder = builder.subject(subject);
+            }
+            builder.build().await?
         }
-    }
-    que
+    };
+    let auth = Reauthenticating::new(auth, token_epoch, sign_in_aborted);
+    Ok(if read_only {
+        DriveHub::new(client, ReadOnlyAuth(auth))
+    } else {
+        DriveHub::new(client, auth)
+    })
 }
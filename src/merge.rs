@@ -0,0 +1,100 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde_json::json;
+
+use crate::{
+    duplicate_clusters, format_size, full_path, output, restore_files, sqlite, File, MemoryIndex,
+    Store, StoreKind,
+};
+
+/// Snapshot of one account, tagged with the name it is reported under
+pub struct Account {
+    pub name: String,
+    pub store: Store,
+}
+impl Account {
+    /// Opens the data directory of a profile, or a snapshot file such as an archived one.
+    pub fn open(name: String, path: &Path) -> Self {
+        let store = if path.is_dir() {
+            Store {
+                kind: if path.join(sqlite::DB_NAME).exists() {
+                    StoreKind::Sqlite
+                } else {
+                    StoreKind::Json
+                },
+                dir: path.to_owned(),
+                compress_level: 0,
+                keep_snapshots: 0,
+                snapshot: None,
+            }
+        } else {
+            Store {
+                kind: StoreKind::Json,
+                dir: path.parent().map_or(PathBuf::new(), Path::to_owned),
+                compress_level: 0,
+                keep_snapshots: 0,
+                snapshot: Some(path.to_owned()),
+            }
+        };
+        Self { name, store }
+    }
+}
+
+/// Lists the clusters of identical files stored in more than one of the accounts, sorted by
+/// reclaimable space.
+pub fn show_cross_account_duplicates(accounts: &[Account]) -> anyhow::Result<()> {
+    let mut files = vec![];
+    let mut id_to_account = HashMap::new();
+    for (i, account) in accounts.iter().enumerate() {
+        let loaded = restore_files(&account.store)?;
+        info!("Loaded {} files of {}", loaded.len(), account.name);
+        // A file shared between the accounts is the same file rather than a copy
+        for file in loaded {
+            if !id_to_account.contains_key(&file.id) {
+                id_to_account.insert(file.id.clone(), i);
+                files.push(file);
+            }
+        }
+    }
+    let index = MemoryIndex::new(&files)?;
+    let account_of = |file: &File| &accounts[id_to_account[&file.id]].name;
+
+    let mut clusters = vec![];
+    let mut total_wasted = 0;
+    for (wasted, sha256, size, files) in duplicate_clusters(&files) {
+        let names: HashSet<_> = files.iter().map(|&f| account_of(f)).collect();
+        if names.len() < 2 {
+            continue;
+        }
+        outln!(
+            "=== {} reclaimable: {} copies of {} in {} accounts (SHA256 {sha256}) ===",
+            format_size(wasted),
+            files.len(),
+            format_size(size),
+            names.len(),
+        );
+        let mut copies = vec![];
+        for file in files {
+            let path = full_path(&index, file)?;
+            outln!("[{}] {path}", account_of(file));
+            copies.push(json!({ "account": account_of(file), "path": path }));
+        }
+        clusters.push(json!({
+            "sha256": sha256,
+            "size": size,
+            "wasted": wasted,
+            "files": copies,
+        }));
+        total_wasted += wasted;
+    }
+    outln!(
+        "{} clusters across accounts, {} reclaimable in total",
+        clusters.len(),
+        format_size(total_wasted)
+    );
+    output::emit(&clusters)
+}
@@ -0,0 +1,157 @@
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use google_drive3::hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+
+use crate::{budget::ApiBudget, restore_files, trend::top_folder_usage, Store};
+
+/// Gauges computed from the snapshot after each sync
+#[derive(Default)]
+struct Gauges {
+    files: u64,
+    quota_bytes: u64,
+    folders: Vec<(String, u64)>,
+    last_sync: Option<DateTime<Utc>>,
+}
+
+/// Metrics served in the Prometheus text format while watching
+pub struct Metrics {
+    gauges: Mutex<Gauges>,
+    budget: &'static ApiBudget,
+}
+impl Metrics {
+    pub fn new(budget: &'static ApiBudget) -> Self {
+        Self {
+            gauges: Mutex::default(),
+            budget,
+        }
+    }
+
+    /// Recomputes the gauges from the snapshot just synced.
+    pub fn update(&self, store: &Store) -> anyhow::Result<()> {
+        let files = restore_files(store)?;
+        let (quota_bytes, folders) = top_folder_usage(&files)?;
+        let mut folders: Vec<_> = folders.into_iter().collect();
+        folders.sort();
+        *self.gauges.lock().expect("the lock is never poisoned") = Gauges {
+            files: files.len() as u64,
+            quota_bytes,
+            folders,
+            last_sync: Some(Utc::now()),
+        };
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+        let gauges = self.gauges.lock().expect("the lock is never poisoned");
+        if let Some(last_sync) = gauges.last_sync {
+            metric(
+                "drive_crawl_files",
+                "gauge",
+                "Number of files in the snapshot",
+                &[(String::new(), gauges.files)],
+            );
+            metric(
+                "drive_crawl_quota_bytes",
+                "gauge",
+                "Quota used by the files in the snapshot",
+                &[(String::new(), gauges.quota_bytes)],
+            );
+            let folders: Vec<_> = gauges
+                .folders
+                .iter()
+                .map(|(folder, bytes)| (format!("{{folder=\"{}\"}}", escape(folder)), *bytes))
+                .collect();
+            metric(
+                "drive_crawl_folder_quota_bytes",
+                "gauge",
+                "Quota used by each top-level folder, where `/` stands for the files right under \
+                the root",
+                &folders,
+            );
+            metric(
+                "drive_crawl_last_sync_timestamp_seconds",
+                "gauge",
+                "Time of the last successful sync",
+                &[(String::new(), last_sync.timestamp() as u64)],
+            );
+        }
+        drop(gauges);
+        let stats = self.budget.stats();
+        let counters = [
+            ("calls", "API calls made", stats.calls),
+            ("retries", "API calls retried", stats.retries),
+            (
+                "throttled",
+                "API calls retried due to rate limits",
+                stats.throttled,
+            ),
+            ("errors", "API calls failed after all retries", stats.errors),
+        ];
+        for (name, help, value) in counters {
+            metric(
+                &format!("drive_crawl_api_{name}_total"),
+                "counter",
+                help,
+                &[(String::new(), value)],
+            );
+        }
+        out
+    }
+}
+
+/// Escapes a label value of the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves the metrics on `/metrics` until the returned future is dropped.
+pub fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+) -> anyhow::Result<impl std::future::Future<Output = google_drive3::hyper::Result<()>>> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let metrics = metrics.clone();
+                async move {
+                    let res = if req.uri().path() == "/metrics" {
+                        Response::builder()
+                            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                            .body(Body::from(metrics.render()))
+                    } else {
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty())
+                    };
+                    Ok::<_, Infallible>(res.expect("the response is valid"))
+                }
+            }))
+        }
+    });
+    Ok(Server::try_bind(&addr)
+        .with_context(|| format!("Failed to listen on {addr}"))?
+        .serve(make_service))
+}
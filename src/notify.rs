@@ -0,0 +1,116 @@
+use anyhow::bail;
+use clap::ValueEnum;
+use google_drive3::hyper::{header, Body, Request};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{connect::Client, format_size, is_complete, restore_files, Store};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum NotifyFormat {
+    /// `{"text": ..., "summary": {...}}` with the numbers broken out
+    Json,
+    /// `{"text": ...}` for the incoming webhooks of Slack
+    Slack,
+}
+
+/// Number of files in the snapshot and the quota used by them
+#[derive(Clone, Copy, Serialize)]
+pub struct Totals {
+    pub files: u64,
+    pub bytes: u64,
+}
+impl Totals {
+    pub fn load(store: &Store) -> anyhow::Result<Self> {
+        let files = restore_files(store)?;
+        Ok(Self {
+            files: files.len() as u64,
+            bytes: files.iter().filter_map(|f| f.quota_bytes_used).sum(),
+        })
+    }
+}
+
+/// Outcome of a crawl or a sync
+#[derive(Serialize)]
+pub struct Summary {
+    command: String,
+    /// The error the command failed with, if any
+    error: Option<String>,
+    /// Whether the snapshot is complete, which an interrupted crawl is not
+    complete: bool,
+    totals: Option<Totals>,
+    files_delta: Option<i64>,
+    bytes_delta: Option<i64>,
+    /// API calls failed after all retries, including those the command recovered from
+    api_errors: u64,
+}
+impl Summary {
+    /// `before` is the totals of the snapshot before the command was run, if there was one.
+    pub fn new(
+        command: &str,
+        store: &Store,
+        before: Option<Totals>,
+        api_errors: u64,
+        result: &anyhow::Result<()>,
+    ) -> Self {
+        let totals = Totals::load(store).ok();
+        let delta = |f: fn(&Totals) -> u64| Some(f(&totals?) as i64 - f(&before?) as i64);
+        Self {
+            command: command.to_owned(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            complete: is_complete(store).unwrap_or(false),
+            totals,
+            files_delta: delta(|t| t.files),
+            bytes_delta: delta(|t| t.bytes),
+            api_errors,
+        }
+    }
+
+    fn text(&self) -> String {
+        let outcome = match (&self.error, self.complete) {
+            (Some(e), _) => format!("failed: {e}"),
+            (None, true) => "finished".to_owned(),
+            (None, false) => "stopped before completing".to_owned(),
+        };
+        let mut text = format!("drive-crawl {} {outcome}", self.command);
+        if let Some(totals) = self.totals {
+            text += &format!(
+                "\n{} files using {}",
+                totals.files,
+                format_size(totals.bytes)
+            );
+            if let (Some(files), Some(bytes)) = (self.files_delta, self.bytes_delta) {
+                let sign = if bytes < 0 { "-" } else { "+" };
+                text += &format!(
+                    " ({files:+} files, {sign}{})",
+                    format_size(bytes.unsigned_abs())
+                );
+            }
+        }
+        if self.api_errors > 0 {
+            text += &format!("\n{} API calls failed", self.api_errors);
+        }
+        text
+    }
+}
+
+/// Posts the summary to the URL as JSON.
+pub async fn send(
+    client: &Client,
+    url: &str,
+    format: NotifyFormat,
+    summary: &Summary,
+) -> anyhow::Result<()> {
+    let body = match format {
+        NotifyFormat::Json => json!({ "text": summary.text(), "summary": summary }),
+        NotifyFormat::Slack => json!({ "text": summary.text() }),
+    };
+    let request = Request::post(url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?;
+    let res = client.request(request).await?;
+    if !res.status().is_success() {
+        bail!("{url} responded with {}", res.status());
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::io::Write;
+
+use google_drive3::api;
+use log::{error, info};
+
+use crate::{
+    audit::AuditLog,
+    ensure_complete, format_size,
+    parents::{self, FolderKind},
+    retry::RetryPolicy,
+    CancellationToken, Drive, Overview, Store,
+};
+
+pub struct Options {
+    /// Ask before moving each file
+    pub confirm: bool,
+}
+
+enum Answer {
+    Yes,
+    No,
+    Quit,
+}
+
+fn ask(question: &str) -> anyhow::Result<Answer> {
+    eprint!("{question} [y/N/q] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(match answer.trim().to_ascii_lowercase().as_str() {
+        "y" => Answer::Yes,
+        "q" => Answer::Quit,
+        _ => Answer::No,
+    })
+}
+
+/// Moves the files without a parent and those in folders not owned by me, as listed by the
+/// overview, into the target folder.  Files with multiple parents are left alone, and so are
+/// trashed files.  Files in a foreign folder are only moved once `resolve-parents` has told that
+/// the folder is neither the root of My Drive nor a computer, since the files right under those
+/// are not crawled with their parent.
+pub async fn adopt_orphans(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    target_id: &str,
+    options: &Options,
+    audit: &mut AuditLog,
+) -> anyhow::Result<()> {
+    // Every file whose folder has not been crawled yet would look orphaned
+    ensure_complete(store, false)?;
+    // Trashed files are left where they are
+    let overview = Overview::load(store, false)?;
+    let folders = parents::restore(store)?;
+    let mut unresolved = 0;
+    let in_foreign_folders = overview.with_foreign_parents.iter().filter(|f| {
+        let resolved = f.parents.iter().all(|id| {
+            f.drive_id.as_ref() == Some(id)
+                || folders
+                    .get(id)
+                    .is_some_and(|folder| folder.kind == Some(FolderKind::Folder))
+        });
+        if !resolved {
+            unresolved += 1;
+        }
+        resolved
+    });
+    let orphans: Vec<_> = overview
+        .without_single_parent
+        .iter()
+        .filter(|f| f.parents.is_empty())
+        .chain(in_foreign_folders)
+        // The root folders of My Drive and the shared drives have no parent either
+        .filter(|f| f.id != target_id && f.drive_id.as_ref() != Some(&f.id))
+        .collect();
+    if unresolved > 0 {
+        outln!(
+            "Skipping {unresolved} files in folders not resolved as regular folders of others.  \
+            Run resolve-parents to look them up."
+        );
+    }
+
+    let (mut moved, mut bytes) = (0, 0);
+    for file in orphans {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let size = format_size(file.quota_bytes_used.unwrap_or(0));
+        if !file.can_move() {
+            outln!("Skipping {} ({size}), which I cannot move", file.name);
+            continue;
+        }
+        if audit.dry_run() {
+            outln!("Would move {} ({size})", file.name);
+        } else {
+            if options.confirm {
+                match ask(&format!("Move {} ({size})?", file.name))? {
+                    Answer::Yes => {}
+                    Answer::No => continue,
+                    Answer::Quit => break,
+                }
+            }
+            // Since a file can have only one parent, the foreign one has to be removed
+            let res = retry
+                .run(|| {
+                    drive
+                        .files()
+                        .update(api::File::default(), &file.id)
+                        .add_parents(target_id)
+                        .remove_parents(&file.parents.join(","))
+                        .supports_all_drives(true)
+                        .param("fields", "id")
+                        .doit_without_upload()
+                })
+                .await;
+            if let Err(e) = res {
+                error!("Failed to move {} ({}): {e}", file.name, file.id);
+                continue;
+            }
+            audit.record(
+                "move",
+                &file.id,
+                serde_json::json!({ "parents": file.parents }),
+                serde_json::json!({ "parents": [target_id] }),
+            )?;
+            outln!("Moved {} ({size})", file.name);
+        }
+        moved += 1;
+        bytes += file.quota_bytes_used.unwrap_or(0);
+    }
+    let verb = if audit.dry_run() {
+        "Would move"
+    } else {
+        "Moved"
+    };
+    outln!("{verb} {moved} files, {}", format_size(bytes));
+    if !audit.dry_run() && moved > 0 {
+        outln!("Run sync to reflect the moves in the snapshot.");
+    }
+    Ok(())
+}
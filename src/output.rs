@@ -0,0 +1,70 @@
+use std::{
+    fmt::Write as _,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// The results of reports as JSON on the standard output, with the text moved to the standard
+    /// error, for piping into `jq`
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_output_format(format: OutputFormat) {
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+pub(crate) fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// The output of the reports run by [`capture`], which is printed nowhere else
+#[derive(Default)]
+pub(crate) struct Captured {
+    pub text: String,
+    pub json: Option<serde_json::Value>,
+}
+static CAPTURED: Mutex<Option<Captured>> = Mutex::new(None);
+
+/// Runs the report, collecting its text and JSON instead of printing them.
+pub(crate) fn capture(report: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<Captured> {
+    *CAPTURED.lock().unwrap() = Some(Captured::default());
+    let res = report();
+    let captured = CAPTURED.lock().unwrap().take().unwrap_or_default();
+    res.map(|()| captured)
+}
+
+/// Prints a line of `outln!`, or appends it to the output being captured.
+pub fn print_line(line: std::fmt::Arguments) {
+    if let Some(ref mut captured) = *CAPTURED.lock().unwrap() {
+        writeln!(captured.text, "{line}").expect("writing to a String never fails");
+    } else if is_json() {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Prints the result of a report as JSON if asked to.
+pub fn emit(value: &impl Serialize) -> anyhow::Result<()> {
+    if let Some(ref mut captured) = *CAPTURED.lock().unwrap() {
+        captured.json = Some(serde_json::to_value(value)?);
+        return Ok(());
+    }
+    if is_json() {
+        let mut stdout = std::io::stdout().lock();
+        serde_json::to_writer_pretty(&mut stdout, value)?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::{cell::Cell, collections::HashMap};
+
+use anyhow::bail;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{stream, StreamExt};
+use log::{info, warn};
+
+use crate::{
+    drive_api::{DriveApi, ListRequest},
+    fetch_account, fetch_start_page_token,
+    retry::RetryPolicy,
+    save_complete_snapshot, save_crawl_fields, save_crawl_labels, save_crawl_query,
+    save_crawl_spaces, CancellationToken, Drive, File, FileList, ListOptions, Provenance, Store,
+};
+
+pub struct Options {
+    /// Number of ranges of modified time
+    pub partitions: usize,
+    /// Number of ranges crawled at the same time
+    pub concurrency: usize,
+    pub list: ListOptions,
+}
+
+/// Splits the files matching `query` by the time from the launch of Google Docs until now into `n`
+/// ranges, the first and last of which are open-ended.
+fn partition_queries(query: &str, n: usize, now: DateTime<Utc>) -> Vec<String> {
+    let start = Utc.with_ymd_and_hms(2006, 1, 1, 0, 0, 0).unwrap();
+    let step = (now - start) / n as i32;
+    let bound = |i: usize| {
+        let time = start + step * i as i32;
+        format!("'{}'", time.format("%Y-%m-%dT%H:%M:%S"))
+    };
+    (0..n)
+        .map(|i| {
+            let mut q = format!("({query})");
+            if i > 0 {
+                q += &format!(" and modifiedTime >= {}", bound(i));
+            }
+            if i + 1 < n {
+                q += &format!(" and modifiedTime < {}", bound(i + 1));
+            }
+            q
+        })
+        .collect()
+}
+
+async fn crawl_partition(
+    api: &impl DriveApi,
+    cancel: &CancellationToken,
+    // Set when another range has failed
+    failed: &Cell<bool>,
+    retry: &RetryPolicy,
+    q: &str,
+    list: &ListOptions,
+) -> anyhow::Result<Vec<File>> {
+    let mut request = ListRequest {
+        corpora: Some("user".into()),
+        q: Some(q.into()),
+        spaces: Some(list.spaces()),
+        page_size: list.page_size,
+        fields: format!("nextPageToken,files({})", list.fields()),
+        scopes: list.scopes(),
+        include_labels: list.include_labels(),
+        ..Default::default()
+    };
+    let mut files = vec![];
+    loop {
+        if cancel.is_cancelled() || failed.get() {
+            bail!("Cancelled");
+        }
+        let res = retry.run(|| api.list_files(&request)).await?;
+        let res = FileList::try_from(res)?;
+        files.extend(res.files);
+        request.page_token = res.next_page_token;
+        if request.page_token.is_none() {
+            info!("Completed {q:?} with {} files", files.len());
+            return Ok(files);
+        }
+    }
+}
+
+/// Crawls My Drive from scratch, fetching ranges of modified time concurrently.
+pub async fn crawl_in_parallel(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    query: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let started_at = Utc::now();
+    // Obtained before the crawl so that changes made during the crawl are not missed
+    fetch_start_page_token(drive, store, retry).await?;
+
+    let failed = Cell::new(false);
+    let queries = partition_queries(query, options.partitions.max(1), Utc::now());
+    let results: Vec<_> = stream::iter(queries)
+        .map(|q| {
+            let failed = &failed;
+            async move {
+                let res = crawl_partition(drive, cancel, failed, retry, &q, &options.list).await;
+                if let Err(ref e) = res {
+                    warn!("Failed to crawl {q:?}: {e}");
+                    // No point in continuing since nothing is saved unless all succeed
+                    failed.set(true);
+                }
+                res
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    // A file modified during the crawl may appear in two ranges
+    let mut id_to_file = HashMap::new();
+    for files in results {
+        let Ok(files) = files else {
+            bail!("Aborted the crawl.  Nothing has been saved.");
+        };
+        for file in files {
+            id_to_file.insert(file.id.clone(), file);
+        }
+    }
+    let files: Vec<_> = id_to_file.into_values().collect();
+    info!("Crawled {} files", files.len());
+    let crawl = Provenance {
+        account: fetch_account(drive, retry).await,
+        query: Some(query.to_owned()),
+        started_at,
+        finished_at: Utc::now(),
+        synced_at: None,
+        tool_version: env!("CARGO_PKG_VERSION").into(),
+        pages: options.partitions.max(1),
+        complete: true,
+    };
+    save_complete_snapshot(store, files, &crawl)?;
+    save_crawl_query(store, query)?;
+    save_crawl_fields(store, &options.list.extra_fields)?;
+    save_crawl_spaces(store, &options.list)?;
+    save_crawl_labels(store, &options.list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partitions_cover_the_whole_time_without_overlaps() {
+        // Five spans of four years with a leap day each
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let bound = |year| format!("'{year}-01-01T00:00:00'");
+        let mut expected = vec![format!("(q) and modifiedTime < {}", bound(2010))];
+        for year in [2010, 2014, 2018] {
+            expected.push(format!(
+                "(q) and modifiedTime >= {} and modifiedTime < {}",
+                bound(year),
+                bound(year + 4)
+            ));
+        }
+        expected.push(format!("(q) and modifiedTime >= {}", bound(2022)));
+        assert_eq!(partition_queries("q", 5, now), expected);
+    }
+
+    #[test]
+    fn one_partition_is_the_query_itself() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(partition_queries("trashed", 1, now), ["(trashed)"]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Context;
+use futures::{stream, StreamExt};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    restore_files, restore_shared_drives, restore_shared_with_me,
+    retry::{status_of, RetryPolicy},
+    write_atomically, CancellationToken, Drive, Store, User,
+};
+
+/// Number of folders fetched before the cache is saved
+const BATCH_SIZE: usize = 100;
+
+/// What a folder outside of the snapshot turned out to be
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FolderKind {
+    Folder,
+    /// The root of My Drive
+    MyDrive,
+    /// Top-level folder of a computer backed up by Drive for Desktop, which is listed under
+    /// "Computers" rather than in My Drive
+    Computer,
+}
+
+/// Folder outside of the snapshot that some crawled files are placed in
+#[derive(Serialize, Deserialize)]
+pub struct ForeignFolder {
+    /// `None` if the folder cannot be accessed, so that it is not fetched again
+    pub name: Option<String>,
+    #[serde(default)]
+    pub owners: Vec<User>,
+    /// `None` for folders cached before the kind was recorded, which are fetched again
+    #[serde(default)]
+    pub kind: Option<FolderKind>,
+}
+impl ForeignFolder {
+    pub fn label(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("(inaccessible folder)");
+        match self.kind {
+            Some(FolderKind::MyDrive) => return "My Drive".into(),
+            Some(FolderKind::Computer) => return format!("Computer {name}"),
+            _ => {}
+        }
+        match self.owners.first().and_then(|o| o.email_address.as_ref()) {
+            Some(owner) => format!("{name} owned by {owner}"),
+            None => name.to_owned(),
+        }
+    }
+}
+
+/// Resolved folders by id, cached in `foreign-parents.json` next to the snapshot
+pub(crate) fn restore(store: &Store) -> anyhow::Result<BTreeMap<String, ForeignFolder>> {
+    Ok(
+        match fs_err::File::open(store.dir.join("foreign-parents.json")) {
+            Ok(file) => serde_json::from_reader(std::io::BufReader::new(file))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => Err(e)?,
+        },
+    )
+}
+fn save(store: &Store, folders: &BTreeMap<String, ForeignFolder>) -> anyhow::Result<()> {
+    write_atomically(&store.dir.join("foreign-parents.json"), |writer| {
+        Ok(serde_json::to_writer(writer, folders)?)
+    })
+}
+
+/// The id of the root of My Drive, for which `root` is an alias
+async fn fetch_root_id(drive: &Drive, retry: &RetryPolicy) -> anyhow::Result<String> {
+    let (_, root) = retry
+        .run(|| drive.files().get("root").param("fields", "id").doit())
+        .await?;
+    root.id.context("The API did not return the id of the root")
+}
+
+async fn fetch(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    root_id: &str,
+    id: &str,
+) -> anyhow::Result<ForeignFolder> {
+    let res = retry
+        .run(|| {
+            drive
+                .files()
+                .get(id)
+                .supports_all_drives(true)
+                .param("fields", "name,owners(emailAddress),parents,ownedByMe")
+                .doit()
+        })
+        .await;
+    match res {
+        Ok((_, file)) => Ok(ForeignFolder {
+            // Folders of mine are in the snapshot unless they are roots, since the crawl does not
+            // return the roots of the computers
+            kind: Some(if id == root_id {
+                FolderKind::MyDrive
+            } else if file.owned_by_me == Some(true)
+                && file.parents.as_ref().is_none_or(|p| p.is_empty())
+            {
+                FolderKind::Computer
+            } else {
+                FolderKind::Folder
+            }),
+            name: file.name,
+            owners: file
+                .owners
+                .unwrap_or_default()
+                .into_iter()
+                .map(|o| User {
+                    email_address: o.email_address,
+                })
+                .collect(),
+        }),
+        Err(e) if status_of(&e).is_some_and(|(code, _)| code == 403 || code == 404) => {
+            warn!("Cannot access the folder {id}: {e}");
+            Ok(ForeignFolder {
+                name: None,
+                owners: vec![],
+                kind: Some(FolderKind::Folder),
+            })
+        }
+        Err(e) => Err(e)?,
+    }
+}
+
+/// Fetches the names and owners of the parents missing from the snapshot, other than shared
+/// drives, `concurrency` at a time, telling the roots of My Drive and of the computers apart.
+/// Those already cached are skipped.
+pub async fn resolve_parents(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let shared_with_me = restore_shared_with_me(store)?;
+    let mut folders = restore(store)?;
+    let known: HashSet<_> = files
+        .iter()
+        .map(|f| f.id.clone())
+        .chain(restore_shared_drives(store)?.into_iter().map(|d| d.id))
+        .collect();
+    let mut unknown: Vec<_> = files
+        .iter()
+        .chain(&shared_with_me)
+        .flat_map(|f| &f.parents)
+        .filter(|&id| !known.contains(id) && folders.get(id).is_none_or(|f| f.kind.is_none()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect();
+    unknown.sort();
+    info!("{} unknown parents to resolve", unknown.len());
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    let root_id = fetch_root_id(drive, retry).await?;
+
+    for batch in unknown.chunks(BATCH_SIZE) {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let fetched: Vec<_> = stream::iter(batch)
+            .map(|id| {
+                let root_id = &root_id;
+                async move { (id, fetch(drive, retry, root_id, id).await) }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        for (id, folder) in fetched {
+            folders.insert(id.clone(), folder?);
+        }
+        save(store, &folders)?;
+        info!("Resolved {} parents so far", folders.len());
+    }
+    Ok(())
+}
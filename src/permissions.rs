@@ -0,0 +1,386 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    enrich::Pipeline, format_size, full_path, output, restore_files, retry::RetryPolicy,
+    CancellationToken, Drive, File, MemoryIndex, Store,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Permission {
+    /// `user`, `group`, `domain` or `anyone`
+    #[serde(rename = "type")]
+    kind: String,
+    role: String,
+    #[serde(rename = "emailAddress")]
+    email_address: Option<String>,
+    domain: Option<String>,
+    /// When the access is revoked, if ever
+    #[serde(rename = "expirationTime", default)]
+    pub(crate) expiration_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct PermissionList {
+    files: Vec<FilePermissions>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+#[derive(Deserialize)]
+struct FilePermissions {
+    id: String,
+    #[serde(default)]
+    permissions: Vec<Permission>,
+}
+
+#[derive(Deserialize)]
+struct FilePermissionList {
+    #[serde(default)]
+    permissions: Vec<Permission>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+/// Fetches the permissions of one file, including the ownership.
+pub(crate) async fn fetch_permissions(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    file_id: &str,
+) -> anyhow::Result<Vec<Permission>> {
+    let mut permissions = vec![];
+    let mut token = None::<String>;
+    loop {
+        let (_, res) = retry
+            .run(|| {
+                let mut call = drive
+                    .permissions()
+                    .list(file_id)
+                    .supports_all_drives(true)
+                    .param(
+                        "fields",
+                        "nextPageToken,permissions(type,role,emailAddress,domain,expirationTime)",
+                    );
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await?;
+        let res: FilePermissionList = serde_json::from_str(&serde_json::to_string(&res)?)?;
+        permissions.extend(res.permissions);
+        token = res.next_page_token;
+        if token.is_none() {
+            return Ok(permissions);
+        }
+    }
+}
+
+fn permissions_path(store: &Store) -> PathBuf {
+    store.dir.join("permissions.json")
+}
+
+/// Fetches the permissions of every file owned by me, except for my own ownership, and then
+/// those of the files in shared drives, which `files.list` leaves out, `concurrency` files at a
+/// time.
+pub async fn list_permissions(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let mut id_to_permissions = HashMap::<String, Vec<Permission>>::new();
+    let mut token = None::<String>;
+    let mut complete = false;
+    for page in 0.. {
+        info!(
+            "Page {page} ({} shared files so far)",
+            id_to_permissions.len()
+        );
+        let res = retry
+            .run(|| {
+                let mut call = drive
+                    .files()
+                    .list()
+                    .corpora("user")
+                    .q("'me' in owners")
+                    .param(
+                        "fields",
+                        "nextPageToken,files(id,permissions(type,role,emailAddress,domain,\
+                        expirationTime))",
+                    );
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await;
+        let res = match res {
+            Ok((_, res)) => res,
+            Err(e) => {
+                error!("Aborting due to an API error: {e}");
+                break;
+            }
+        };
+        let res: PermissionList = serde_json::from_str(&serde_json::to_string(&res)?)?;
+        for file in res.files {
+            let permissions: Vec<_> = file
+                .permissions
+                .into_iter()
+                .filter(|p| p.role != "owner")
+                .collect();
+            if !permissions.is_empty() {
+                id_to_permissions.insert(file.id, permissions);
+            }
+        }
+        token = res.next_page_token;
+        if token.is_none() {
+            complete = true;
+            break;
+        }
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Saving before terminating.");
+            break;
+        }
+    }
+
+    let pipeline = Pipeline {
+        cancel,
+        concurrency,
+        checkpoint: store.dir.join("permissions-checkpoint.json"),
+    };
+    if complete && !cancel.is_cancelled() {
+        let in_shared_drives = restore_files(store)?
+            .into_iter()
+            .filter(|f| f.drive_id.is_some())
+            .map(|f| f.id);
+        let enriched = pipeline
+            .run("permissions", in_shared_drives, |id| async move {
+                fetch_permissions(drive, retry, &id).await
+            })
+            .await;
+        match enriched {
+            Ok(enriched) => {
+                complete = enriched.complete;
+                for (id, permissions) in enriched.results {
+                    let permissions: Vec<_> = permissions
+                        .into_iter()
+                        .filter(|p| p.role != "owner")
+                        .collect();
+                    if !permissions.is_empty() {
+                        id_to_permissions.insert(id, permissions);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Aborting due to an API error: {e:#}");
+                complete = false;
+            }
+        }
+    } else {
+        complete = false;
+    }
+    if !complete {
+        warn!("Saving permissions of only part of the files");
+    }
+    let path = permissions_path(store);
+    let file = fs_err::File::create(&path)?;
+    serde_json::to_writer(BufWriter::new(file), &id_to_permissions)?;
+    info!(
+        "Saved permissions of {} files to {path:?}",
+        id_to_permissions.len()
+    );
+    if complete {
+        pipeline.finish()?;
+    }
+    Ok(())
+}
+
+/// Who a file is exposed to, from the widest audience
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Audience {
+    AnyoneWithLink,
+    OutsideDomain,
+    SpecificPeople,
+}
+impl Audience {
+    fn of(permission: &Permission, domain: Option<&str>) -> Self {
+        let permission_domain = match permission.kind.as_str() {
+            "anyone" => return Self::AnyoneWithLink,
+            "domain" => permission.domain.as_deref(),
+            _ => permission
+                .email_address
+                .as_deref()
+                .and_then(|email| email.rsplit_once('@'))
+                .map(|(_, d)| d),
+        };
+        match domain {
+            Some(domain) if permission_domain.is_some_and(|d| !d.eq_ignore_ascii_case(domain)) => {
+                Self::OutsideDomain
+            }
+            _ => Self::SpecificPeople,
+        }
+    }
+    fn title(self) -> &'static str {
+        match self {
+            Self::AnyoneWithLink => "Shared with anyone with the link",
+            Self::OutsideDomain => "Shared outside the domain",
+            Self::SpecificPeople => "Shared with specific people",
+        }
+    }
+}
+
+pub(crate) fn describe(permission: &Permission) -> String {
+    let whom = match permission.kind.as_str() {
+        "anyone" => "anyone",
+        "domain" => permission.domain.as_deref().unwrap_or("?"),
+        _ => permission.email_address.as_deref().unwrap_or("?"),
+    };
+    format!("{whom} ({})", permission.role)
+}
+
+fn load_permissions(store: &Store) -> anyhow::Result<HashMap<String, Vec<Permission>>> {
+    let path = permissions_path(store);
+    Ok(serde_json::from_reader(BufReader::new(
+        fs_err::File::open(&path).context("No permissions found.  Run `permissions` first.")?,
+    ))?)
+}
+
+/// Quota used by each file together with everything inside it
+fn subtree_sizes<'a>(files: &'a [File], index: &MemoryIndex<'a>) -> HashMap<&'a str, u64> {
+    let mut subtree_size = HashMap::<&str, u64>::new();
+    for file in files {
+        let bytes = file.quota_bytes_used.unwrap_or(0);
+        let mut current = Some(file);
+        // Bounded in case of cyclic parents
+        for _ in 0..files.len() {
+            let Some(file) = current else { break };
+            *subtree_size.entry(&file.id).or_default() += bytes;
+            current = file
+                .parents
+                .first()
+                .and_then(|id| index.id_to_file.get(id as &str).copied());
+        }
+    }
+    subtree_size
+}
+
+/// Lists the shared files by their widest audience, grouped by the folder containing them.
+/// Sizes of shared folders include everything inside, which is shared as well.
+pub fn show_sharing(store: &Store, domain: Option<&str>) -> anyhow::Result<()> {
+    let id_to_permissions = load_permissions(store)?;
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let subtree_size = subtree_sizes(&files, &index);
+
+    // Audience => folder path => shared files in the folder
+    let mut groups = BTreeMap::<_, BTreeMap<String, Vec<(&File, &[Permission])>>>::new();
+    for file in &files {
+        let Some(permissions) = id_to_permissions.get(&file.id) else {
+            continue;
+        };
+        let Some(audience) = permissions.iter().map(|p| Audience::of(p, domain)).min() else {
+            continue;
+        };
+        let folder = match file
+            .parents
+            .first()
+            .and_then(|id| index.id_to_file.get(id as &str))
+        {
+            Some(parent) => full_path(&index, parent)?,
+            None => String::from("/"),
+        };
+        groups
+            .entry(audience)
+            .or_default()
+            .entry(folder)
+            .or_default()
+            .push((file, &permissions[..]));
+    }
+
+    let mut rows = vec![];
+    for (audience, folders) in groups {
+        outln!("=== {} ===", audience.title());
+        for (folder, shared) in folders {
+            let total: u64 = shared
+                .iter()
+                .map(|(file, _)| subtree_size[&file.id as &str])
+                .sum();
+            outln!("{:>12}  {folder}", format_size(total));
+            for (file, permissions) in shared {
+                let whom: Vec<_> = permissions.iter().map(describe).collect();
+                outln!(
+                    "{:>12}    {}  [{}]",
+                    format_size(subtree_size[&file.id as &str]),
+                    file.name,
+                    whom.join(", ")
+                );
+                rows.push(json!({
+                    "audience": audience.title(),
+                    "folder": folder,
+                    "id": file.id,
+                    "name": file.name,
+                    "size": subtree_size[&file.id as &str],
+                    "permissions": permissions,
+                }));
+            }
+        }
+    }
+    output::emit(&rows)
+}
+
+/// Lists the files anyone with the link can access, largest first, along with the role granted
+/// and when the access expires.  Sizes of folders include everything inside.
+pub fn show_public(store: &Store) -> anyhow::Result<()> {
+    let id_to_permissions = load_permissions(store)?;
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let subtree_size = subtree_sizes(&files, &index);
+
+    let mut public = vec![];
+    for file in &files {
+        let Some(permissions) = id_to_permissions.get(&file.id) else {
+            continue;
+        };
+        for permission in permissions.iter().filter(|p| p.kind == "anyone") {
+            public.push((subtree_size[&file.id as &str], file, permission));
+        }
+    }
+    public.sort_by(|(x, a, _), (y, b, _)| y.cmp(x).then_with(|| a.id.cmp(&b.id)));
+
+    let now = Utc::now();
+    let mut rows = vec![];
+    for (size, file, permission) in &public {
+        let expiration = match permission.expiration_time {
+            None => "never expires".to_owned(),
+            Some(time) if time <= now => format!("expired {}", time.format("%Y-%m-%d")),
+            Some(time) => format!("expires {}", time.format("%Y-%m-%d")),
+        };
+        let path = full_path(&index, file)?;
+        outln!(
+            "{:>12}  {:9}  {expiration:18}  {path}",
+            format_size(*size),
+            permission.role,
+        );
+        rows.push(json!({
+            "path": path,
+            "size": size,
+            "role": permission.role,
+            "expiration_time": permission.expiration_time,
+        }));
+    }
+    outln!(
+        "{} files and folders are accessible to anyone with the link",
+        public.len()
+    );
+    output::emit(&rows)
+}
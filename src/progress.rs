@@ -0,0 +1,87 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::{format_size, File};
+
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Stops drawing the progress bars for the rest of the process, for runs whose output is
+/// collected rather than watched.
+pub fn hide_progress() {
+    HIDDEN.store(true, Ordering::SeqCst);
+}
+
+/// Progress of a crawl drawn on the terminal.  Since the API does not tell the number of files
+/// in advance, the bar advances by the quota used by the crawled files against the usage reported
+/// by `about.get`, and is a spinner when the usage is unknown.
+pub struct CrawlProgress {
+    bar: ProgressBar,
+    start: Instant,
+    /// Pages and files fetched in this run, excluding those resumed from
+    pages: usize,
+    files: usize,
+}
+impl CrawlProgress {
+    /// `crawled_bytes` is the quota used by the files fetched by the crawl being resumed.
+    pub fn new(total_bytes: Option<u64>, crawled_bytes: u64) -> Self {
+        let bar = match total_bytes {
+            Some(total) => {
+                let bar = ProgressBar::new(total.max(crawled_bytes));
+                bar.set_style(
+                    ProgressStyle::with_template(
+                        "[{elapsed_precise}] {wide_bar} {percent:>3}% ETA {eta}  {msg}",
+                    )
+                    .expect("the template is valid"),
+                );
+                bar
+            }
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(
+                    ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}")
+                        .expect("the template is valid"),
+                );
+                bar
+            }
+        };
+        if HIDDEN.load(Ordering::SeqCst) {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        bar.set_position(crawled_bytes);
+        // Otherwise the resumed part would count as fetched instantly
+        bar.reset_eta();
+        bar.enable_steady_tick(Duration::from_millis(200));
+        Self {
+            bar,
+            start: Instant::now(),
+            pages: 0,
+            files: 0,
+        }
+    }
+    pub fn page(&mut self, page_count: usize, files: &[File]) {
+        self.pages += 1;
+        self.files += files.len();
+        let bytes: u64 = files.iter().filter_map(|f| f.quota_bytes_used).sum();
+        if let Some(total) = self.bar.length() {
+            // Files added during the crawl may take it past the usage fetched at the start
+            self.bar.set_length(total.max(self.bar.position() + bytes));
+        }
+        self.bar.inc(bytes);
+        let secs = self.start.elapsed().as_secs_f64().max(1e-3);
+        self.bar.set_message(format!(
+            "page {page_count} ({:.1}/s), {} files ({:.0}/s), {}",
+            self.pages as f64 / secs,
+            self.files,
+            self.files as f64 / secs,
+            format_size(self.bar.position()),
+        ));
+    }
+    /// Removes the bar, leaving the summary to the log.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,37 @@
+use std::{error::Error, future::Future, pin::Pin};
+
+use google_drive3::client::GetToken;
+
+/// Authenticator that only ever asks for read-only scopes, so that the tokens it obtains cannot
+/// modify anything even if a mutating call slips through
+#[derive(Clone)]
+pub struct ReadOnlyAuth<A>(pub A);
+
+/// The read-only counterpart of the scope.  Those with none are narrowed to `drive.readonly`,
+/// except `drive.appdata`, which covers only the hidden data of this app and has no read-only
+/// counterpart.
+fn read_only_scope(scope: &str) -> &str {
+    if scope.ends_with(".readonly") || scope == "https://www.googleapis.com/auth/drive.appdata" {
+        scope
+    } else if scope == "https://www.googleapis.com/auth/drive.metadata" {
+        "https://www.googleapis.com/auth/drive.metadata.readonly"
+    } else {
+        "https://www.googleapis.com/auth/drive.readonly"
+    }
+}
+
+impl<A: GetToken + Clone + 'static> GetToken for ReadOnlyAuth<A> {
+    fn get_token<'a>(
+        &'a self,
+        scopes: &'a [&str],
+    ) -> Pin<
+        Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + Send + Sync>>> + Send + 'a>,
+    > {
+        Box::pin(async move {
+            let mut scopes: Vec<_> = scopes.iter().map(|s| read_only_scope(s)).collect();
+            scopes.sort();
+            scopes.dedup();
+            self.0.get_token(&scopes).await
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use std::{error::Error, future::Future, pin::Pin, sync::Arc};
+
+use drive_crawl::{connect::Connector, retry::TokenEpoch, CancellationToken};
+use google_drive3::{client::GetToken, oauth2::authenticator::Authenticator};
+use log::warn;
+use tokio::sync::Mutex;
+
+/// Authenticator that obtains a new token once Drive has rejected the current one, as when it
+/// has been revoked before it expires.  A refresh token that has been revoked as well makes the
+/// authenticator sign in again, which fails with the dedicated exit code in non-interactive mode.
+#[derive(Clone)]
+pub struct Reauthenticating {
+    auth: Authenticator<Connector>,
+    /// Shared with the [`RetryPolicy`](drive_crawl::retry::RetryPolicy) of the calls
+    epoch: TokenEpoch,
+    /// Held while obtaining a new token, so that the calls rejected together refresh it once
+    refreshing: Arc<Mutex<()>>,
+    /// Cancelled once signing in would wait for a code that nobody enters
+    sign_in_aborted: CancellationToken,
+}
+
+impl Reauthenticating {
+    pub fn new(
+        auth: Authenticator<Connector>,
+        epoch: TokenEpoch,
+        sign_in_aborted: CancellationToken,
+    ) -> Self {
+        Self {
+            auth,
+            epoch,
+            refreshing: Default::default(),
+            sign_in_aborted,
+        }
+    }
+
+    async fn token(&self, scopes: &[&str]) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        if self.epoch.is_rejected() {
+            let _refreshing = self.refreshing.lock().await;
+            // Another call may have obtained one while this one was waiting
+            if self.epoch.is_rejected() {
+                warn!("Drive rejected the access token, so obtaining a new one");
+                // Replaces the cached token, which would otherwise be handed out until it
+                // expires
+                let token = self.auth.force_refreshed_token(scopes).await?;
+                self.epoch.refreshed();
+                return Ok(token.token().map(str::to_owned));
+            }
+        }
+        self.auth.get_token(scopes).await
+    }
+}
+
+impl GetToken for Reauthenticating {
+    fn get_token<'a>(
+        &'a self,
+        scopes: &'a [&str],
+    ) -> Pin<
+        Box<dyn Future<Output = Result<Option<String>, Box<dyn Error + Send + Sync>>> + Send + 'a>,
+    > {
+        Box::pin(async move {
+            tokio::select! {
+                res = self.token(scopes) => res,
+                () = self.sign_in_aborted.cancelled() => {
+                    Err("Signing in is required.  Run once without --non-interactive.".into())
+                }
+            }
+        })
+    }
+}
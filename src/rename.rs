@@ -0,0 +1,198 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use google_drive3::api;
+use log::{error, info, warn};
+use regex::Regex;
+use serde_json::json;
+
+use crate::{
+    audit::AuditLog, drive_api::DriveApi, full_path, is_under, restore_files, retry::RetryPolicy,
+    search::Pattern, CancellationToken, MemoryIndex, Store,
+};
+
+/// How the names are changed, in the order of the fields
+#[derive(Default)]
+pub struct Transform {
+    /// Removed from the start of the names that have it
+    pub strip_prefix: Option<String>,
+    /// Occurrences of the regex replaced with the template, where `$1` refers to a group
+    pub replace: Option<(Regex, String)>,
+    /// Collapse runs of whitespace into a single space and trim both ends
+    pub normalize_whitespace: bool,
+}
+impl Transform {
+    fn apply(&self, name: &str) -> String {
+        let mut name = name.to_owned();
+        if let Some(rest) = self
+            .strip_prefix
+            .as_deref()
+            .and_then(|prefix| name.strip_prefix(prefix))
+        {
+            name = rest.to_owned();
+        }
+        if let Some((ref regex, ref template)) = self.replace {
+            name = regex.replace_all(&name, template.as_str()).into_owned();
+        }
+        if self.normalize_whitespace {
+            name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        name
+    }
+}
+
+/// Which files are renamed; all of the conditions given have to hold
+pub struct Scope<'a> {
+    /// Only the items under this folder, at any depth
+    pub folder_id: Option<&'a str>,
+    /// Only the items whose names match
+    pub pattern: Option<&'a Pattern>,
+}
+
+/// Renames the items in the scope whose names the transform changes, after previewing the new
+/// names and asking for confirmation.
+pub async fn rename(
+    drive: &impl DriveApi,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    transform: &Transform,
+    scope: &Scope<'_>,
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut renames = vec![];
+    for file in &files {
+        if file.trashed
+            || scope
+                .folder_id
+                .is_some_and(|id| !is_under(&index, file, id))
+            || scope.pattern.is_some_and(|p| !p.is_match(&file.name))
+        {
+            continue;
+        }
+        let name = transform.apply(&file.name);
+        if name.is_empty() {
+            warn!(
+                "Skipping {} ({}), which would have no name",
+                file.name, file.id
+            );
+        } else if name != file.name {
+            renames.push((full_path(&index, file)?, file, name));
+        }
+    }
+    renames.sort_by(|x, y| x.0.cmp(&y.0));
+
+    // Drive allows the same name in a folder, but it is rarely intended
+    let mut new_paths = HashMap::<_, Vec<_>>::new();
+    for (_, file, name) in &renames {
+        new_paths
+            .entry((file.parents.first(), name))
+            .or_default()
+            .push(&file.id);
+    }
+    let renaming: HashSet<_> = renames.iter().map(|(_, file, _)| &file.id).collect();
+    for file in files
+        .iter()
+        .filter(|f| !f.trashed && !renaming.contains(&f.id))
+    {
+        if let Some(ids) = new_paths.get_mut(&(file.parents.first(), &file.name)) {
+            ids.push(&file.id);
+        }
+    }
+
+    for (path, file, name) in &renames {
+        let clash = if new_paths[&(file.parents.first(), name)].len() > 1 {
+            "  (name taken in the folder)"
+        } else {
+            ""
+        };
+        outln!("{path} → {name}{clash}");
+    }
+    let message = format!("{} items", renames.len());
+    if audit.dry_run() {
+        outln!("Would rename {message}");
+        return Ok(());
+    }
+    if renames.is_empty() {
+        outln!("No items to rename");
+        return Ok(());
+    }
+    eprint!("Rename {message}? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        outln!("Cancelled");
+        return Ok(());
+    }
+
+    let (mut done, mut failed) = (0, 0);
+    for (_, file, name) in &renames {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let renamed = api::File {
+            name: Some(name.clone()),
+            ..Default::default()
+        };
+        let res = retry
+            .run(|| drive.update_file(&file.id, renamed.clone(), "id"))
+            .await;
+        if let Err(e) = res {
+            error!("Failed to rename {} ({}): {e}", file.name, file.id);
+            failed += 1;
+            continue;
+        }
+        audit.record(
+            "rename",
+            &file.id,
+            json!({ "name": file.name }),
+            json!({ "name": name }),
+        )?;
+        done += 1;
+    }
+    outln!("Renamed {done} items, failed {failed}");
+    if done > 0 {
+        outln!("Run sync to reflect the changes in the snapshot.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_the_steps_in_order() {
+        let transform = Transform {
+            strip_prefix: Some("Copy of ".into()),
+            replace: Some((Regex::new(r"IMG_(\d+)").unwrap(), "Photo $1".into())),
+            normalize_whitespace: true,
+        };
+        assert_eq!(
+            transform.apply("Copy of  IMG_0042 .jpg "),
+            "Photo 0042 .jpg"
+        );
+        // The prefix is stripped only from the start
+        assert_eq!(transform.apply("A Copy of IMG_1"), "A Copy of Photo 1");
+    }
+
+    #[test]
+    fn leaves_names_without_steps_as_is() {
+        assert_eq!(Transform::default().apply("  a  b "), "  a  b ");
+    }
+
+    #[test]
+    fn strips_the_prefix_once() {
+        let transform = Transform {
+            strip_prefix: Some("x".into()),
+            ..Default::default()
+        };
+        assert_eq!(transform.apply("xxy"), "xy");
+    }
+}
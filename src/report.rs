@@ -0,0 +1,468 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use anyhow::bail;
+use chrono::Utc;
+use clap::ValueEnum;
+use log::{error, info};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    format_size, full_path, history::TIMESTAMP_FORMAT, output, permissions::show_public,
+    restore_files, restore_provenance, root_name, root_names, show_duplicates, show_overview,
+    types::show_by_type, with_index, FileIndex, MemoryIndex, Store,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// Self-contained zoomable treemap
+    Html,
+    /// One row per folder down to `--depth` with the cumulative usage broken down by owner, to
+    /// attribute storage costs
+    FolderCsv,
+    /// The overview, the usage by type, the public files, the largest files and the duplicates
+    /// as text, JSON and CSV in a new timestamped directory under the output, made read-only and
+    /// listed with their SHA256 in a manifest, as periodic evidence for audits
+    Archive,
+}
+
+/// A folder or file of the treemap.  Field names are kept short since there can be many.
+#[derive(Serialize)]
+struct Node {
+    #[serde(rename = "n")]
+    name: String,
+    #[serde(rename = "s")]
+    size: u64,
+    #[serde(rename = "c", skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Node>,
+}
+
+/// Builds the subtree with the cumulative sizes, merging the children smaller than `min_size`
+/// into one node to keep the report small.
+fn build(
+    index: &dyn FileIndex,
+    id: &str,
+    name: String,
+    size: u64,
+    min_size: u64,
+) -> anyhow::Result<Node> {
+    let mut children = vec![];
+    for child in index.children(id)? {
+        let bytes = child.quota_bytes_used.unwrap_or(0);
+        children.push(build(index, &child.id, child.name, bytes, min_size)?);
+    }
+    let size = size + children.iter().map(|c| c.size).sum::<u64>();
+    children.sort_by_key(|c| std::cmp::Reverse(c.size));
+    let small = children.partition_point(|c| c.size >= min_size);
+    if children.len() - small >= 2 {
+        let rest = children.split_off(small);
+        children.push(Node {
+            name: format!("({} smaller items)", rest.len()),
+            size: rest.iter().map(|c| c.size).sum(),
+            children: vec![],
+        });
+    }
+    Ok(Node {
+        name,
+        size,
+        children,
+    })
+}
+
+/// A folder of the CSV report
+#[derive(Serialize)]
+struct FolderRow {
+    path: String,
+    depth: usize,
+    bytes: u64,
+    files: u64,
+    /// Bytes by the email address of the owner, as `owner=bytes` separated by `;`
+    owners: String,
+}
+
+/// Usage of a subtree
+#[derive(Default)]
+struct Tally {
+    bytes: u64,
+    files: u64,
+    owners: BTreeMap<String, u64>,
+}
+
+/// Tallies the subtree, adding a row for each folder no deeper than `max_depth`.
+fn tally(
+    index: &dyn FileIndex,
+    id: &str,
+    path: &str,
+    depth: usize,
+    max_depth: usize,
+    rows: &mut Vec<FolderRow>,
+) -> anyhow::Result<Tally> {
+    let mut total = Tally::default();
+    for child in index.children(id)? {
+        let child_path = format!("{path}/{}", child.name);
+        let usage = if child.mime_type == "application/vnd.google-apps.folder" {
+            tally(index, &child.id, &child_path, depth + 1, max_depth, rows)?
+        } else {
+            let bytes = child.quota_bytes_used.unwrap_or(0);
+            // Files in shared drives are owned by the drive rather than by anyone
+            let owner = match child.owners.first() {
+                Some(owner) => owner
+                    .email_address
+                    .clone()
+                    .unwrap_or_else(|| String::from("(unknown)")),
+                None => String::from("(shared drive)"),
+            };
+            Tally {
+                bytes,
+                files: 1,
+                owners: BTreeMap::from([(owner, bytes)]),
+            }
+        };
+        total.bytes += usage.bytes;
+        total.files += usage.files;
+        for (owner, bytes) in usage.owners {
+            *total.owners.entry(owner).or_default() += bytes;
+        }
+    }
+    if depth <= max_depth {
+        let mut owners: Vec<_> = total.owners.iter().collect();
+        owners.sort_by_key(|&(_, &bytes)| std::cmp::Reverse(bytes));
+        rows.push(FolderRow {
+            path: path.to_owned(),
+            depth,
+            bytes: total.bytes,
+            files: total.files,
+            owners: owners
+                .iter()
+                .map(|(owner, bytes)| format!("{owner}={bytes}"))
+                .collect::<Vec<_>>()
+                .join(";"),
+        });
+    }
+    Ok(total)
+}
+
+fn write_folder_csv(store: &Store, output: &Path, max_depth: usize) -> anyhow::Result<()> {
+    let root_names = root_names(store)?;
+    let mut rows = with_index(store, |index| {
+        let mut rows = vec![];
+        for id in index.roots()? {
+            let name = match index.get(&id)? {
+                Some(file) => file.name,
+                None => root_name(&root_names, &id),
+            };
+            tally(index, &id, &name, 0, max_depth, &mut rows)?;
+        }
+        Ok(rows)
+    })?;
+    rows.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut csv = csv::Writer::from_writer(fs_err::File::create(output)?);
+    for row in &rows {
+        csv.serialize(row)?;
+    }
+    csv.flush()?;
+    info!("Wrote {} folders to {output:?}", rows.len());
+    Ok(())
+}
+
+pub fn write_report(
+    store: &Store,
+    format: ReportFormat,
+    output: &Path,
+    min_size: u64,
+    depth: usize,
+) -> anyhow::Result<()> {
+    match format {
+        ReportFormat::Html => write_html(store, output, min_size),
+        ReportFormat::FolderCsv => write_folder_csv(store, output, depth),
+        ReportFormat::Archive => write_archive(store, output),
+    }
+}
+
+/// Writes the treemap as a self-contained HTML page.
+fn write_html(store: &Store, output: &Path, min_size: u64) -> anyhow::Result<()> {
+    let root_names = root_names(store)?;
+    let roots = with_index(store, |index| {
+        let mut roots = vec![];
+        for id in index.roots()? {
+            let (name, size) = match index.get(&id)? {
+                Some(file) => (file.name, file.quota_bytes_used.unwrap_or(0)),
+                None => (root_name(&root_names, &id), 0),
+            };
+            roots.push(build(index, &id, name, size, min_size)?);
+        }
+        Ok(roots)
+    })?;
+    let mut tree = Node {
+        name: String::from("All"),
+        size: roots.iter().map(|r| r.size).sum(),
+        children: roots,
+    };
+    tree.children.sort_by_key(|c| std::cmp::Reverse(c.size));
+
+    // Prevent a name containing `</script>` from closing the script element
+    let data = serde_json::to_string(&tree)?.replace("</", "<\\/");
+    let mut file = fs_err::File::create(output)?;
+    file.write_all(HTML_TEMPLATE.replace("__DATA__", &data).as_bytes())?;
+    info!("Wrote the report to {output:?}");
+    Ok(())
+}
+
+/// Number of files listed by the `largest` report of an archive
+const LARGEST_FILES: usize = 1000;
+
+/// Lists the `top` largest files outside the trash.
+fn show_largest(store: &Store, top: usize) -> anyhow::Result<()> {
+    let mut files = restore_files(store)?;
+    files.retain(|f| !f.trashed);
+    let index = MemoryIndex::new(&files)?;
+    let mut largest: Vec<_> = files.iter().collect();
+    largest.sort_by(|a, b| {
+        b.quota_bytes_used
+            .cmp(&a.quota_bytes_used)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    largest.truncate(top);
+    let mut rows = vec![];
+    for file in largest {
+        let path = full_path(&index, file)?;
+        let bytes = file.quota_bytes_used.unwrap_or(0);
+        outln!("{:>12}  {path}", format_size(bytes));
+        rows.push(json!({
+            "id": file.id,
+            "path": path,
+            "bytes": bytes,
+            "mime_type": file.mime_type,
+            "owner": file.owners.first().and_then(|o| o.email_address.as_deref()),
+            "modified_time": file.modified_time,
+        }));
+    }
+    output::emit(&rows)
+}
+
+/// The rows as CSV if the report is a list of objects, with nested values written as JSON
+fn to_csv(report: &Value) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(rows) = report.as_array() else {
+        return Ok(None);
+    };
+    let Some(first) = rows.first().and_then(|row| row.as_object()) else {
+        return Ok(None);
+    };
+    let columns: Vec<_> = first.keys().cloned().collect();
+    let mut csv = csv::Writer::from_writer(vec![]);
+    csv.write_record(&columns)?;
+    for row in rows {
+        csv.write_record(columns.iter().map(|column| match &row[column] {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        }))?;
+    }
+    Ok(Some(csv.into_inner().map_err(|e| e.into_error())?))
+}
+
+/// Writes a file of the archive, which must not exist yet, and makes it read-only.  Returns its
+/// entry in the manifest.
+fn write_once(path: &Path, data: &[u8]) -> anyhow::Result<Value> {
+    let mut file = fs_err::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(true);
+    fs_err::set_permissions(path, permissions)?;
+    let sha256: String = Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(json!({
+        "name": path.file_name().map(|name| name.to_string_lossy()),
+        "bytes": data.len(),
+        "sha256": sha256,
+    }))
+}
+
+/// Prints a report of the snapshot through [`output`]
+type Report = fn(&Store) -> anyhow::Result<()>;
+
+/// Runs the standard reports into a new directory named after the current time under `dir`.
+/// Every report is attempted even if some fail, such as `public` before `permissions` is run,
+/// and the failures are recorded in the manifest.
+fn write_archive(store: &Store, dir: &Path) -> anyhow::Result<()> {
+    let reports: [(&str, Report); 5] = [
+        ("overview", |store| show_overview(store, true, false, false)),
+        ("by-type", |store| show_by_type(store, false)),
+        ("public", show_public),
+        ("largest", |store| show_largest(store, LARGEST_FILES)),
+        ("duplicates", show_duplicates),
+    ];
+    let generated_at = Utc::now();
+    let archive = dir.join(generated_at.format(TIMESTAMP_FORMAT).to_string());
+    fs_err::create_dir_all(dir)?;
+    // Never adds to nor overwrites an earlier archive
+    fs_err::create_dir(&archive)?;
+
+    let mut files = vec![];
+    let mut failed = vec![];
+    for (name, report) in reports {
+        let captured = match output::capture(|| report(store)) {
+            Ok(captured) => captured,
+            Err(e) => {
+                error!("The {name} report failed: {e:#}");
+                failed.push(json!({ "report": name, "error": format!("{e:#}") }));
+                continue;
+            }
+        };
+        let path = |extension: &str| archive.join(format!("{name}.{extension}"));
+        files.push(write_once(&path("txt"), captured.text.as_bytes())?);
+        let report = captured.json.unwrap_or_default();
+        files.push(write_once(
+            &path("json"),
+            &serde_json::to_vec_pretty(&report)?,
+        )?);
+        if let Some(csv) = to_csv(&report)? {
+            files.push(write_once(&path("csv"), &csv)?);
+        }
+    }
+    let snapshot = store
+        .snapshot
+        .clone()
+        .unwrap_or_else(|| store.file_list_path());
+    let manifest = json!({
+        "generated_at": generated_at,
+        "tool_version": env!("CARGO_PKG_VERSION"),
+        "crawl": restore_provenance(&snapshot)?,
+        "files": files,
+        "failed": failed,
+    });
+    write_once(
+        &archive.join("manifest.json"),
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    info!(
+        "Archived {} reports to {archive:?}",
+        reports.len() - failed.len()
+    );
+    if !failed.is_empty() {
+        bail!(
+            "{} of {} reports failed, as recorded in the manifest",
+            failed.len(),
+            reports.len()
+        );
+    }
+    Ok(())
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Drive usage</title>
+<style>
+  body { margin: 0; font: 13px sans-serif; display: flex; flex-direction: column; height: 100vh; }
+  #path { padding: 6px; background: #333; color: #fff; }
+  #path span { cursor: pointer; text-decoration: underline; }
+  #map { position: relative; flex: 1; overflow: hidden; }
+  .box { position: absolute; box-sizing: border-box; border: 1px solid #fff; overflow: hidden;
+         white-space: nowrap; padding: 1px 3px; }
+  .folder { cursor: zoom-in; }
+</style>
+</head>
+<body>
+<div id="path"></div>
+<div id="map"></div>
+<script>
+const data = __DATA__;
+const stack = [data];
+
+function formatSize(size) {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let i = 0;
+  while (size >= 1024 && i + 1 < units.length) { size /= 1024; i++; }
+  return (i ? size.toFixed(2) : size) + " " + units[i];
+}
+
+function worst(row, side) {
+  const sum = row.reduce((a, r) => a + r.area, 0);
+  const max = Math.max(...row.map(r => r.area));
+  const min = Math.min(...row.map(r => r.area));
+  return Math.max(side * side * max / (sum * sum), sum * sum / (side * side * min));
+}
+
+// Squarified treemap layout of items sorted in descending order of area
+function squarify(items, x, y, w, h) {
+  const out = [];
+  while (items.length) {
+    const side = Math.min(w, h);
+    let row = [], best = Infinity, i = 0;
+    for (; i < items.length; i++) {
+      const next = worst(row.concat([items[i]]), side);
+      if (next > best) break;
+      row.push(items[i]);
+      best = next;
+    }
+    items = items.slice(i);
+    const thickness = row.reduce((a, r) => a + r.area, 0) / side;
+    let offset = 0;
+    for (const r of row) {
+      const length = r.area / thickness;
+      if (w >= h) out.push({ node: r.node, x: x, y: y + offset, w: thickness, h: length });
+      else out.push({ node: r.node, x: x + offset, y: y, w: length, h: thickness });
+      offset += length;
+    }
+    if (w >= h) { x += thickness; w -= thickness; } else { y += thickness; h -= thickness; }
+  }
+  return out;
+}
+
+function draw(parent, node, path, x, y, w, h, depth) {
+  const children = (node.c || []).filter(c => c.s > 0);
+  if (!children.length || w < 4 || h < 4) return;
+  const scale = w * h / node.s;
+  const items = children.map(c => ({ node: c, area: c.s * scale }));
+  for (const r of squarify(items, x, y, w, h)) {
+    if (r.w < 1 || r.h < 1) continue;
+    const child = r.node, childPath = path + "/" + child.n;
+    const box = document.createElement("div");
+    box.className = "box" + (child.c ? " folder" : "");
+    box.style.left = r.x + "px";
+    box.style.top = r.y + "px";
+    box.style.width = r.w + "px";
+    box.style.height = r.h + "px";
+    box.style.background = `hsl(${(depth * 67 + child.n.length * 13) % 360}, 50%, ${85 - depth * 8}%)`;
+    box.title = childPath + "\n" + formatSize(child.s);
+    box.textContent = child.n;
+    if (child.c) {
+      box.onclick = e => { e.stopPropagation(); stack.push(child); render(); };
+    }
+    parent.appendChild(box);
+    if (depth < 3) draw(parent, child, childPath, r.x + 3, r.y + 16, r.w - 6, r.h - 19, depth + 1);
+  }
+}
+
+function render() {
+  const header = document.getElementById("path");
+  header.textContent = "";
+  stack.forEach((node, i) => {
+    const link = document.createElement("span");
+    link.textContent = node.n;
+    link.onclick = () => { stack.length = i + 1; render(); };
+    header.appendChild(link);
+    header.appendChild(document.createTextNode(i + 1 < stack.length ? " / " : ""));
+  });
+  header.appendChild(document.createTextNode("  (" + formatSize(stack[stack.length - 1].s) + ")"));
+  const map = document.getElementById("map");
+  map.textContent = "";
+  const path = stack.slice(1).map(n => n.n).join("/");
+  draw(map, stack[stack.length - 1], path, 0, 0, map.clientWidth, map.clientHeight, 0);
+}
+
+window.onresize = render;
+render();
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,193 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use google_drive3::Error;
+use log::warn;
+use rand::Rng;
+
+use crate::{budget::ApiBudget, CancellationToken};
+
+/// How failed API calls are retried with exponential backoff
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Number of attempts including the first one
+    pub max_attempts: u32,
+    /// Rate limit and statistics shared by every call of the run
+    pub budget: &'static ApiBudget,
+    /// Longest an attempt may take before it is abandoned and retried, if limited
+    pub timeout: Option<Duration>,
+    /// Abandons the call in flight once cancelled, so that a stuck request cannot keep the
+    /// progress from being saved
+    pub cancel: Option<CancellationToken>,
+    /// Shared with the authenticator of the client the calls are made through
+    pub token_epoch: TokenEpoch,
+}
+
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(64);
+
+/// Generation of the access token of a client, shared by its [`RetryPolicy`] and its
+/// authenticator.  A token rejected by Drive is replaced once, however many calls it failed.
+#[derive(Clone, Default)]
+pub struct TokenEpoch(Arc<Epochs>);
+#[derive(Default)]
+struct Epochs {
+    /// Incremented whenever the authenticator obtains a new token
+    current: AtomicU64,
+    /// The tokens of the generations below this one have been rejected
+    rejected: AtomicU64,
+}
+impl TokenEpoch {
+    /// Generation of the token handed out now
+    pub fn current(&self) -> u64 {
+        self.0.current.load(Ordering::SeqCst)
+    }
+    /// Records that Drive has rejected the token of the generation.  A call that started
+    /// before the token was replaced does not make the authenticator replace it again.
+    pub fn reject(&self, epoch: u64) {
+        self.0.rejected.fetch_max(epoch + 1, Ordering::SeqCst);
+    }
+    /// Whether the token handed out now has been rejected, so that a new one should be obtained
+    pub fn is_rejected(&self) -> bool {
+        self.current() < self.0.rejected.load(Ordering::SeqCst)
+    }
+    /// Records that the authenticator has obtained a new token.
+    pub fn refreshed(&self) {
+        self.0.current.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl RetryPolicy {
+    /// The same policy without the timeout, for transfers of content that take as long as the
+    /// file needs.  They can still be cancelled.
+    pub fn without_timeout(&self) -> Self {
+        Self {
+            timeout: None,
+            ..self.clone()
+        }
+    }
+
+    /// Calls `f` until it succeeds, fails with an error that is not worth retrying, or runs out of
+    /// attempts.  `f` has to rebuild the call every time since calls are consumed by `doit`.
+    pub async fn run<T, Fut>(&self, mut f: impl FnMut() -> Fut) -> google_drive3::Result<T>
+    where
+        Fut: Future<Output = google_drive3::Result<T>>,
+    {
+        let mut delay = INITIAL_DELAY;
+        let mut attempt = 1;
+        let mut reauthenticated = false;
+        loop {
+            self.budget.acquire().await;
+            let epoch = self.token_epoch.current();
+            let attempt_result = async {
+                match self.timeout {
+                    Some(timeout) => {
+                        tokio::time::timeout(timeout, f())
+                            .await
+                            .unwrap_or_else(|_| {
+                                Err(Error::Io(std::io::Error::new(
+                                    std::io::ErrorKind::TimedOut,
+                                    format!("No response in {timeout:?}"),
+                                )))
+                            })
+                    }
+                    None => f().await,
+                }
+            };
+            let result = tokio::select! {
+                result = attempt_result => result,
+                () = cancelled(self.cancel.as_ref()) => return Err(Error::Cancelled),
+            };
+            match result {
+                // A revoked token fails the same way until replaced, so the call is retried
+                // right away with a new one, but only once
+                Err(e) if !reauthenticated && is_unauthorized(&e) => {
+                    warn!("Retrying with a new access token: {e}");
+                    self.token_epoch.reject(epoch);
+                    reauthenticated = true;
+                }
+                Err(e) if attempt < self.max_attempts && is_retryable(&e) => {
+                    self.budget.record_retry(is_rate_limited(&e));
+                    // Jittered so that concurrent clients do not retry in lockstep
+                    let wait = delay.mul_f64(rand::thread_rng().gen_range(0.5..1.0));
+                    warn!(
+                        "Attempt {attempt}/{} failed, retrying in {wait:.1?}: {e}",
+                        self.max_attempts
+                    );
+                    tokio::select! {
+                        () = tokio::time::sleep(wait) => {}
+                        () = cancelled(self.cancel.as_ref()) => return Err(Error::Cancelled),
+                    }
+                    delay = (delay * 2).min(MAX_DELAY);
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.budget.record_error();
+                    return Err(e);
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Completes once cancelled, or never without a token.
+async fn cancelled(cancel: Option<&CancellationToken>) {
+    match cancel {
+        Some(cancel) => cancel.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Rate limits, server errors and network errors are transient; anything else (notably auth
+/// errors) would fail again the same way.
+fn is_retryable(error: &Error) -> bool {
+    match error {
+        Error::HttpError(_) | Error::Io(_) => true,
+        _ => status_of(error).is_some_and(|(code, reasons)| {
+            (500..=599).contains(&code) || is_rate_limit_status(code, &reasons)
+        }),
+    }
+}
+
+fn is_unauthorized(error: &Error) -> bool {
+    status_of(error).is_some_and(|(code, _)| code == 401)
+}
+
+fn is_rate_limited(error: &Error) -> bool {
+    status_of(error).is_some_and(|(code, reasons)| is_rate_limit_status(code, &reasons))
+}
+
+/// The HTTP status of a failed call, along with the reasons given in the body if any
+pub(crate) fn status_of(error: &Error) -> Option<(u16, Vec<&str>)> {
+    match error {
+        Error::Failure(res) => Some((res.status().as_u16(), vec![])),
+        Error::BadRequest(value) => {
+            let code = value["error"]["code"].as_u64()?;
+            let reasons = value["error"]["errors"]
+                .as_array()
+                .map_or(&[][..], |x| x)
+                .iter()
+                .filter_map(|e| e["reason"].as_str())
+                .collect();
+            Some((code as u16, reasons))
+        }
+        _ => None,
+    }
+}
+
+fn is_rate_limit_status(code: u16, reasons: &[&str]) -> bool {
+    match code {
+        429 => true,
+        403 => reasons
+            .iter()
+            .any(|&r| r == "userRateLimitExceeded" || r == "rateLimitExceeded"),
+        _ => false,
+    }
+}
@@ -0,0 +1,172 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::{
+    audit::AuditLog, enrich::Pipeline, format_size, full_path, restore_files, retry::RetryPolicy,
+    CancellationToken, Drive, MemoryIndex, Store,
+};
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Revision {
+    pub(crate) id: String,
+    #[serde(rename = "modifiedTime")]
+    pub(crate) modified_time: Option<DateTime<Utc>>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub(crate) size: Option<u64>,
+    /// Pinned revisions are never purged
+    #[serde(rename = "keepForever", default)]
+    pub(crate) keep_forever: bool,
+}
+#[derive(Deserialize)]
+struct RevisionList {
+    #[serde(default)]
+    revisions: Vec<Revision>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+/// Revisions of the file, oldest first, so that the last one is the head.
+pub(crate) async fn list_revisions(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    file_id: &str,
+) -> anyhow::Result<Vec<Revision>> {
+    let mut revisions = vec![];
+    let mut token = None::<String>;
+    loop {
+        let (_, res) = retry
+            .run(|| {
+                let mut call = drive.revisions().list(file_id).param(
+                    "fields",
+                    "nextPageToken,revisions(id,modifiedTime,size,keepForever)",
+                );
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await?;
+        let res: RevisionList = serde_json::from_str(&serde_json::to_string(&res)?)?;
+        revisions.extend(res.revisions);
+        token = res.next_page_token;
+        if token.is_none() {
+            return Ok(revisions);
+        }
+    }
+}
+
+/// Lists the revisions of files whose quota usage exceeds their size by `threshold` bytes, and
+/// deletes the old ones if `purge` is set.  The revisions are fetched `concurrency` files at a
+/// time before anything is printed.
+#[allow(clippy::too_many_arguments)]
+pub async fn audit_revisions(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    threshold: u64,
+    purge: bool,
+    concurrency: usize,
+    audit: &mut AuditLog,
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut candidates: Vec<_> = files
+        .iter()
+        .filter(|file| !file.mime_type.starts_with("application/vnd.google-apps."))
+        .filter_map(|file| {
+            let excess = file.quota_bytes_used?.checked_sub(file.size?)?;
+            (excess >= threshold).then_some((excess, file))
+        })
+        .collect();
+    candidates.sort_by_key(|&(excess, _)| std::cmp::Reverse(excess));
+    info!("{} files exceed the threshold", candidates.len());
+
+    let pipeline = Pipeline {
+        cancel,
+        concurrency,
+        checkpoint: store.dir.join("revisions-checkpoint.json"),
+    };
+    let ids = candidates.iter().map(|(_, file)| file.id.clone());
+    let enriched = pipeline
+        .run("revisions", ids, |id| async move {
+            list_revisions(drive, retry, &id).await
+        })
+        .await?;
+    if !enriched.complete {
+        warn!("Interrupted while fetching the revisions.  Run again to resume.");
+        return Ok(());
+    }
+
+    let (mut purged, mut reclaimed) = (0, 0);
+    for (excess, file) in candidates {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        outln!(
+            "=== {} in old revisions: {} ===",
+            format_size(excess),
+            full_path(&index, file)?
+        );
+        let Some((head, old)) = enriched.results[&file.id].split_last() else {
+            continue;
+        };
+        for revision in old {
+            let time = revision
+                .modified_time
+                .map_or("?".into(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+            let size = revision.size.unwrap_or(0);
+            let pinned = if revision.keep_forever {
+                "  (kept forever)"
+            } else {
+                ""
+            };
+            outln!("{time}  {:>12}{pinned}", format_size(size));
+            if !purge || revision.keep_forever {
+                continue;
+            }
+            if audit.dry_run() {
+                outln!("  Would delete revision {}", revision.id);
+            } else if let Err(e) = retry
+                .run(|| drive.revisions().delete(&file.id, &revision.id).doit())
+                .await
+            {
+                warn!(
+                    "Failed to delete revision {} of {}: {e}",
+                    revision.id, file.id
+                );
+                continue;
+            } else {
+                let old = serde_json::json!({
+                    "revision_id": revision.id,
+                    "modified_time": revision.modified_time,
+                    "size": revision.size,
+                });
+                audit.record("delete_revision", &file.id, old, ())?;
+            }
+            purged += 1;
+            reclaimed += size;
+        }
+        let time = head
+            .modified_time
+            .map_or("?".into(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+        outln!(
+            "{time}  {:>12}  (head)",
+            format_size(head.size.unwrap_or(0))
+        );
+    }
+    if purge {
+        let verb = if audit.dry_run() {
+            "Would delete"
+        } else {
+            "Deleted"
+        };
+        outln!("{verb} {purged} revisions, {}", format_size(reclaimed));
+    }
+    // Revisions fetched before a purge would be stale on the next run
+    pipeline.finish()
+}
@@ -0,0 +1,86 @@
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde_json::json;
+
+use crate::{format_size, full_path, output, restore_files, MarkFilter, MemoryIndex, Store};
+
+/// How the pattern of the search is interpreted
+pub enum Pattern {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+impl Pattern {
+    pub fn new(pattern: &str, regex: bool) -> anyhow::Result<Self> {
+        Ok(if regex {
+            Self::Regex(Regex::new(pattern)?)
+        } else {
+            Self::Glob(Glob::new(pattern)?.compile_matcher())
+        })
+    }
+    pub(crate) fn is_match(&self, s: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob.is_match(s),
+            Self::Regex(regex) => regex.is_match(s),
+        }
+    }
+}
+
+pub struct Filters<'a> {
+    /// Match against the full path instead of the name
+    pub full_path: bool,
+    /// Prefix of the mime type, like `image/`
+    pub mime_type: Option<&'a str>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub marks: MarkFilter,
+    /// Email address of one of the owners, compared case-insensitively
+    pub owner: Option<&'a str>,
+}
+
+pub fn search(store: &Store, pattern: &Pattern, filters: &Filters) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut found = vec![];
+    for file in &files {
+        let size = file.quota_bytes_used.unwrap_or(0);
+        if filters.min_size.is_some_and(|min| size < min)
+            || filters.max_size.is_some_and(|max| size > max)
+            || filters
+                .mime_type
+                .is_some_and(|mime| !file.mime_type.starts_with(mime))
+            || !filters.marks.matches(file)
+            || filters.owner.is_some_and(|owner| {
+                !file.owners.iter().any(|o| {
+                    o.email_address
+                        .as_deref()
+                        .is_some_and(|email| email.eq_ignore_ascii_case(owner))
+                })
+            })
+        {
+            continue;
+        }
+        let path = full_path(&index, file)?;
+        let target = if filters.full_path { &path } else { &file.name };
+        if pattern.is_match(target) {
+            let owners: Vec<_> = file
+                .owners
+                .iter()
+                .filter_map(|o| o.email_address.as_deref())
+                .collect();
+            found.push((path, size, owners));
+        }
+    }
+    found.sort();
+    for (path, size, _) in &found {
+        outln!("{:>12}  {path}", format_size(*size));
+    }
+    outln!(
+        "{} files, {} in total",
+        found.len(),
+        format_size(found.iter().map(|(_, size, _)| size).sum())
+    );
+    output::emit(&json!(found
+        .iter()
+        .map(|(path, size, owners)| json!({ "path": path, "size": size, "owners": owners }))
+        .collect::<Vec<_>>()))
+}
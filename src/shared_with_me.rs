@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use serde_json::{json, Value};
+
+use crate::{format_size, output, parents, restore_shared_with_me, Store};
+
+/// Items shared with me by one person or placed in one folder
+#[derive(Default)]
+struct Tally {
+    items: u64,
+    folders: u64,
+    /// Size of the files, excluding the contents of the folders which are not crawled
+    bytes: u64,
+}
+
+fn print_tallies(title: &str, tallies: HashMap<String, Tally>, top: usize) -> Value {
+    let mut tallies: Vec<_> = tallies.into_iter().collect();
+    tallies.sort_by(|(x, a), (y, b)| b.items.cmp(&a.items).then(x.cmp(y)));
+    outln!("=== {title} ===");
+    for (key, tally) in tallies.iter().take(top) {
+        outln!(
+            "{:>7} items  {:>6} folders  {:>12}  {key}",
+            tally.items,
+            tally.folders,
+            format_size(tally.bytes)
+        );
+    }
+    if tallies.len() > top {
+        outln!("... and {} more", tallies.len() - top);
+    }
+    json!(tallies
+        .iter()
+        .take(top)
+        .map(|(key, tally)| json!({
+            "key": key,
+            "items": tally.items,
+            "folders": tally.folders,
+            "bytes": tally.bytes,
+        }))
+        .collect::<Vec<_>>())
+}
+
+/// Shows who shared the most items with me and which of their folders the items are in, along
+/// with the largest items.
+pub fn show_shared_with_me(store: &Store, top: usize) -> anyhow::Result<()> {
+    let files = restore_shared_with_me(store)?;
+    if files.is_empty() {
+        bail!("No items shared with me found.  Run `crawl --shared-with-me` first.");
+    }
+    let folders = parents::restore(store)?;
+    let mut by_sharer = HashMap::<String, Tally>::new();
+    let mut by_folder = HashMap::<String, Tally>::new();
+    for file in &files {
+        // The sharer is unknown for items shared through a group, for example
+        let sharer = file
+            .sharing_user
+            .as_ref()
+            .or(file.owners.first())
+            .and_then(|u| u.email_address.clone())
+            .unwrap_or_else(|| "(unknown)".into());
+        let folder = match file.parents.first() {
+            Some(id) => folders.get(id).map_or_else(|| id.clone(), |f| f.label()),
+            None => "(no visible folder)".into(),
+        };
+        for tally in [
+            by_sharer.entry(sharer).or_default(),
+            by_folder.entry(folder).or_default(),
+        ] {
+            tally.items += 1;
+            if file.mime_type == "application/vnd.google-apps.folder" {
+                tally.folders += 1;
+            }
+            tally.bytes += file.size.unwrap_or(0);
+        }
+    }
+    outln!("{} items are shared with me", files.len());
+    let by_sharer = print_tallies("By sharer", by_sharer, top);
+    let by_folder = print_tallies("By folder", by_folder, top);
+
+    let mut largest: Vec<_> = files.iter().filter(|f| f.size.is_some()).collect();
+    largest.sort_by_key(|f| std::cmp::Reverse(f.size));
+    outln!("=== Largest files ===");
+    for file in largest.iter().take(top) {
+        outln!(
+            "{:>12}  {}  ({})",
+            format_size(file.size.unwrap_or(0)),
+            file.name,
+            file.id
+        );
+    }
+    output::emit(&json!({
+        "items": files.len(),
+        "by_sharer": by_sharer,
+        "by_folder": by_folder,
+        "largest": largest
+            .iter()
+            .take(top)
+            .map(|f| json!({ "id": f.id, "name": f.name, "size": f.size }))
+            .collect::<Vec<_>>(),
+    }))
+}
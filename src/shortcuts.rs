@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    drive_api::DriveApi,
+    enrich::Pipeline,
+    full_path, output, restore_files,
+    retry::{status_of, RetryPolicy},
+    CancellationToken, MemoryIndex, Store,
+};
+
+/// What Drive tells about the target of a broken shortcut
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum Target {
+    /// Exists but is not in the snapshot, like a file owned by someone else
+    Found {
+        name: String,
+        owner: Option<String>,
+        trashed: bool,
+    },
+    /// Deleted, or no longer shared with me
+    Inaccessible,
+}
+impl Target {
+    fn describe(&self) -> String {
+        match self {
+            Self::Found {
+                name,
+                owner,
+                trashed,
+            } => format!(
+                "{name} owned by {}{}",
+                owner.as_deref().unwrap_or("?"),
+                if *trashed { ", trashed" } else { "" }
+            ),
+            Self::Inaccessible => "inaccessible".into(),
+        }
+    }
+}
+
+async fn fetch_target(
+    api: &impl DriveApi,
+    retry: &RetryPolicy,
+    id: &str,
+) -> anyhow::Result<Target> {
+    match retry
+        .run(|| api.get_file(id, "name,owners(emailAddress),trashed"))
+        .await
+    {
+        Ok(file) => Ok(Target::Found {
+            name: file.name.unwrap_or_default(),
+            owner: file
+                .owners
+                .and_then(|owners| owners.into_iter().next())
+                .and_then(|owner| owner.email_address),
+            trashed: file.trashed.unwrap_or(false),
+        }),
+        Err(e) if status_of(&e).is_some_and(|(code, _)| code == 403 || code == 404) => {
+            Ok(Target::Inaccessible)
+        }
+        Err(e) => Err(e)?,
+    }
+}
+
+/// Looks up the targets of the broken shortcuts in Drive, `concurrency` at a time
+pub struct Resolve<'a, A> {
+    pub api: &'a A,
+    pub cancel: &'a CancellationToken,
+    pub retry: &'a RetryPolicy,
+    pub concurrency: usize,
+}
+
+/// Lists the shortcuts whose targets are missing from the snapshot, because they have been
+/// deleted or are not owned by me.  With `resolve`, tells which of them still exist.
+pub async fn show_broken_shortcuts<A: DriveApi>(
+    store: &Store,
+    resolve: Option<Resolve<'_, A>>,
+) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut broken = vec![];
+    for file in &files {
+        let Some(ref details) = file.shortcut_details else {
+            continue;
+        };
+        if !index.id_to_file.contains_key(&details.target_id as &str) {
+            broken.push((full_path(&index, file)?, &details.target_id));
+        }
+    }
+    broken.sort();
+
+    let mut targets = Default::default();
+    if let Some(resolve) = resolve {
+        let pipeline = Pipeline {
+            cancel: resolve.cancel,
+            concurrency: resolve.concurrency,
+            checkpoint: store.dir.join("shortcut-targets-checkpoint.json"),
+        };
+        let ids = broken.iter().map(|&(_, id)| id.clone());
+        let (api, retry) = (resolve.api, resolve.retry);
+        let enriched = pipeline
+            .run("shortcut targets", ids, |id| async move {
+                fetch_target(api, retry, &id).await
+            })
+            .await?;
+        if enriched.complete {
+            pipeline.finish()?;
+        }
+        targets = enriched.results;
+    }
+
+    for (path, target_id) in &broken {
+        match targets.get(*target_id) {
+            Some(target) => outln!("{path} → {target_id} ({})", target.describe()),
+            None => outln!("{path} → {target_id}"),
+        }
+    }
+    outln!("{} broken shortcuts", broken.len());
+    output::emit(&json!(broken
+        .iter()
+        .map(|(path, target_id)| json!({
+            "path": path,
+            "target_id": target_id,
+            "target": targets.get(*target_id),
+        }))
+        .collect::<Vec<_>>()))
+}
@@ -0,0 +1,497 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::{space_of, File, FileIndex, FileList, Overview, PageStore, ShortcutDetails, User};
+
+pub(crate) const DB_NAME: &str = "file-list.sqlite3";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id TEXT PRIMARY KEY,
+    mime_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    quota_bytes_used INTEGER,
+    size INTEGER,
+    sha256_checksum TEXT,
+    drive_id TEXT,
+    shortcut_target_id TEXT,
+    owners TEXT,
+    shared INTEGER NOT NULL DEFAULT 0,
+    shared_with_me_time TEXT,
+    last_modifying_user TEXT,
+    modified_time TEXT,
+    created_time TEXT,
+    md5_checksum TEXT,
+    trashed INTEGER NOT NULL DEFAULT 0,
+    extra TEXT,
+    sha1_checksum TEXT,
+    spaces TEXT,
+    capabilities TEXT,
+    starred INTEGER NOT NULL DEFAULT 0,
+    label_info TEXT
+);
+CREATE TABLE IF NOT EXISTS parents (
+    file_id TEXT NOT NULL,
+    parent_id TEXT NOT NULL,
+    PRIMARY KEY (file_id, parent_id)
+);
+CREATE INDEX IF NOT EXISTS parents_parent_id ON parents (parent_id);
+CREATE TABLE IF NOT EXISTS crawl_state (
+    corpus TEXT PRIMARY KEY,
+    pages INTEGER NOT NULL,
+    next_page_token TEXT
+);
+";
+
+const FILE_COLUMNS: &str = "f.id, f.mime_type, \
+    (SELECT json_group_array(p.parent_id) FROM parents p WHERE p.file_id = f.id), \
+    f.name, f.quota_bytes_used, f.size, f.sha256_checksum, f.drive_id, f.shortcut_target_id, \
+    f.owners, f.shared, f.shared_with_me_time, f.last_modifying_user, f.modified_time, \
+    f.created_time, f.md5_checksum, f.trashed, f.extra, \
+    f.sha1_checksum, f.spaces, f.capabilities, f.starred, f.label_info";
+
+/// Columns added after the table was first created, with their types
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("shortcut_target_id", "TEXT"),
+    ("owners", "TEXT"),
+    ("shared", "INTEGER NOT NULL DEFAULT 0"),
+    ("shared_with_me_time", "TEXT"),
+    ("last_modifying_user", "TEXT"),
+    ("modified_time", "TEXT"),
+    ("created_time", "TEXT"),
+    ("md5_checksum", "TEXT"),
+    ("trashed", "INTEGER NOT NULL DEFAULT 0"),
+    ("extra", "TEXT"),
+    ("sha1_checksum", "TEXT"),
+    ("spaces", "TEXT"),
+    ("capabilities", "TEXT"),
+    ("starred", "INTEGER NOT NULL DEFAULT 0"),
+    ("label_info", "TEXT"),
+];
+
+pub fn open(dir: &Path) -> anyhow::Result<Connection> {
+    let path = dir.join(DB_NAME);
+    let conn = Connection::open(&path)?;
+    conn.execute_batch(SCHEMA)?;
+    // Databases created by older versions lack some of the columns
+    for (column, kind) in ADDED_COLUMNS {
+        let exists = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('files') WHERE name = ?1",
+            [column],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !exists {
+            conn.execute(&format!("ALTER TABLE files ADD COLUMN {column} {kind}"), [])?;
+        }
+    }
+    info!("Opened {path:?}");
+    Ok(conn)
+}
+
+fn conversion_error(
+    column: usize,
+    error: impl std::error::Error + Send + Sync + 'static,
+) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, error.into())
+}
+
+fn time_from_row(row: &Row, column: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let Some(time) = row.get::<_, Option<String>>(column)? else {
+        return Ok(None);
+    };
+    let time = DateTime::parse_from_rfc3339(&time).map_err(|e| conversion_error(column, e))?;
+    Ok(Some(time.with_timezone(&Utc)))
+}
+
+fn file_from_row(row: &Row) -> rusqlite::Result<File> {
+    let parents: String = row.get(2)?;
+    let owners: Option<String> = row.get(9)?;
+    let extra: Option<String> = row.get(17)?;
+    let spaces: Option<String> = row.get(19)?;
+    let capabilities: Option<String> = row.get(20)?;
+    let label_info: Option<String> = row.get(22)?;
+    Ok(File {
+        id: row.get(0)?,
+        mime_type: row.get(1)?,
+        parents: serde_json::from_str(&parents).map_err(|e| conversion_error(2, e))?,
+        name: row.get(3)?,
+        quota_bytes_used: row.get::<_, Option<i64>>(4)?.map(|x| x as u64),
+        size: row.get::<_, Option<i64>>(5)?.map(|x| x as u64),
+        sha256_checksum: row.get(6)?,
+        drive_id: row.get(7)?,
+        shortcut_details: row
+            .get::<_, Option<String>>(8)?
+            .map(|target_id| ShortcutDetails { target_id }),
+        owners: match owners {
+            Some(owners) => serde_json::from_str(&owners).map_err(|e| conversion_error(9, e))?,
+            None => vec![],
+        },
+        shared: row.get(10)?,
+        shared_with_me_time: time_from_row(row, 11)?,
+        last_modifying_user: row.get::<_, Option<String>>(12)?.map(|email_address| User {
+            email_address: Some(email_address),
+        }),
+        modified_time: time_from_row(row, 13)?,
+        created_time: time_from_row(row, 14)?,
+        md5_checksum: row.get(15)?,
+        sha1_checksum: row.get(18)?,
+        trashed: row.get(16)?,
+        extra: match extra {
+            Some(extra) => serde_json::from_str(&extra).map_err(|e| conversion_error(17, e))?,
+            None => Default::default(),
+        },
+        sharing_user: None,
+        spaces: match spaces {
+            Some(spaces) => serde_json::from_str(&spaces).map_err(|e| conversion_error(19, e))?,
+            None => vec![],
+        },
+        capabilities: capabilities
+            .map(|c| serde_json::from_str(&c).map_err(|e| conversion_error(20, e)))
+            .transpose()?,
+        starred: row.get(21)?,
+        label_info: label_info
+            .map(|l| serde_json::from_str(&l).map_err(|e| conversion_error(22, e)))
+            .transpose()?,
+    })
+}
+
+fn query_files(
+    conn: &Connection,
+    condition: &str,
+    params: impl rusqlite::Params,
+) -> anyhow::Result<Vec<File>> {
+    let mut stmt = conn.prepare(&format!("SELECT {FILE_COLUMNS} FROM files f {condition}"))?;
+    let files = stmt
+        .query_map(params, file_from_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(files)
+}
+
+pub fn load_files(conn: &Connection) -> anyhow::Result<Vec<File>> {
+    query_files(conn, "", [])
+}
+
+/// The files with the ids, skipping those not stored
+pub fn load_files_by_id<'a>(
+    conn: &Connection,
+    ids: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<Vec<File>> {
+    let mut files = vec![];
+    for id in ids {
+        files.extend(query_files(conn, "WHERE f.id = ?1", [id])?);
+    }
+    Ok(files)
+}
+
+pub fn upsert_files<'a>(
+    conn: &Connection,
+    files: impl IntoIterator<Item = &'a File>,
+) -> anyhow::Result<()> {
+    let mut insert_file = conn.prepare_cached(
+        "INSERT OR REPLACE INTO files \
+        (id, mime_type, name, quota_bytes_used, size, sha256_checksum, drive_id, \
+        shortcut_target_id, owners, shared, shared_with_me_time, last_modifying_user, \
+        modified_time, created_time, md5_checksum, trashed, extra, sha1_checksum, spaces, \
+        capabilities, starred, label_info) \
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, \
+        ?19, ?20, ?21, ?22)",
+    )?;
+    let mut delete_parents = conn.prepare_cached("DELETE FROM parents WHERE file_id = ?1")?;
+    let mut insert_parent =
+        conn.prepare_cached("INSERT OR IGNORE INTO parents (file_id, parent_id) VALUES (?1, ?2)")?;
+    for file in files {
+        insert_file.execute(params![
+            file.id,
+            file.mime_type,
+            file.name,
+            file.quota_bytes_used.map(|x| x as i64),
+            file.size.map(|x| x as i64),
+            file.sha256_checksum,
+            file.drive_id,
+            file.shortcut_details.as_ref().map(|s| &s.target_id),
+            serde_json::to_string(&file.owners)?,
+            file.shared,
+            file.shared_with_me_time.map(|t| t.to_rfc3339()),
+            file.last_modifying_user
+                .as_ref()
+                .and_then(|u| u.email_address.as_ref()),
+            file.modified_time.map(|t| t.to_rfc3339()),
+            file.created_time.map(|t| t.to_rfc3339()),
+            file.md5_checksum,
+            file.trashed,
+            (!file.extra.is_empty())
+                .then(|| serde_json::to_string(&file.extra))
+                .transpose()?,
+            file.sha1_checksum,
+            (!file.spaces.is_empty())
+                .then(|| serde_json::to_string(&file.spaces))
+                .transpose()?,
+            file.capabilities
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            file.starred,
+            file.label_info
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        ])?;
+        delete_parents.execute([&file.id])?;
+        for parent in &file.parents {
+            insert_parent.execute([&file.id, parent])?;
+        }
+    }
+    Ok(())
+}
+
+pub fn delete_files<'a>(
+    conn: &Connection,
+    ids: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<usize> {
+    let mut delete_file = conn.prepare_cached("DELETE FROM files WHERE id = ?1")?;
+    let mut delete_parents = conn.prepare_cached("DELETE FROM parents WHERE file_id = ?1")?;
+    let mut count = 0;
+    for id in ids {
+        count += delete_file.execute([id])?;
+        delete_parents.execute([id])?;
+    }
+    Ok(count)
+}
+
+/// Crawl progress of one corpus, stored alongside the files
+pub struct SqlitePages<'a> {
+    conn: &'a mut Connection,
+    corpus: String,
+    pages: usize,
+    next_page_token: Option<String>,
+}
+impl<'a> SqlitePages<'a> {
+    pub fn open(conn: &'a mut Connection, corpus: &str) -> anyhow::Result<Self> {
+        let state = conn
+            .query_row(
+                "SELECT pages, next_page_token FROM crawl_state WHERE corpus = ?1",
+                [corpus],
+                |row| Ok((row.get::<_, i64>(0)? as usize, row.get(1)?)),
+            )
+            .optional()?;
+        let (pages, next_page_token) = match state {
+            Some(state) => {
+                info!("Resuming {corpus:?} from page {}", state.0);
+                state
+            }
+            None => {
+                info!("Starting {corpus:?} from scratch");
+                (0, Some(String::new()))
+            }
+        };
+        Ok(Self {
+            conn,
+            corpus: corpus.to_owned(),
+            pages,
+            next_page_token,
+        })
+    }
+}
+impl PageStore for SqlitePages<'_> {
+    fn next_page_token(&self) -> Option<&str> {
+        self.next_page_token.as_deref()
+    }
+    fn page_count(&self) -> usize {
+        self.pages
+    }
+    fn crawled_bytes(&self) -> anyhow::Result<u64> {
+        let drive_id = self.corpus.strip_prefix("drive:");
+        let bytes: i64 = self.conn.query_row(
+            "SELECT COALESCE(SUM(quota_bytes_used), 0) FROM files WHERE drive_id IS ?1",
+            [drive_id],
+            |row| row.get(0),
+        )?;
+        Ok(bytes as u64)
+    }
+    fn push(&mut self, page: FileList) -> anyhow::Result<()> {
+        let tx = self.conn.transaction()?;
+        upsert_files(&tx, &page.files)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO crawl_state (corpus, pages, next_page_token) \
+            VALUES (?1, ?2, ?3)",
+            params![self.corpus, (self.pages + 1) as i64, page.next_page_token],
+        )?;
+        tx.commit()?;
+        self.pages += 1;
+        self.next_page_token = page.next_page_token;
+        Ok(())
+    }
+    fn save(&mut self) -> anyhow::Result<()> {
+        // Every page is committed as soon as it is pushed
+        Ok(())
+    }
+}
+
+/// Replaces the files in My Drive with the result of a crawl done at once.
+pub fn replace_user_files(conn: &mut Connection, files: &[File]) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM parents WHERE file_id IN (SELECT id FROM files WHERE drive_id IS NULL)",
+        [],
+    )?;
+    tx.execute("DELETE FROM files WHERE drive_id IS NULL", [])?;
+    upsert_files(&tx, files)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO crawl_state (corpus, pages, next_page_token) \
+        VALUES ('user', 1, NULL)",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn is_complete(conn: &Connection, corpus: &str) -> anyhow::Result<bool> {
+    let state: Option<Option<String>> = conn
+        .query_row(
+            "SELECT next_page_token FROM crawl_state WHERE corpus = ?1",
+            [corpus],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(matches!(state, Some(None)))
+}
+
+pub fn overview(conn: &Connection, include_trashed: bool) -> anyhow::Result<Overview> {
+    let included = if include_trashed {
+        "1"
+    } else {
+        "NOT f.trashed"
+    };
+    let total_quota_bytes = conn.query_row(
+        &format!("SELECT COALESCE(SUM(quota_bytes_used), 0) FROM files f WHERE {included}"),
+        [],
+        |row| row.get::<_, i64>(0),
+    )? as u64;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT drive_id, COALESCE(SUM(quota_bytes_used), 0) FROM files f WHERE {included} \
+        GROUP BY drive_id"
+    ))?;
+    let quota_bytes_per_drive: HashMap<_, _> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT json_extract(owners, '$[0].emailAddress'), COALESCE(SUM(quota_bytes_used), 0) \
+        FROM files f WHERE {included} GROUP BY 1"
+    ))?;
+    let quota_bytes_per_owner: HashMap<_, _> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT spaces, COALESCE(SUM(quota_bytes_used), 0) FROM files f WHERE {included} \
+        GROUP BY spaces"
+    ))?;
+    let mut quota_bytes_per_space = HashMap::<_, u64>::new();
+    for row in stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, i64>(1)? as u64,
+        ))
+    })? {
+        let (spaces, bytes) = row?;
+        let spaces: Vec<String> = match spaces {
+            Some(spaces) => serde_json::from_str(&spaces)?,
+            None => vec![],
+        };
+        *quota_bytes_per_space
+            .entry(space_of(&spaces).to_owned())
+            .or_default() += bytes;
+    }
+    let (shortcuts, broken_shortcuts) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(shortcut_target_id NOT IN (SELECT id FROM files)), 0) \
+            FROM files f WHERE shortcut_target_id IS NOT NULL AND {included}"
+        ),
+        [],
+        |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+    )?;
+    let shortcut_target_bytes = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(quota_bytes_used), 0) FROM files WHERE id IN ( \
+                SELECT shortcut_target_id FROM files f \
+                WHERE shortcut_target_id IS NOT NULL AND {included} \
+            )"
+        ),
+        [],
+        |row| row.get::<_, i64>(0),
+    )? as u64;
+    let without_single_parent = query_files(
+        conn,
+        &format!(
+            "WHERE (SELECT COUNT(*) FROM parents p WHERE p.file_id = f.id) != 1 AND {included}"
+        ),
+        [],
+    )?;
+    let with_foreign_parents = query_files(
+        conn,
+        &format!(
+            "WHERE f.quota_bytes_used > 1024 AND {included} AND EXISTS (
+            SELECT 1 FROM parents p
+            WHERE p.file_id = f.id
+                AND p.parent_id NOT IN (SELECT id FROM files)
+                AND p.parent_id IS NOT f.drive_id
+        )"
+        ),
+        [],
+    )?;
+    Ok(Overview {
+        total_quota_bytes,
+        quota_bytes_per_drive,
+        quota_bytes_per_owner,
+        quota_bytes_per_space,
+        shortcuts,
+        broken_shortcuts,
+        shortcut_target_bytes,
+        without_single_parent,
+        with_foreign_parents,
+    })
+}
+
+pub struct SqliteIndex<'a>(pub &'a Connection);
+impl FileIndex for SqliteIndex<'_> {
+    fn get(&self, id: &str) -> anyhow::Result<Option<File>> {
+        Ok(query_files(self.0, "WHERE f.id = ?1", [id])?.pop())
+    }
+    fn children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        // The parents of a file are listed in the order of the primary key, so the first one is
+        // the smallest
+        query_files(
+            self.0,
+            "JOIN parents c ON c.file_id = f.id WHERE c.parent_id = ?1 \
+            AND c.parent_id = (SELECT MIN(q.parent_id) FROM parents q WHERE q.file_id = f.id)",
+            [id],
+        )
+    }
+    fn all_children(&self, id: &str) -> anyhow::Result<Vec<File>> {
+        query_files(
+            self.0,
+            "JOIN parents c ON c.file_id = f.id WHERE c.parent_id = ?1",
+            [id],
+        )
+    }
+    fn with_multiple_parents(&self) -> anyhow::Result<Vec<File>> {
+        query_files(
+            self.0,
+            "WHERE (SELECT COUNT(*) FROM parents p WHERE p.file_id = f.id) > 1 ORDER BY f.id",
+            [],
+        )
+    }
+    fn roots(&self) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self.0.prepare(
+            "SELECT DISTINCT p.parent_id FROM parents p
+            WHERE NOT EXISTS (SELECT 1 FROM parents q WHERE q.file_id = p.parent_id)
+            ORDER BY p.parent_id",
+        )?;
+        let roots = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(roots)
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use crate::{format_size, full_path, output, restore_files, MemoryIndex, Store};
+
+/// Lists the files of at least `min_size` that have not been modified for `years`, grouped by
+/// folder with the largest total first.
+pub fn show_stale(store: &Store, years: u32, min_size: u64) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let threshold = Utc::now() - Duration::days(365 * years as i64);
+
+    let mut folders = HashMap::<String, (u64, Vec<_>)>::new();
+    for file in &files {
+        if file.mime_type == "application/vnd.google-apps.folder" {
+            continue;
+        }
+        let size = file.quota_bytes_used.unwrap_or(0);
+        let Some(modified_time) = file.modified_time else {
+            continue;
+        };
+        if size < min_size || modified_time >= threshold {
+            continue;
+        }
+        let path = full_path(&index, file)?;
+        let (folder, name) = path.rsplit_once('/').unwrap_or(("", path.as_str()));
+        let folder = if folder.is_empty() { "/" } else { folder };
+        let entry = folders.entry(folder.to_owned()).or_default();
+        entry.0 += size;
+        entry
+            .1
+            .push((size, modified_time, name.to_owned(), file.can_trash()));
+    }
+
+    let mut folders: Vec<_> = folders.into_iter().collect();
+    folders.sort_by_key(|(_, (total, _))| std::cmp::Reverse(*total));
+    let (mut count, mut total) = (0, 0);
+    let mut results = vec![];
+    for (folder, (size, mut files)) in folders {
+        outln!("=== {folder} ({}) ===", format_size(size));
+        files.sort_by_key(|&(size, _, _, _)| std::cmp::Reverse(size));
+        for (size, modified_time, name, can_trash) in &files {
+            outln!(
+                "{:>12}  {}  {name}{}",
+                format_size(*size),
+                modified_time.format("%Y-%m-%d"),
+                if *can_trash { "" } else { "  (cannot trash)" }
+            );
+        }
+        count += files.len();
+        total += size;
+        let files: Vec<_> = files
+            .iter()
+            .map(|(size, modified_time, name, can_trash)| {
+                json!({
+                    "name": name,
+                    "size": size,
+                    "modified_time": modified_time,
+                    "can_trash": can_trash,
+                })
+            })
+            .collect();
+        results.push(json!({ "folder": folder, "size": size, "files": files }));
+    }
+    outln!(
+        "{count} files untouched for {years} years, {} in total",
+        format_size(total)
+    );
+    output::emit(&results)
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{write_atomically, Store};
+
+/// Outcome of the last run, written to `status.json` in the data directory in non-interactive
+/// mode for schedulers and monitoring to pick up
+#[derive(Serialize)]
+pub struct Status {
+    pub command: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub exit_code: i32,
+    /// The error the command failed with, if any
+    pub error: Option<String>,
+    pub api_calls: u64,
+    /// API calls failed after all retries, including those the command recovered from
+    pub api_errors: u64,
+}
+impl Status {
+    pub fn save(&self, store: &Store) -> anyhow::Result<()> {
+        write_atomically(&store.dir.join("status.json"), |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use age::secrecy::{Secret, SecretString};
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use google_drive3::oauth2::storage::{TokenInfo, TokenStorage};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Service name of the keyring entries, each of which is named after the token cache path
+const KEYRING_SERVICE: &str = "drive-crawl";
+
+/// Environment variable holding the passphrase of the encrypted token cache
+const PASSPHRASE_VAR: &str = "DRIVE_CRAWL_PASSPHRASE";
+
+/// Where the OAuth tokens of `--auth installed` are kept
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TokenStoreKind {
+    /// In plain JSON at the token cache path
+    Plaintext,
+    /// In the keyring of the OS
+    Keyring,
+    /// At the token cache path with `.age` appended, encrypted with a passphrase taken from
+    /// `DRIVE_CRAWL_PASSPHRASE` or asked for
+    Encrypted,
+}
+
+/// A token in the same shape as in the plaintext cache, so that the cache migrates as is
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredToken {
+    scopes: Vec<String>,
+    token: TokenInfo,
+}
+
+enum Backend {
+    Keyring(keyring::Entry),
+    Encrypted {
+        path: PathBuf,
+        passphrase: SecretString,
+    },
+}
+impl Backend {
+    fn name(&self) -> String {
+        match self {
+            Backend::Keyring(_) => "the keyring".into(),
+            Backend::Encrypted { path, .. } => format!("{path:?}"),
+        }
+    }
+
+    fn load(&self) -> anyhow::Result<Vec<StoredToken>> {
+        let json = match self {
+            Backend::Keyring(entry) => match entry.get_password() {
+                Ok(json) => json,
+                Err(keyring::Error::NoEntry) => return Ok(vec![]),
+                Err(e) => Err(e)?,
+            },
+            Backend::Encrypted { path, passphrase } => {
+                let encrypted = match fs_err::read(path) {
+                    Ok(encrypted) => encrypted,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+                    Err(e) => Err(e)?,
+                };
+                let age::Decryptor::Passphrase(decryptor) = age::Decryptor::new(&encrypted[..])?
+                else {
+                    bail!("{path:?} is not encrypted with a passphrase");
+                };
+                let mut json = String::new();
+                decryptor
+                    .decrypt(passphrase, None)
+                    .with_context(|| format!("Failed to decrypt {path:?}"))?
+                    .read_to_string(&mut json)?;
+                json
+            }
+        };
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    fn save(&self, tokens: &[StoredToken]) -> anyhow::Result<()> {
+        let json = serde_json::to_string(tokens)?;
+        match self {
+            Backend::Keyring(entry) => entry.set_password(&json)?,
+            Backend::Encrypted { path, passphrase } => {
+                let mut encrypted = vec![];
+                let mut writer = age::Encryptor::with_user_passphrase(passphrase.clone())
+                    .wrap_output(&mut encrypted)?;
+                writer.write_all(json.as_bytes())?;
+                writer.finish()?;
+                fs_err::write(path, encrypted)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encrypted_path(token_cache: &Path) -> PathBuf {
+    let mut path = token_cache.as_os_str().to_owned();
+    path.push(".age");
+    path.into()
+}
+
+fn keyring_entry(token_cache: &Path) -> anyhow::Result<keyring::Entry> {
+    // Named after the path so that every profile has its own entry
+    let user = std::path::absolute(token_cache)?;
+    Ok(keyring::Entry::new(
+        KEYRING_SERVICE,
+        &user.to_string_lossy(),
+    )?)
+}
+
+/// Whether any kind of store has tokens for the token cache path
+pub fn has_tokens(token_cache: &Path) -> bool {
+    token_cache.exists()
+        || encrypted_path(token_cache).exists()
+        || keyring_entry(token_cache).is_ok_and(|entry| entry.get_password().is_ok())
+}
+
+/// Tokens kept in the keyring or in an encrypted file instead of in plain JSON
+pub struct SecureStorage {
+    backend: Backend,
+    tokens: Mutex<Vec<StoredToken>>,
+}
+impl SecureStorage {
+    /// Opens the store, moving the tokens of the plaintext cache into it if it is still empty.
+    pub fn open(
+        kind: TokenStoreKind,
+        token_cache: &Path,
+        non_interactive: bool,
+    ) -> anyhow::Result<Self> {
+        let backend = match kind {
+            TokenStoreKind::Plaintext => bail!("Plaintext tokens are kept by the authenticator"),
+            TokenStoreKind::Keyring => Backend::Keyring(keyring_entry(token_cache)?),
+            TokenStoreKind::Encrypted => {
+                let passphrase = match std::env::var(PASSPHRASE_VAR) {
+                    Ok(passphrase) => passphrase,
+                    Err(_) if non_interactive => {
+                        bail!("Set {PASSPHRASE_VAR} to decrypt the tokens in non-interactive mode")
+                    }
+                    Err(_) => rpassword::prompt_password("Passphrase of the tokens: ")?,
+                };
+                Backend::Encrypted {
+                    path: encrypted_path(token_cache),
+                    passphrase: Secret::new(passphrase),
+                }
+            }
+        };
+        let mut tokens = backend.load()?;
+        if token_cache.exists() {
+            if tokens.is_empty() {
+                tokens = serde_json::from_str(&fs_err::read_to_string(token_cache)?)
+                    .with_context(|| format!("Failed to parse {token_cache:?}"))?;
+                backend.save(&tokens)?;
+                fs_err::remove_file(token_cache)?;
+                info!(
+                    "Moved the tokens from {token_cache:?} into {}",
+                    backend.name()
+                );
+            } else {
+                warn!(
+                    "{token_cache:?} is left in plaintext although {} has tokens.  Delete it.",
+                    backend.name()
+                );
+            }
+        }
+        Ok(Self {
+            backend,
+            tokens: Mutex::new(tokens),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenStorage for SecureStorage {
+    async fn set(&self, scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        let mut scopes: Vec<_> = scopes.iter().map(|s| s.to_string()).collect();
+        scopes.sort();
+        let mut tokens = self.tokens.lock().expect("the lock is never poisoned");
+        tokens.retain(|t| t.scopes != scopes);
+        tokens.push(StoredToken { scopes, token });
+        self.backend.save(&tokens)
+    }
+
+    async fn get(&self, target_scopes: &[&str]) -> Option<TokenInfo> {
+        let tokens = self.tokens.lock().expect("the lock is never poisoned");
+        tokens
+            .iter()
+            .find(|t| {
+                target_scopes
+                    .iter()
+                    .all(|s| t.scopes.iter().any(|x| x == s))
+            })
+            .map(|t| t.token.clone())
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use google_drive3::api;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::AuditLog, bfs_children, fetch_subtree, retry::RetryPolicy, with_index,
+    CancellationToken, Drive, File, ListOptions, Store,
+};
+
+/// Number of files requested before the progress is reported
+const BATCH_SIZE: usize = 100;
+
+pub struct Options {
+    /// Email of the new owner
+    pub email: String,
+    /// Fetch the folder tree from the API instead of the snapshot
+    pub live: bool,
+    /// Number of requests made at the same time
+    pub concurrency: usize,
+    /// Email the new owner about every file
+    pub notify: bool,
+}
+
+/// Line of `ownership-transfers.jsonl`, recorded once the request has been made so that an
+/// interrupted run resumes from where it stopped
+#[derive(Serialize, Deserialize)]
+struct Requested {
+    id: String,
+    email: String,
+    time: DateTime<Utc>,
+}
+
+fn requested_path(store: &Store) -> PathBuf {
+    store.dir.join("ownership-transfers.jsonl")
+}
+
+/// Ids of the files already requested to be transferred to the email
+fn load_requested(store: &Store, email: &str) -> anyhow::Result<HashSet<String>> {
+    let file = match fs_err::File::open(requested_path(store)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => Err(e)?,
+    };
+    let mut ids = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let entry: Requested = serde_json::from_str(&line?)?;
+        if entry.email.eq_ignore_ascii_case(email) {
+            ids.insert(entry.id);
+        }
+    }
+    Ok(ids)
+}
+
+async fn my_email(drive: &Drive, retry: &RetryPolicy) -> anyhow::Result<String> {
+    let (_, about) = retry
+        .run(|| {
+            drive
+                .about()
+                .get()
+                .param("fields", "user(emailAddress)")
+                .doit()
+        })
+        .await?;
+    about
+        .user
+        .and_then(|u| u.email_address)
+        .context("The API did not return the email address of the user")
+}
+
+/// Asks the new owner to take over the file, which they have to accept.  They get write access
+/// right away.
+async fn request_transfer(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    file_id: &str,
+    email: &str,
+    notify: bool,
+) -> google_drive3::Result<()> {
+    let permission = api::Permission {
+        type_: Some("user".into()),
+        role: Some("writer".into()),
+        email_address: Some(email.to_owned()),
+        pending_owner: Some(true),
+        ..Default::default()
+    };
+    retry
+        .run(|| {
+            drive
+                .permissions()
+                .create(permission.clone(), file_id)
+                .send_notification_email(notify)
+                .param("fields", "id")
+                .doit()
+        })
+        .await?;
+    Ok(())
+}
+
+/// Requests the transfer of the ownership of every file I own under the folder, including the
+/// folder itself, to the new owner.  Files already requested in earlier runs are skipped.
+pub async fn transfer_ownership(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    folder_id: &str,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let email = &options.email as &str;
+    let me = my_email(drive, retry).await?;
+    if me.eq_ignore_ascii_case(email) {
+        bail!("The files are already owned by {email}");
+    }
+    let files: Vec<File> = if options.live {
+        fetch_subtree(drive, cancel, retry, folder_id, &ListOptions::default()).await?
+    } else {
+        with_index(store, |index| {
+            let Some(root) = index.get(folder_id)? else {
+                bail!("File with id {folder_id:?} was not found");
+            };
+            Ok(bfs_children(index, root)?.into())
+        })?
+    };
+    let requested = load_requested(store, email)?;
+    let owned: Vec<_> = files
+        .iter()
+        .filter(|f| {
+            f.owners
+                .iter()
+                .any(|o| o.email_address.as_deref() == Some(&me as &str))
+        })
+        .collect();
+    let targets: Vec<_> = owned
+        .iter()
+        .filter(|f| !requested.contains(&f.id))
+        .copied()
+        .collect();
+    info!(
+        "{} of {} files are owned by me, {} of which were requested before",
+        owned.len(),
+        files.len(),
+        owned.len() - targets.len()
+    );
+    if audit.dry_run() {
+        outln!(
+            "Would request {} files to be transferred to {email}",
+            targets.len()
+        );
+        return Ok(());
+    }
+
+    let mut writer = BufWriter::new(
+        fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(requested_path(store))?,
+    );
+    let (mut done, mut failures) = (0, vec![]);
+    for batch in targets.chunks(BATCH_SIZE) {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Run again to resume.");
+            break;
+        }
+        let results: Vec<_> = stream::iter(batch)
+            .map(|&file| async move {
+                let res = request_transfer(drive, retry, &file.id, email, options.notify).await;
+                (file, res)
+            })
+            .buffer_unordered(options.concurrency.max(1))
+            .collect()
+            .await;
+        for (file, res) in results {
+            if let Err(e) = res {
+                error!("Failed to request the transfer of {}: {e}", file.id);
+                failures.push(file);
+                continue;
+            }
+            let entry = Requested {
+                id: file.id.clone(),
+                email: email.to_owned(),
+                time: Utc::now(),
+            };
+            serde_json::to_writer(&mut writer, &entry)?;
+            writeln!(writer)?;
+            audit.record(
+                "request_ownership_transfer",
+                &file.id,
+                serde_json::json!({ "owner": me }),
+                serde_json::json!({ "pending_owner": email }),
+            )?;
+            done += 1;
+        }
+        writer.flush()?;
+        info!("Requested {done} of {} files", targets.len());
+    }
+
+    if !failures.is_empty() {
+        outln!("=== Failed ===");
+        for file in &failures {
+            outln!("{}  {}", file.id, file.name);
+        }
+    }
+    outln!(
+        "Requested {done} files to be transferred to {email}, failed {}.  \
+        They are transferred once {email} accepts the requests.",
+        failures.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, io::Write};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::{audit::AuditLog, format_size, output, retry::RetryPolicy, Drive};
+
+#[serde_as]
+#[derive(Deserialize)]
+struct TrashedFile {
+    id: String,
+    name: String,
+    #[serde(default)]
+    parents: Vec<String>,
+    #[serde(rename = "quotaBytesUsed")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    quota_bytes_used: Option<u64>,
+    #[serde(rename = "trashedTime")]
+    trashed_time: Option<DateTime<Utc>>,
+    /// `false` if trashed only because one of the ancestors was
+    #[serde(rename = "explicitlyTrashed", default)]
+    explicitly_trashed: bool,
+}
+#[derive(Deserialize)]
+struct TrashedFileList {
+    files: Vec<TrashedFile>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+/// Fetches every trashed file owned by me, including the contents of trashed folders.
+async fn fetch_trash(drive: &Drive, retry: &RetryPolicy) -> anyhow::Result<Vec<TrashedFile>> {
+    let mut files = vec![];
+    let mut token = None::<String>;
+    loop {
+        let (_, res) = retry
+            .run(|| {
+                let mut call = drive
+                    .files()
+                    .list()
+                    .corpora("user")
+                    .q("trashed = true and 'me' in owners")
+                    .param(
+                        "fields",
+                        "nextPageToken,\
+                        files(id,name,parents,quotaBytesUsed,trashedTime,explicitlyTrashed)",
+                    );
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await?;
+        let res: TrashedFileList = serde_json::from_str(&serde_json::to_string(&res)?)?;
+        files.extend(res.files);
+        info!("Found {} trashed files so far", files.len());
+        token = res.next_page_token;
+        if token.is_none() {
+            return Ok(files);
+        }
+    }
+}
+
+/// Explicitly trashed files along with the quota used by them and their trashed contents,
+/// largest first
+fn summarize(files: &[TrashedFile]) -> Vec<(&TrashedFile, u64)> {
+    let id_to_file: HashMap<_, _> = files.iter().map(|f| (&f.id as &str, f)).collect();
+    let mut size = HashMap::<&str, u64>::new();
+    for file in files {
+        let bytes = file.quota_bytes_used.unwrap_or(0);
+        let mut current = Some(file);
+        // Bounded in case of cyclic parents
+        for _ in 0..files.len() {
+            let Some(file) = current else { break };
+            *size.entry(&file.id).or_default() += bytes;
+            if file.explicitly_trashed {
+                break;
+            }
+            current = file
+                .parents
+                .first()
+                .and_then(|id| id_to_file.get(id as &str).copied());
+        }
+    }
+    let mut ret: Vec<_> = files
+        .iter()
+        .filter(|f| f.explicitly_trashed)
+        .map(|f| (f, size[&f.id as &str]))
+        .collect();
+    ret.sort_by_key(|&(_, bytes)| std::cmp::Reverse(bytes));
+    ret
+}
+
+pub async fn show_trash(drive: &Drive, retry: &RetryPolicy) -> anyhow::Result<()> {
+    let files = fetch_trash(drive, retry).await?;
+    let summary = summarize(&files);
+    let now = Utc::now();
+    for (file, bytes) in &summary {
+        let age = match file.trashed_time {
+            Some(time) => format!("{:>5} days", (now - time).num_days()),
+            None => format!("{:>10}", "?"),
+        };
+        outln!("{:>12}  {age}  {}", format_size(*bytes), file.name);
+    }
+    let total = summary.iter().map(|&(_, bytes)| bytes).sum();
+    outln!(
+        "{} items ({} files), {} in total",
+        summary.len(),
+        files.len(),
+        format_size(total)
+    );
+    output::emit(&json!(summary
+        .iter()
+        .map(|(file, bytes)| json!({
+            "id": file.id,
+            "name": file.name,
+            "bytes": bytes,
+            "trashed_time": file.trashed_time,
+        }))
+        .collect::<Vec<_>>()))
+}
+
+/// Permanently deletes all trashed files after asking for confirmation.
+pub async fn empty_trash(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+) -> anyhow::Result<()> {
+    let files = fetch_trash(drive, retry).await?;
+    let total: u64 = files.iter().filter_map(|f| f.quota_bytes_used).sum();
+    let message = format!("{} trashed files using {}", files.len(), format_size(total));
+    if audit.dry_run() {
+        outln!("Would permanently delete {message}");
+        return Ok(());
+    }
+    if files.is_empty() {
+        outln!("The trash is already empty");
+        return Ok(());
+    }
+    eprint!("Permanently delete {message}? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        outln!("Cancelled");
+        return Ok(());
+    }
+    retry.run(|| drive.files().empty_trash().doit()).await?;
+    for file in &files {
+        let old = json!({ "name": file.name, "parents": file.parents });
+        audit.record("delete", &file.id, old, ())?;
+    }
+    info!("Emptied the trash, reclaiming {}", format_size(total));
+    Ok(())
+}
@@ -0,0 +1,308 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    ensure_complete, format_size, full_path, output, root_name, root_names, with_index, File,
+    FileIndex, MarkFilter, Store, WithoutTrashed,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortKey {
+    /// Largest first
+    Size,
+    Name,
+    /// Most items first
+    Count,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TreeFormat {
+    /// Indented lines with the sizes and the percentages of the parent and of the total
+    Text,
+    /// A nested object per root, with the size, the number of descendants and the percentages
+    /// of each item
+    Json,
+    /// A Graphviz graph, to be rendered with `dot -Tsvg`
+    Dot,
+}
+
+/// Where the size of a file with multiple parents is counted
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MultiParent {
+    /// Under the first parent only
+    First,
+    /// Under every parent, divided evenly between them
+    Split,
+}
+
+pub struct Options {
+    /// Items using less than this are hidden
+    pub min_size: u64,
+    /// Depth below which nothing is shown, where the roots are at depth 0
+    pub max_depth: Option<usize>,
+    pub sort: SortKey,
+    /// Show the number of items in each folder
+    pub show_counts: bool,
+    /// Show the size of the target next to each shortcut, which is not added to the totals
+    pub shortcut_sizes: bool,
+    pub multi_parent: MultiParent,
+    pub include_trashed: bool,
+    /// Show a bar proportional to the share of the parent
+    pub bars: bool,
+    pub format: TreeFormat,
+    /// Count only the starred or labeled files, hiding the folders without any
+    pub marks: MarkFilter,
+    /// Show the tree of a partial snapshot, only warning that it is incomplete
+    pub allow_partial: bool,
+}
+
+/// Width of the bars at 100%
+const BAR_WIDTH: usize = 20;
+
+#[derive(Serialize)]
+struct Node {
+    name: String,
+    /// Including the descendants
+    size: u64,
+    /// Number of descendants
+    count: u64,
+    /// Percentage of the size of the parent, or of the total for the roots
+    percent_of_parent: f64,
+    /// Percentage of the total size of the roots
+    percent_of_total: f64,
+    children: Vec<Node>,
+    /// Whether the item or any of its descendants passes [`Options::marks`]
+    #[serde(skip)]
+    marked: bool,
+}
+
+/// Quota used by the file and its descendants
+fn subtree_size(index: &dyn FileIndex, file: &File) -> anyhow::Result<u64> {
+    let mut size = file.quota_bytes_used.unwrap_or(0);
+    for child in index.children(&file.id)? {
+        size += subtree_size(index, &child)?;
+    }
+    Ok(size)
+}
+
+/// Name of the item, with the target appended if it is a shortcut
+fn display_name(index: &dyn FileIndex, file: &File, options: &Options) -> anyhow::Result<String> {
+    let Some(ref details) = file.shortcut_details else {
+        return Ok(file.name.clone());
+    };
+    Ok(match index.get(&details.target_id)? {
+        None => format!("{} → (missing target)", file.name),
+        Some(target) if options.shortcut_sizes => format!(
+            "{} → {} [{}]",
+            file.name,
+            target.name,
+            format_size(subtree_size(index, &target)?)
+        ),
+        Some(target) => format!("{} → {}", file.name, target.name),
+    })
+}
+
+/// Builds the subtree, with the sizes and counts covering all descendants even if they are not
+/// shown.  `marked` tells whether the item itself passes [`Options::marks`], and its `size` is
+/// counted only then.
+fn build(
+    index: &dyn FileIndex,
+    id: &str,
+    name: String,
+    size: u64,
+    marked: bool,
+    depth: usize,
+    options: &Options,
+) -> anyhow::Result<Node> {
+    let size = if marked { size } else { 0 };
+    let files = match options.multi_parent {
+        MultiParent::First => index.children(id)?,
+        MultiParent::Split => index.all_children(id)?,
+    };
+    let mut children = vec![];
+    for child in files {
+        let bytes = child.quota_bytes_used.unwrap_or(0);
+        let mut node = build(
+            index,
+            &child.id,
+            display_name(index, &child, options)?,
+            bytes,
+            options.marks.matches(&child),
+            depth + 1,
+            options,
+        )?;
+        if let MultiParent::Split = options.multi_parent {
+            node.size /= child.parents.len().max(1) as u64;
+        }
+        children.push(node);
+    }
+    let size = size + children.iter().map(|c| c.size).sum::<u64>();
+    let count = children.iter().map(|c| 1 + c.count).sum();
+    let marked = marked || children.iter().any(|c| c.marked);
+    if options.max_depth.is_some_and(|max| depth >= max) {
+        children.clear();
+    }
+    children.retain(|c| c.size >= options.min_size && c.marked);
+    match options.sort {
+        SortKey::Size => children.sort_by_key(|c| std::cmp::Reverse(c.size)),
+        SortKey::Name => children.sort_by(|x, y| x.name.cmp(&y.name)),
+        SortKey::Count => children.sort_by_key(|c| std::cmp::Reverse(c.count)),
+    }
+    Ok(Node {
+        name,
+        size,
+        count,
+        percent_of_parent: 0.0,
+        percent_of_total: 0.0,
+        children,
+        marked,
+    })
+}
+
+fn percent(size: u64, of: u64) -> f64 {
+    if of == 0 {
+        0.0
+    } else {
+        size as f64 / of as f64 * 100.0
+    }
+}
+
+/// Fills in the percentages of the subtree, whose parent has `parent_size`.
+fn fill_percentages(node: &mut Node, parent_size: u64, total: u64) {
+    node.percent_of_parent = percent(node.size, parent_size);
+    node.percent_of_total = percent(node.size, total);
+    for child in &mut node.children {
+        fill_percentages(child, node.size, total);
+    }
+}
+
+fn print_line(node: &Node, branch: &str, options: &Options) {
+    let count = if options.show_counts && !node.children.is_empty() {
+        format!("  ({} items)", node.count)
+    } else {
+        String::new()
+    };
+    let bar = if options.bars {
+        let filled = (node.percent_of_parent / 100.0 * BAR_WIDTH as f64).round() as usize;
+        format!("{:<BAR_WIDTH$}  ", "█".repeat(filled.min(BAR_WIDTH)))
+    } else {
+        String::new()
+    };
+    outln!(
+        "{:>12}  {:>5.1}% {:>5.1}%  {bar}{branch}{}{count}",
+        format_size(node.size),
+        node.percent_of_parent,
+        node.percent_of_total,
+        node.name
+    );
+}
+
+fn print_children(node: &Node, prefix: &str, options: &Options) {
+    for (i, child) in node.children.iter().enumerate() {
+        let (branch, indent) = if i + 1 == node.children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        print_line(child, &format!("{prefix}{branch}"), options);
+        print_children(child, &format!("{prefix}{indent}"), options);
+    }
+}
+
+/// Escapes a string quoted in DOT.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes the nodes of the subtree and the edges from their parents, numbering them from `next`.
+fn write_dot(out: &mut String, node: &Node, next: &mut usize, options: &Options) -> usize {
+    let id = *next;
+    *next += 1;
+    let mut label = format!(
+        "{}\\n{} ({:.1}%)",
+        escape(&node.name),
+        format_size(node.size),
+        node.percent_of_total
+    );
+    if options.show_counts && !node.children.is_empty() {
+        label += &format!(" ({} items)", node.count);
+    }
+    let _ = writeln!(out, "  n{id} [label=\"{label}\"];");
+    for child in &node.children {
+        let child_id = write_dot(out, child, next, options);
+        let _ = writeln!(out, "  n{id} -> n{child_id};");
+    }
+    id
+}
+
+pub fn show_tree(store: &Store, options: &Options) -> anyhow::Result<()> {
+    ensure_complete(store, options.allow_partial)?;
+    let root_names = root_names(store)?;
+    with_index(store, |index| {
+        let without_trashed;
+        let index = if options.include_trashed {
+            index
+        } else {
+            without_trashed = WithoutTrashed(index);
+            &without_trashed as &dyn FileIndex
+        };
+        let mut roots = vec![];
+        for id in index.roots()? {
+            let (name, size, marked) = match index.get(&id)? {
+                Some(file) => {
+                    let marked = options.marks.matches(&file);
+                    (file.name, file.quota_bytes_used.unwrap_or(0), marked)
+                }
+                None => (root_name(&root_names, &id), 0, options.marks.is_empty()),
+            };
+            roots.push(build(index, &id, name, size, marked, 0, options)?);
+        }
+        // Including the roots hidden by `min_size`
+        let total = roots.iter().map(|root| root.size).sum();
+        roots.retain(|root| root.size >= options.min_size && root.marked);
+        for root in &mut roots {
+            fill_percentages(root, total, total);
+        }
+        match options.format {
+            TreeFormat::Text => {
+                for root in &roots {
+                    print_line(root, "", options);
+                    print_children(root, "", options);
+                }
+                output::emit(&roots)?;
+            }
+            TreeFormat::Json => {
+                // Printed as is unless the same JSON is going to be emitted to stdout
+                if !output::is_json() {
+                    outln!("{}", serde_json::to_string_pretty(&roots)?);
+                }
+                return output::emit(&roots);
+            }
+            TreeFormat::Dot => {
+                let mut out = "digraph tree {\n  rankdir=LR;\n  node [shape=box];\n".to_owned();
+                let mut next = 0;
+                for root in &roots {
+                    write_dot(&mut out, root, &mut next, options);
+                }
+                out += "}";
+                outln!("{out}");
+                return output::emit(&roots);
+            }
+        }
+        let multi_parent = index.with_multiple_parents()?;
+        if !multi_parent.is_empty() {
+            outln!("=== Files with multiple parents ===");
+            for file in &multi_parent {
+                outln!(
+                    "{:>12}  {}  (in {} folders)",
+                    format_size(file.quota_bytes_used.unwrap_or(0)),
+                    full_path(index, file)?,
+                    file.parents.len()
+                );
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use log::info;
+use serde_json::json;
+
+use crate::{
+    collect_files, format_size, full_path, history, output, restore_data_from, File, MemoryIndex,
+    Store,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TrendFormat {
+    /// One line per folder with a sparkline, fastest growing first
+    Sparkline,
+    /// One row per snapshot and one column per folder
+    Csv,
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Quota used in total and by each top-level folder, as of one snapshot
+struct Point {
+    time: NaiveDateTime,
+    total: u64,
+    folders: HashMap<String, u64>,
+}
+
+/// Quota used in total and by each top-level folder, where the files right under the roots are
+/// counted together as `/`
+pub(crate) fn top_folder_usage(files: &[File]) -> anyhow::Result<(u64, HashMap<String, u64>)> {
+    let index = MemoryIndex::new(files)?;
+    let (mut total, mut folders) = (0, HashMap::<String, u64>::new());
+    for file in files {
+        let bytes = file.quota_bytes_used.unwrap_or(0);
+        total += bytes;
+        let path = full_path(&index, file)?;
+        let folder = match path.trim_start_matches('/').split_once('/') {
+            Some((folder, _)) => folder,
+            None => "/",
+        };
+        *folders.entry(folder.to_owned()).or_default() += bytes;
+    }
+    Ok((total, folders))
+}
+
+fn load_point(time: NaiveDateTime, path: &Path) -> anyhow::Result<Point> {
+    let files = collect_files(restore_data_from(path, false)?)?;
+    let (total, folders) = top_folder_usage(&files)?;
+    Ok(Point {
+        time,
+        total,
+        folders,
+    })
+}
+
+fn sparkline(values: &[u64]) -> String {
+    let (min, max) = (
+        values.iter().copied().min().unwrap_or(0),
+        values.iter().copied().max().unwrap_or(0),
+    );
+    values
+        .iter()
+        .map(|&v| match max - min {
+            0 => SPARKS[0],
+            range => SPARKS[((v - min) * (SPARKS.len() as u64 - 1) / range) as usize],
+        })
+        .collect()
+}
+
+/// Shows how the usage of the top-level folders changed across the archived snapshots, limited
+/// to the `top` folders that grew the most.
+pub fn show_trend(store: &Store, format: TrendFormat, top: usize) -> anyhow::Result<()> {
+    let snapshots = history::list(store)?;
+    if snapshots.is_empty() {
+        outln!("No snapshots archived yet.  They are taken on every complete crawl and sync.");
+        return Ok(());
+    }
+    let mut points = vec![];
+    for (time, path) in &snapshots {
+        info!("Loading {path:?}");
+        points.push(load_point(*time, path)?);
+    }
+
+    let series = |folder: &str| -> Vec<u64> {
+        points
+            .iter()
+            .map(|p| p.folders.get(folder).copied().unwrap_or(0))
+            .collect()
+    };
+    let mut folders: Vec<_> = points
+        .iter()
+        .flat_map(|p| p.folders.keys())
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|folder| {
+            let values = series(&folder);
+            let growth = *values.last().unwrap() as i64 - values[0] as i64;
+            (growth, folder, values)
+        })
+        .collect();
+    folders.sort_by(|(x, a, _), (y, b, _)| y.cmp(x).then_with(|| a.cmp(b)));
+    folders.truncate(top);
+
+    match format {
+        TrendFormat::Sparkline => {
+            let totals: Vec<_> = points.iter().map(|p| p.total).collect();
+            let first = points[0].time.format("%Y-%m-%d");
+            let last = points[points.len() - 1].time.format("%Y-%m-%d");
+            outln!("{} snapshots from {first} to {last}", points.len());
+            let print = |name: &str, growth: i64, values: &[u64]| {
+                let sign = if growth < 0 { "-" } else { "+" };
+                outln!(
+                    "{}  {:>12}  {sign}{:>11}  {name}",
+                    sparkline(values),
+                    format_size(*values.last().unwrap()),
+                    format_size(growth.unsigned_abs())
+                );
+            };
+            print(
+                "(total)",
+                totals[totals.len() - 1] as i64 - totals[0] as i64,
+                &totals,
+            );
+            for (growth, folder, values) in &folders {
+                print(folder, *growth, values);
+            }
+            output::emit(&json!({
+                "times": points.iter().map(|p| p.time).collect::<Vec<_>>(),
+                "total": totals,
+                "folders": folders
+                    .iter()
+                    .map(|(growth, folder, values)| json!({
+                        "folder": folder,
+                        "growth": growth,
+                        "values": values,
+                    }))
+                    .collect::<Vec<_>>(),
+            }))?;
+        }
+        TrendFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout().lock());
+            let mut header = vec!["time".to_owned(), "total".to_owned()];
+            header.extend(folders.iter().map(|(_, folder, _)| folder.clone()));
+            writer.write_record(&header)?;
+            for (i, point) in points.iter().enumerate() {
+                let mut record = vec![
+                    point.time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                    point.total.to_string(),
+                ];
+                record.extend(folders.iter().map(|(_, _, values)| values[i].to_string()));
+                writer.write_record(&record)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::{format_size, full_path, output, restore_files, MemoryIndex, Store};
+
+/// Extension of the name in lowercase, or `(none)`
+pub(crate) fn extension(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() && !ext.contains(' ') => {
+            ext.to_lowercase()
+        }
+        _ => "(none)".into(),
+    }
+}
+
+/// Prints the number of files and the quota used per mime type, or per extension if
+/// `by_extension` is set, largest first.  Folders are not counted.
+pub fn show_by_type(store: &Store, by_extension: bool) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let mut groups = HashMap::<String, (u64, u64)>::new();
+    for file in &files {
+        if file.mime_type == "application/vnd.google-apps.folder" {
+            continue;
+        }
+        let key = if by_extension {
+            extension(&file.name)
+        } else {
+            file.mime_type.clone()
+        };
+        let entry = groups.entry(key).or_default();
+        entry.0 += 1;
+        entry.1 += file.quota_bytes_used.unwrap_or(0);
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by(|(x, (_, a)), (y, (_, b))| b.cmp(a).then_with(|| x.cmp(y)));
+    let total_count: u64 = groups.iter().map(|(_, (count, _))| count).sum();
+    let total_bytes: u64 = groups.iter().map(|(_, (_, bytes))| bytes).sum();
+    let percent = |bytes: u64| match total_bytes {
+        0 => 0.0,
+        total => bytes as f64 * 100.0 / total as f64,
+    };
+    outln!("{:>12}  {:>6}  {:>9}  type", "size", "share", "files");
+    for (key, (count, bytes)) in &groups {
+        outln!(
+            "{:>12}  {:>5.1}%  {count:>9}  {key}",
+            format_size(*bytes),
+            percent(*bytes)
+        );
+    }
+    outln!(
+        "{:>12}  {:>5.1}%  {total_count:>9}  (total)",
+        format_size(total_bytes),
+        percent(total_bytes)
+    );
+    output::emit(&json!(groups
+        .iter()
+        .map(|(key, (count, bytes))| json!({ "type": key, "files": count, "bytes": bytes }))
+        .collect::<Vec<_>>()))
+}
+
+/// Whether the mime type Drive detected agrees with one guessed from the extension.  Only the
+/// top-level types like `image` are compared, since the subtypes often differ in spelling, except
+/// for `application` which covers unrelated formats.  Unknown extensions agree with anything.
+fn agrees(extension: &str, mime_type: &str) -> bool {
+    let top_level = |mime: &str| mime.split_once('/').map_or(mime, |(top, _)| top).to_owned();
+    let guesses = mime_guess::from_ext(extension);
+    guesses.is_empty()
+        || guesses.iter().any(|guess| {
+            let guess = guess.essence_str();
+            guess == mime_type
+                || top_level(guess) != "application" && top_level(guess) == top_level(mime_type)
+        })
+}
+
+/// Lists the files whose extension does not agree with the mime type Drive detected, such as a
+/// `.jpg` that is actually a video, largest first.  Google Docs and the files Drive could not
+/// identify are skipped.
+pub fn show_mismatched_types(store: &Store) -> anyhow::Result<()> {
+    let files = restore_files(store)?;
+    let index = MemoryIndex::new(&files)?;
+    let mut found = vec![];
+    for file in &files {
+        let ext = extension(&file.name);
+        if ext == "(none)"
+            || file.mime_type.starts_with("application/vnd.google-apps.")
+            || file.mime_type == "application/octet-stream"
+            || agrees(&ext, &file.mime_type)
+        {
+            continue;
+        }
+        let expected = mime_guess::from_ext(&ext).first_or_octet_stream();
+        found.push((
+            file.quota_bytes_used.unwrap_or(0),
+            full_path(&index, file)?,
+            expected.essence_str().to_owned(),
+            &file.mime_type,
+        ));
+    }
+    found.sort_by(|x, y| y.0.cmp(&x.0).then_with(|| x.1.cmp(&y.1)));
+    for (size, path, expected, actual) in &found {
+        outln!(
+            "{:>12}  {path}  (expected {expected}, detected {actual})",
+            format_size(*size)
+        );
+    }
+    outln!(
+        "{} files whose extension disagrees with the type, {} in total",
+        found.len(),
+        format_size(found.iter().map(|(size, ..)| size).sum())
+    );
+    output::emit(&json!(found
+        .iter()
+        .map(|(size, path, expected, actual)| json!({
+            "path": path,
+            "size": size,
+            "expected": expected,
+            "detected": actual,
+        }))
+        .collect::<Vec<_>>()))
+}
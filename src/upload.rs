@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use google_drive3::{api, Error};
+use log::{error, info, warn};
+use md5::Md5;
+use serde_json::json;
+
+use crate::{
+    audit::AuditLog, budget::ApiBudget, download::hash_file, drive_api::create_file,
+    retry::RetryPolicy, CancellationToken, Drive,
+};
+
+/// Files larger than this are sent in chunks in a resumable session, in which a dropped
+/// connection resends only the current chunk, rather than in a single request
+const RESUMABLE_THRESHOLD: u64 = 5 << 20;
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Reader of the content uploaded, held back to the bandwidth limit of the budget.  The
+/// generated API reads it synchronously, so waiting blocks the thread, though not the other tasks.
+struct Throttled<R> {
+    inner: R,
+    budget: &'static ApiBudget,
+}
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.budget.throttle_blocking(read as u64);
+        Ok(read)
+    }
+}
+impl<R: Seek> Seek for Throttled<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// File or folder already in a destination folder
+struct Remote {
+    id: String,
+    mime_type: String,
+    size: Option<u64>,
+    md5_checksum: Option<String>,
+}
+
+/// Lists the children of the folder by name.  Of those sharing a name, the first one is taken.
+async fn list_children(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    folder_id: &str,
+) -> anyhow::Result<HashMap<String, Remote>> {
+    let mut children = HashMap::new();
+    let mut token = None::<String>;
+    loop {
+        let (_, res) = retry
+            .run(|| {
+                let mut call = drive
+                    .files()
+                    .list()
+                    .q(&format!("'{folder_id}' in parents and trashed = false"))
+                    .supports_all_drives(true)
+                    .include_items_from_all_drives(true)
+                    .param(
+                        "fields",
+                        "nextPageToken,files(id,name,mimeType,size,md5Checksum)",
+                    );
+                if let Some(ref token) = token {
+                    call = call.page_token(token);
+                }
+                call.doit()
+            })
+            .await?;
+        for file in res.files.unwrap_or_default() {
+            let (Some(id), Some(name)) = (file.id, file.name) else {
+                continue;
+            };
+            if children.contains_key(&name) {
+                warn!("Several files are named {name:?} in {folder_id}; comparing with the first");
+                continue;
+            }
+            let remote = Remote {
+                id,
+                mime_type: file.mime_type.unwrap_or_default(),
+                size: file.size.map(|x| x as u64),
+                md5_checksum: file.md5_checksum,
+            };
+            children.insert(name, remote);
+        }
+        token = res.next_page_token;
+        if token.is_none() {
+            return Ok(children);
+        }
+    }
+}
+
+/// Uploads the file or the directory with all of its contents into the folder.  Files whose
+/// size and MD5 match those of a file with the same name are skipped, and those that differ get
+/// their content replaced as a new revision.  An upload interrupted by ctrl-c starts over on the
+/// next run.
+pub async fn upload(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    retry: &RetryPolicy,
+    audit: &mut AuditLog,
+    local_path: &Path,
+    folder_id: &str,
+) -> anyhow::Result<()> {
+    // Children of each destination folder, where folders created in dry-run mode have no id
+    let mut children = HashMap::<Option<String>, HashMap<String, Remote>>::new();
+    let mut stack = vec![(local_path.to_owned(), Some(folder_id.to_owned()))];
+    let (mut uploaded, mut skipped, mut failed) = (0, 0, 0);
+    while let Some((path, parent)) = stack.pop() {
+        if cancel.is_cancelled() {
+            info!("Received ctrl-c.  Terminating.");
+            break;
+        }
+        let name = path
+            .file_name()
+            .with_context(|| format!("{path:?} has no name"))?
+            .to_string_lossy()
+            .into_owned();
+        if !children.contains_key(&parent) {
+            let listed = match parent {
+                Some(ref id) => list_children(drive, retry, id).await?,
+                None => HashMap::new(),
+            };
+            children.insert(parent.clone(), listed);
+        }
+        let remote = children[&parent].get(&name);
+
+        if path.is_dir() {
+            let id = match remote {
+                Some(remote) if remote.mime_type == FOLDER_MIME_TYPE => Some(remote.id.clone()),
+                _ if audit.dry_run() => {
+                    outln!("Would create the folder {path:?}");
+                    None
+                }
+                _ => {
+                    let id =
+                        create_folder(drive, retry, &name, parent.as_deref().unwrap_or_default())
+                            .await?;
+                    audit.record(
+                        "create_folder",
+                        &id,
+                        (),
+                        json!({ "name": name, "parents": [parent] }),
+                    )?;
+                    info!("Created the folder {path:?}");
+                    children.insert(Some(id.clone()), HashMap::new());
+                    Some(id)
+                }
+            };
+            let mut entries = fs_err::read_dir(&path)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<anyhow::Result<Vec<PathBuf>>>()?;
+            // Popped in the order of the names
+            entries.sort_by(|x, y| y.cmp(x));
+            stack.extend(entries.into_iter().map(|entry| (entry, id.clone())));
+            continue;
+        }
+
+        let size = fs_err::metadata(&path)?.len();
+        let existing = match remote {
+            Some(remote) if remote.mime_type == FOLDER_MIME_TYPE => {
+                error!("Cannot upload {path:?} since a folder of the same name exists");
+                failed += 1;
+                continue;
+            }
+            Some(remote) => {
+                let same = remote.size == Some(size)
+                    && remote.md5_checksum.is_some()
+                    && remote.md5_checksum == Some(hash_file::<Md5>(&path)?);
+                if same {
+                    info!("Already uploaded: {path:?}");
+                    skipped += 1;
+                    continue;
+                }
+                Some((remote.id.clone(), remote.size, remote.md5_checksum.clone()))
+            }
+            None => None,
+        };
+        if audit.dry_run() {
+            let verb = if existing.is_some() {
+                "replace"
+            } else {
+                "upload"
+            };
+            outln!("Would {verb} {path:?}");
+            uploaded += 1;
+            continue;
+        }
+        info!("Uploading {path:?}");
+        let parent = parent.as_deref().unwrap_or_default();
+        let existing_id = existing.as_ref().map(|(id, _, _)| id as &str);
+        let res = tokio::select! {
+            res = upload_file(drive, retry, &path, size, &name, parent, existing_id) => res,
+            _ = cancel.cancelled() => {
+                info!("Received ctrl-c.  Terminating.");
+                break;
+            }
+        };
+        match res {
+            Ok(file) => {
+                let id = file.id.unwrap_or_default();
+                let new = json!({ "size": size, "md5": file.md5_checksum });
+                match existing {
+                    Some((_, size, md5)) => audit.record(
+                        "update_content",
+                        &id,
+                        json!({ "size": size, "md5": md5 }),
+                        new,
+                    )?,
+                    None => audit.record(
+                        "upload",
+                        &id,
+                        (),
+                        json!({ "name": name, "parents": [parent], "size": size }),
+                    )?,
+                }
+                uploaded += 1;
+            }
+            Err(e) => {
+                error!("Failed to upload {path:?}: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+    let verb = if audit.dry_run() {
+        "Would upload"
+    } else {
+        "Uploaded"
+    };
+    outln!("{verb} {uploaded}, skipped {skipped}, failed {failed} files");
+    if !audit.dry_run() && uploaded > 0 {
+        outln!("Run sync to reflect the changes in the snapshot.");
+    }
+    Ok(())
+}
+
+async fn create_folder(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    name: &str,
+    parent: &str,
+) -> anyhow::Result<String> {
+    let folder = api::File {
+        name: Some(name.to_owned()),
+        mime_type: Some(FOLDER_MIME_TYPE.into()),
+        parents: Some(vec![parent.to_owned()]),
+        ..Default::default()
+    };
+    let folder = retry.run(|| create_file(drive, &folder)).await?;
+    folder.id.context("The API did not return the id")
+}
+
+/// Creates the file in the folder, or replaces the content of `existing`, with the content type
+/// guessed from the extension.
+async fn upload_file(
+    drive: &Drive,
+    retry: &RetryPolicy,
+    path: &Path,
+    size: u64,
+    name: &str,
+    parent: &str,
+    existing: Option<&str>,
+) -> anyhow::Result<api::File> {
+    let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+    let resumable = size > RESUMABLE_THRESHOLD;
+    let (_, file) = retry
+        .without_timeout()
+        .run(|| async {
+            let reader = Throttled {
+                inner: fs_err::File::open(path).map_err(Error::Io)?,
+                budget: retry.budget,
+            };
+            let mime_type = mime_type.clone();
+            match existing {
+                Some(id) => {
+                    let call = drive
+                        .files()
+                        .update(api::File::default(), id)
+                        .supports_all_drives(true)
+                        .param("fields", "id,md5Checksum");
+                    if resumable {
+                        call.upload_resumable(reader, mime_type).await
+                    } else {
+                        call.upload(reader, mime_type).await
+                    }
+                }
+                None => {
+                    let file = api::File {
+                        name: Some(name.to_owned()),
+                        parents: Some(vec![parent.to_owned()]),
+                        ..Default::default()
+                    };
+                    let call = drive
+                        .files()
+                        .create(file)
+                        .supports_all_drives(true)
+                        .param("fields", "id,md5Checksum");
+                    if resumable {
+                        call.upload_resumable(reader, mime_type).await
+                    } else {
+                        call.upload(reader, mime_type).await
+                    }
+                }
+            }
+        })
+        .await?;
+    if file.id.is_none() {
+        bail!("The API did not return the id");
+    }
+    Ok(file)
+}
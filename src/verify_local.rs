@@ -0,0 +1,181 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::bail;
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{
+    download::{is_native, verify, walk, MANIFEST_NAME},
+    format_size, output, with_index, CancellationToken, File, Store,
+};
+
+pub struct Options {
+    /// Number of files hashed at the same time
+    pub threads: usize,
+}
+
+/// Every file under the directory, not following symbolic links
+fn local_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut stack = vec![dir.to_owned()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs_err::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                stack.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Compares the local directory with the folder in the snapshot, as a backup of it, without
+/// downloading anything.  The local files are hashed on `threads` threads and checked against
+/// the size and the strongest checksum on Drive.  Lists the files missing locally, the extra
+/// ones, and the corrupted ones.  Google-native files have no content to compare and are skipped.
+pub fn verify_local(
+    store: &Store,
+    cancel: &CancellationToken,
+    folder_id: &str,
+    local_path: &Path,
+    options: &Options,
+) -> anyhow::Result<()> {
+    if !local_path.is_dir() {
+        bail!("{local_path:?} is not a directory");
+    }
+    let files = with_index(store, |index| walk(index, folder_id, local_path))?;
+    let mut expected = HashMap::<PathBuf, File>::new();
+    let mut ambiguous = HashSet::new();
+    let mut skipped = 0;
+    for (path, file) in files {
+        if file.trashed || file.mime_type == "application/vnd.google-apps.folder" {
+            continue;
+        }
+        if is_native(&file) {
+            skipped += 1;
+            continue;
+        }
+        if expected.contains_key(&path) {
+            ambiguous.insert(path);
+            continue;
+        }
+        expected.insert(path, file);
+    }
+    for path in &ambiguous {
+        warn!("Skipping {path:?}, which several files on Drive share");
+        expected.remove(path);
+    }
+
+    let local = local_files(local_path)?;
+    let local_set: HashSet<_> = local.iter().collect();
+    let mut missing: Vec<_> = expected
+        .keys()
+        .filter(|path| !local_set.contains(path))
+        .cloned()
+        .collect();
+    let mut extra: Vec<_> = local
+        .iter()
+        .filter(|path| !expected.contains_key(*path) && !ambiguous.contains(*path))
+        .filter(|path| path.file_name().is_some_and(|name| name != MANIFEST_NAME))
+        .cloned()
+        .collect();
+    let mut targets: Vec<_> = expected
+        .iter()
+        .filter(|(path, _)| local_set.contains(path))
+        .collect();
+    targets.sort_by_key(|(x, _)| *x);
+    let total = targets.len();
+    info!("Hashing {total} files on {} threads", options.threads);
+
+    // Taken one by one by the threads, so that a few large files do not hold up the rest
+    let queue = Mutex::new(targets.into_iter());
+    let results = Mutex::new(vec![]);
+    std::thread::scope(|scope| {
+        for _ in 0..options.threads.max(1) {
+            scope.spawn(|| loop {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let Some((path, file)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = verify(path, file);
+                results.lock().unwrap().push((path, file, result));
+            });
+        }
+    });
+    if cancel.is_cancelled() {
+        bail!("Cancelled before hashing every file");
+    }
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(x, ..)| *x);
+    let mut corrupted = vec![];
+    let mut unverified = vec![];
+    let mut verified_bytes = 0;
+    for (path, file, result) in results {
+        match result {
+            Ok(Some(_)) => verified_bytes += file.size.unwrap_or(0),
+            Ok(None) => unverified.push(path.clone()),
+            Err(e) => corrupted.push((path.clone(), e.to_string())),
+        }
+    }
+    missing.sort();
+    extra.sort();
+
+    let show = |path: &Path| {
+        path.strip_prefix(local_path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    if !missing.is_empty() {
+        outln!("=== Missing locally ===");
+        for path in &missing {
+            outln!("{}", show(path));
+        }
+    }
+    if !extra.is_empty() {
+        outln!("=== Not on Drive ===");
+        for path in &extra {
+            outln!("{}", show(path));
+        }
+    }
+    if !corrupted.is_empty() {
+        outln!("=== Corrupted ===");
+        for (path, reason) in &corrupted {
+            outln!("{}  ({reason})", show(path));
+        }
+    }
+    if !unverified.is_empty() {
+        outln!("=== Only the size matched, without a checksum on Drive ===");
+        for path in &unverified {
+            outln!("{}", show(path));
+        }
+    }
+    outln!(
+        "{} of {total} files verified ({}), {} missing, {} extra, {} corrupted, \
+        {skipped} Google-native files skipped",
+        total - corrupted.len() - unverified.len(),
+        format_size(verified_bytes),
+        missing.len(),
+        extra.len(),
+        corrupted.len(),
+    );
+    output::emit(&json!({
+        "missing": missing,
+        "extra": extra,
+        "corrupted": corrupted
+            .iter()
+            .map(|(path, reason)| json!({ "path": path, "reason": reason }))
+            .collect::<Vec<_>>(),
+        "unverified": unverified,
+        "skipped_native": skipped,
+    }))
+}
@@ -0,0 +1,184 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use google_drive3::{
+    api::Channel,
+    hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    },
+};
+use log::{error, info, warn};
+use tokio::sync::Notify;
+
+use crate::{
+    metrics::{self, Metrics},
+    restore_start_page_token,
+    retry::RetryPolicy,
+    sync_files, CancellationToken, Drive, Store,
+};
+
+/// Where Drive sends the notifications of changes
+pub struct Webhook {
+    /// Local address the receiver listens on
+    pub listen: SocketAddr,
+    /// Public HTTPS URL forwarded to the receiver, as registered with Drive
+    pub url: String,
+}
+
+pub struct Options {
+    /// How often to sync without a notification.  Also catches the notifications that were lost.
+    pub interval: Duration,
+    pub webhook: Option<Webhook>,
+    /// Local address to serve the metrics for Prometheus on, at `/metrics`
+    pub metrics: Option<SocketAddr>,
+}
+
+/// Channels are renewed this long before they expire.
+const RENEW_MARGIN: Duration = Duration::from_secs(60);
+
+/// Keeps the snapshot up to date until cancelled, syncing whenever Drive notifies of a change
+/// through the webhook, or at every interval.
+pub async fn watch(
+    drive: &Drive,
+    cancel: &CancellationToken,
+    store: &Store,
+    retry: &RetryPolicy,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let notify = Arc::new(Notify::new());
+    if let Some(ref webhook) = options.webhook {
+        let server = serve(webhook.listen, notify.clone())?;
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("The webhook receiver stopped: {e}");
+            }
+        });
+        info!("Listening for notifications on {}", webhook.listen);
+    }
+    let metrics = match options.metrics {
+        Some(addr) => {
+            let metrics = Arc::new(Metrics::new(retry.budget));
+            let server = metrics::serve(addr, metrics.clone())?;
+            tokio::spawn(async move {
+                if let Err(e) = server.await {
+                    error!("The metrics server stopped: {e}");
+                }
+            });
+            info!("Serving the metrics on http://{addr}/metrics");
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    let mut channel = None::<Channel>;
+    while !cancel.is_cancelled() {
+        sync_files(drive, cancel, store, retry).await?;
+        if let Some(ref metrics) = metrics {
+            if let Err(e) = metrics.update(store) {
+                warn!("Failed to update the metrics: {e:#}");
+            }
+        }
+        if let Some(ref webhook) = options.webhook {
+            let expires_soon = channel.as_ref().is_none_or(|c| {
+                c.expiration.is_none_or(|ms| {
+                    ms - Utc::now().timestamp_millis() < RENEW_MARGIN.as_millis() as i64
+                })
+            });
+            if expires_soon {
+                if let Some(old) = channel.take() {
+                    stop_channel(drive, retry, old).await;
+                }
+                match register_channel(drive, store, retry, &webhook.url).await {
+                    Ok(new) => channel = Some(new),
+                    Err(e) => warn!("Failed to register the channel, polling instead: {e:#}"),
+                }
+            }
+        }
+        wait(cancel, &notify, options.interval).await;
+    }
+    info!("Received ctrl-c.  Terminating.");
+    if let Some(channel) = channel {
+        stop_channel(drive, retry, channel).await;
+    }
+    Ok(())
+}
+
+/// Sleeps until notified, the interval passes or cancelled, whichever comes first.
+async fn wait(cancel: &CancellationToken, notify: &Notify, interval: Duration) {
+    tokio::select! {
+        () = cancel.cancelled() => {}
+        () = notify.notified() => {
+            // Changes tend to come in bursts, which one sync can cover
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        () = tokio::time::sleep(interval) => {}
+    }
+}
+
+fn serve(
+    addr: SocketAddr,
+    notify: Arc<Notify>,
+) -> anyhow::Result<impl std::future::Future<Output = google_drive3::hyper::Result<()>>> {
+    let make_service = make_service_fn(move |_| {
+        let notify = notify.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let notify = notify.clone();
+                async move {
+                    let state = req
+                        .headers()
+                        .get("X-Goog-Resource-State")
+                        .and_then(|v| v.to_str().ok());
+                    // `sync` is sent once when the channel is created
+                    if state == Some("change") {
+                        info!("Notified of a change");
+                        notify.notify_one();
+                    }
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+    Ok(Server::try_bind(&addr)
+        .with_context(|| format!("Failed to listen on {addr}"))?
+        .serve(make_service))
+}
+
+async fn register_channel(
+    drive: &Drive,
+    store: &Store,
+    retry: &RetryPolicy,
+    url: &str,
+) -> anyhow::Result<Channel> {
+    let token = restore_start_page_token(store)?;
+    let request = Channel {
+        id: Some(format!("drive-crawl-{:016x}", rand::random::<u64>())),
+        type_: Some("web_hook".into()),
+        address: Some(url.to_owned()),
+        ..Default::default()
+    };
+    let (_, channel) = retry
+        .run(|| drive.changes().watch(request.clone(), &token).doit())
+        .await?;
+    info!(
+        "Registered the channel {}",
+        channel.id.as_deref().unwrap_or("?")
+    );
+    Ok(channel)
+}
+
+async fn stop_channel(drive: &Drive, retry: &RetryPolicy, channel: Channel) {
+    let channel = Channel {
+        id: channel.id,
+        resource_id: channel.resource_id,
+        ..Default::default()
+    };
+    if let Err(e) = retry
+        .run(|| drive.channels().stop(channel.clone()).doit())
+        .await
+    {
+        warn!("Failed to stop the channel: {e}");
+    }
+}
@@ -0,0 +1,266 @@
+use std::{cell::Cell, time::Duration};
+
+use drive_crawl::{
+    budget, crawl_snapshot,
+    drive_api::{Failure, Fixture, MockDrive},
+    load_snapshot,
+    retry::RetryPolicy,
+    CancellationToken, ListOptions,
+};
+use google_drive3::{
+    hyper::{Body, Response},
+    Error,
+};
+use tokio::time::Instant;
+
+const QUERY: &str = "'me' in owners";
+
+fn retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        budget: &budget::UNLIMITED,
+        timeout: None,
+        cancel: None,
+        token_epoch: Default::default(),
+    }
+}
+
+fn mock(failures: Vec<Failure>) -> MockDrive {
+    let mut fixture: Fixture =
+        serde_json::from_str(include_str!("fixtures/five-files.json")).unwrap();
+    fixture.failures = failures;
+    MockDrive::new(fixture)
+}
+
+fn failure(arg: &str, status: u16, reason: Option<&str>, times: u32) -> Failure {
+    Failure {
+        call: "list".into(),
+        arg: Some(arg.into()),
+        status,
+        reason: reason.map(Into::into),
+        times,
+    }
+}
+
+fn list_options() -> ListOptions {
+    ListOptions {
+        page_size: 2,
+        ..Default::default()
+    }
+}
+
+fn ids(path: &std::path::Path) -> Vec<String> {
+    let mut ids: Vec<_> = load_snapshot(path)
+        .unwrap()
+        .into_iter()
+        .map(|f| f.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn follows_the_pages() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![]);
+    let complete = crawl_snapshot(
+        &drive,
+        &CancellationToken::default(),
+        &retry(),
+        &path,
+        QUERY,
+        &list_options(),
+    )
+    .await
+    .unwrap();
+    assert!(complete);
+    assert_eq!(drive.calls(), ["list ", "list 2", "list 4"]);
+    assert_eq!(ids(&path), ["a", "b", "c", "d", "e"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_server_errors_and_rate_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![
+        failure("2", 503, None, 1),
+        failure("2", 403, Some("userRateLimitExceeded"), 1),
+    ]);
+    let complete = crawl_snapshot(
+        &drive,
+        &CancellationToken::default(),
+        &retry(),
+        &path,
+        QUERY,
+        &list_options(),
+    )
+    .await
+    .unwrap();
+    assert!(complete);
+    assert_eq!(
+        drive.calls(),
+        ["list ", "list 2", "list 2", "list 2", "list 4"]
+    );
+    assert_eq!(ids(&path), ["a", "b", "c", "d", "e"]);
+}
+
+#[tokio::test]
+async fn saves_on_errors_and_resumes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![failure("2", 403, Some("insufficientPermissions"), 1)]);
+    let cancel = CancellationToken::default();
+    let complete = crawl_snapshot(&drive, &cancel, &retry(), &path, QUERY, &list_options())
+        .await
+        .unwrap();
+    assert!(!complete);
+    assert_eq!(drive.calls(), ["list ", "list 2"]);
+    assert_eq!(ids(&path), ["a", "b"]);
+
+    let complete = crawl_snapshot(&drive, &cancel, &retry(), &path, QUERY, &list_options())
+        .await
+        .unwrap();
+    assert!(complete);
+    assert_eq!(drive.calls(), ["list ", "list 2", "list 2", "list 4"]);
+    assert_eq!(ids(&path), ["a", "b", "c", "d", "e"]);
+}
+
+#[tokio::test]
+async fn refuses_to_resume_with_another_query() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![failure("2", 404, None, 1)]);
+    let cancel = CancellationToken::default();
+    let complete = crawl_snapshot(&drive, &cancel, &retry(), &path, QUERY, &list_options())
+        .await
+        .unwrap();
+    assert!(!complete);
+    let checkpoint = dir.path().join("file-list.json.checkpoint.json");
+    let before = std::fs::read(&checkpoint).unwrap();
+    let res = crawl_snapshot(&drive, &cancel, &retry(), &path, "starred", &list_options()).await;
+    let message = res.unwrap_err().to_string();
+    assert!(
+        message.contains(&format!("was for {QUERY:?}, not \"starred\"")),
+        "{message}"
+    );
+    assert_eq!(std::fs::read(&checkpoint).unwrap(), before);
+    assert_eq!(ids(&path), ["a", "b"]);
+    assert_eq!(drive.calls(), ["list ", "list 2"]);
+}
+
+#[tokio::test]
+async fn stops_when_cancelled() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![]);
+    let cancel = CancellationToken::default();
+    cancel.cancel();
+    let complete = crawl_snapshot(&drive, &cancel, &retry(), &path, QUERY, &list_options())
+        .await
+        .unwrap();
+    assert!(!complete);
+    assert_eq!(drive.calls(), ["list "]);
+    assert_eq!(ids(&path), ["a", "b"]);
+}
+
+#[tokio::test]
+async fn retries_once_with_a_new_token_when_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file-list.json");
+    let drive = mock(vec![failure("2", 401, None, 1)]);
+    let retry = retry();
+    let complete = crawl_snapshot(
+        &drive,
+        &CancellationToken::default(),
+        &retry,
+        &path,
+        QUERY,
+        &list_options(),
+    )
+    .await
+    .unwrap();
+    assert!(complete);
+    assert_eq!(drive.calls(), ["list ", "list 2", "list 2", "list 4"]);
+    assert_eq!(ids(&path), ["a", "b", "c", "d", "e"]);
+
+    // Left for the authenticator, which there is none of here
+    assert!(retry.token_epoch.is_rejected());
+    retry.token_epoch.refreshed();
+    assert!(!retry.token_epoch.is_rejected());
+    // A call that was sent with the old token before the refresh
+    retry.token_epoch.reject(0);
+    assert!(!retry.token_epoch.is_rejected());
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_a_hung_attempt_after_the_timeout() {
+    let retry = RetryPolicy {
+        timeout: Some(Duration::from_secs(30)),
+        ..retry()
+    };
+    let attempts = Cell::new(0);
+    let start = Instant::now();
+    let res = retry
+        .run(|| {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt == 1 {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<_, Error>(attempt)
+            }
+        })
+        .await;
+    assert_eq!(res.unwrap(), 2);
+    assert!(start.elapsed() >= Duration::from_secs(30));
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelling_aborts_the_call_in_flight() {
+    let cancel = CancellationToken::default();
+    let retry = RetryPolicy {
+        cancel: Some(cancel.clone()),
+        ..retry()
+    };
+    let start = Instant::now();
+    let (res, ()) = tokio::join!(
+        retry.run(std::future::pending::<google_drive3::Result<()>>),
+        async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            cancel.cancel();
+        }
+    );
+    assert!(matches!(res, Err(Error::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(6));
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelling_aborts_the_backoff() {
+    let cancel = CancellationToken::default();
+    let retry = RetryPolicy {
+        cancel: Some(cancel.clone()),
+        ..retry()
+    };
+    let attempts = Cell::new(0);
+    let start = Instant::now();
+    let (res, ()) = tokio::join!(
+        retry.run(|| {
+            attempts.set(attempts.get() + 1);
+            async {
+                Err::<(), _>(Error::Failure(
+                    Response::builder().status(503).body(Body::empty()).unwrap(),
+                ))
+            }
+        }),
+        async {
+            // During the first backoff, which lasts at least half a second
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        }
+    );
+    assert!(matches!(res, Err(Error::Cancelled)));
+    assert_eq!(attempts.get(), 1);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}